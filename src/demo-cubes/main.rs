@@ -81,7 +81,7 @@ fn main() {
     let camera_loc = gd.new_object(None, "camera");
     gd.set_to_identity(camera_loc);
 
-    sc.start(gd, |gd, current_input, last_input| {
+    sc.start(gd, |gd, _, current_input, last_input| {
         let mut gd = gd;
         match current_input.is_focused() {
             true => {
@@ -125,5 +125,5 @@ fn main() {
         gd.update_location(camera_loc, head_trans);
 
         (gd, scene, camera_loc)
-    });
+    }).unwrap();
 }
@@ -1,5 +1,11 @@
+use std::io::IoResult;
 
-use snowmew::common::{Common, CommonData};
+use cow::btree::BTreeSet;
+
+use snowmew::common::{Common, CommonData, ObjectKey, Remap};
+use snowmew::merge::Merge;
+use snowmew::replicate::Replicate;
+use snowmew::stats::{Stats, TableStats, report};
 use position::{Positions, PositionData};
 use graphics::{Graphics, GraphicsData};
 use graphics::default::load_default;
@@ -26,9 +32,60 @@ impl GameData {
     }
 }
 
+// the console's stats, sizes of the current generation
+pub fn stats_command(gd: &mut GameData, _: &[&str]) -> Result<String, String> {
+    Ok(report(gd.stats(None).as_slice()))
+}
+
+impl Stats for GameData {
+    fn stats(&self, other: Option<&GameData>) -> Vec<TableStats> {
+        let mut out = self.common.stats(other.map(|o| &o.common));
+        out.push_all(self.position.stats(other.map(|o| &o.position)).as_slice());
+        out.push_all(self.graphics.stats(other.map(|o| &o.graphics)).as_slice());
+        out
+    }
+}
+
+impl Merge for GameData {
+    fn merge(base: &GameData, ours: &GameData, theirs: &GameData) -> Result<GameData, String> {
+        let mut out = ours.clone();
+        out.common = try!(Merge::merge(&base.common, &ours.common, &theirs.common));
+        out.graphics = try!(Merge::merge(&base.graphics, &ours.graphics, &theirs.graphics));
+        out.merge_positions(base, ours, theirs);
+        Ok(out)
+    }
+}
+
+impl Replicate for GameData {
+    fn write_delta(&self, old: &GameData, old_hidden: &BTreeSet<ObjectKey>, hidden: &BTreeSet<ObjectKey>,
+                   w: &mut Writer) -> IoResult<()> {
+        try!(self.common.write_delta(&old.common, old_hidden, hidden, w));
+        try!(self.position.write_delta(&old.position, old_hidden, hidden, w));
+        self.graphics.write_delta(&old.graphics, old_hidden, hidden, w)
+    }
+
+    fn read_delta(&self, r: &mut Reader) -> IoResult<GameData> {
+        Ok(GameData {
+            common: try!(self.common.read_delta(r)),
+            position: try!(self.position.read_delta(r)),
+            graphics: try!(self.graphics.read_delta(r))
+        })
+    }
+}
+
 impl Common for GameData {
     fn get_common<'a>(&'a self) -> &'a CommonData { &self.common }
     fn get_common_mut<'a>(&'a mut self) -> &'a mut CommonData { &mut self.common }
+
+    fn delete_data(&mut self, oid: ObjectKey) {
+        self.delete_position(oid);
+        self.delete_graphics(oid);
+    }
+
+    fn copy_data(&mut self, remap: &Remap) {
+        self.copy_positions(remap);
+        self.copy_graphics(remap);
+    }
 }
 
 impl Positions for GameData {
@@ -10,6 +10,7 @@ extern crate loader = "snowmew-loader";
 extern crate position = "snowmew-position";
 extern crate graphics = "snowmew-graphics";
 extern crate cgmath;
+extern crate cow;
 extern crate native;
 extern crate green;
 extern crate ovr = "oculus-vr";
@@ -32,8 +33,9 @@ use graphics::{Graphics};
 use graphics::light;
 
 use render::RenderFactory;
-use loader::Obj;
+use loader::Assets;
 use snowmew::common::Common;
+use snowmew::console::Console;
 
 use gamedata::GameData;
 
@@ -64,19 +66,15 @@ fn main() {
     };
 
     let mut db = GameData::new();
-    let loader = Obj::load(&path).expect("Failed to load OBJ");
+    let mut assets = Assets::new();
     let import = db.new_object(None, "import");
-    loader.import(import, &mut db);
+    assets.load_obj(&path, import, &mut db).expect("Failed to load OBJ");
     let scene = db.new_scene("scene");
     let geo_dir = db.find("import/objects").expect("geometry not found from import");
     for (name, id) in db.clone().walk_dir(geo_dir) {
-        match db.get_draw(id) {
-            Some(d) => {
-                let obj = db.new_object(Some(scene), name);
-                db.set_draw(obj, d.geometry, d.material);
-                db.set_scale(obj, scale);
-            }
-            None => ()
+        if db.get_draw(id).is_some() {
+            let obj = db.instantiate(id, Some(scene), name).expect("failed to spawn object").get(id);
+            db.set_scale(obj, scale);
         }
     }
 
@@ -102,8 +100,16 @@ fn main() {
                                       Vector3::new(1f32, 1., 1.), 1.);
     db.new_light(scene, "sun", light::Directional(sun));
 
-    sc.start(db, |gd, input_state, last_input| {
+    let mut console = Console::new();
+    position::console_commands(&mut console);
+    graphics::console_commands(&mut console);
+    console.register("stats", "stats, prints the size of every table", gamedata::stats_command);
+    console.read_stdin();
+
+    sc.start(db, |gd, _, input_state, last_input| {
         let mut gd = gd;
+        assets.reload(&mut gd);
+        console.poll(&mut gd, input_state, last_input);
         match input_state.is_focused() {
             true => {
                 match input_state.cursor_delta(last_input.time()) {
@@ -125,13 +131,17 @@ fn main() {
             false => {}
         }
 
-        let input_vec = Vector3::new(
-            if input_state.key_down(glfw::KeyA) {0.01f32} else {0f32} +
-            if input_state.key_down(glfw::KeyD) {-0.01f32} else {0f32}, 
-            0f32,
-            if input_state.key_down(glfw::KeyW) {0.01f32} else {0f32} +
-            if input_state.key_down(glfw::KeyS) {-0.01f32} else {0f32}
-        );
+        let input_vec = if console.is_open() {
+            Vector3::new(0f32, 0., 0.)
+        } else {
+            Vector3::new(
+                if input_state.key_down(glfw::KeyA) {0.01f32} else {0f32} +
+                if input_state.key_down(glfw::KeyD) {-0.01f32} else {0f32}, 
+                0f32,
+                if input_state.key_down(glfw::KeyW) {0.01f32} else {0f32} +
+                if input_state.key_down(glfw::KeyS) {-0.01f32} else {0f32}
+            )
+        };
 
         let rot: Quaternion<f32> = Rotation3::from_axis_angle(&Vector3::new(0f32, 1f32, 0f32), deg(-rot_x as f32).to_rad());
 
@@ -145,5 +155,5 @@ fn main() {
         gd.update_location(camera_loc, head_trans);
 
         (gd, scene, camera_loc)
-    });
+    }).unwrap();
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 
-use snowmew::common::{Common, CommonData};
+use snowmew::common::{Common, CommonData, ObjectKey, Remap};
+use snowmew::merge::Merge;
 use position::{Positions, PositionData};
 use graphics::{Graphics, GraphicsData};
 use graphics::default::load_default;
@@ -26,9 +27,29 @@ impl GameData {
     }
 }
 
+impl Merge for GameData {
+    fn merge(base: &GameData, ours: &GameData, theirs: &GameData) -> Result<GameData, String> {
+        let mut out = ours.clone();
+        out.common = try!(Merge::merge(&base.common, &ours.common, &theirs.common));
+        out.graphics = try!(Merge::merge(&base.graphics, &ours.graphics, &theirs.graphics));
+        out.merge_positions(base, ours, theirs);
+        Ok(out)
+    }
+}
+
 impl Common for GameData {
     fn get_common<'a>(&'a self) -> &'a CommonData { &self.common }
     fn get_common_mut<'a>(&'a mut self) -> &'a mut CommonData { &mut self.common }
+
+    fn delete_data(&mut self, oid: ObjectKey) {
+        self.delete_position(oid);
+        self.delete_graphics(oid);
+    }
+
+    fn copy_data(&mut self, remap: &Remap) {
+        self.copy_positions(remap);
+        self.copy_graphics(remap);
+    }
 }
 
 impl Positions for GameData {
@@ -79,7 +79,7 @@ fn main() {
 
     let mut gear_rot = 90f32;
 
-    sc.start(gd, |gd, _, _| {
+    sc.start(gd, |gd, _, _, _| {
         let mut gd = gd;
 
         for (idx, &(logo, _)) in scene_logos.iter().enumerate() {
@@ -91,5 +91,5 @@ fn main() {
         gear_rot += 0.5;
 
         (gd, scene, camera_loc)
-    });
+    }).unwrap();
 }
\ No newline at end of file
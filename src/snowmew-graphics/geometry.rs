@@ -1,10 +1,12 @@
 
 use std::default::Default;
+use std::io::IoResult;
 use cgmath::vector::{Vector2, Vector3};
 
 use snowmew::common::ObjectKey;
+use snowmew::snapshot::{Snapshot, invalid};
 
-#[deriving(Clone)]
+#[deriving(Clone, PartialEq)]
 pub enum Primative {
     Point,
     Line,
@@ -12,31 +14,31 @@ pub enum Primative {
     TriangleAdjacency
 }
 
-#[deriving(Clone)]
+#[deriving(Clone, PartialEq)]
 pub struct VertexGeo {
     pub position: Vector3<f32>
 }
 
-#[deriving(Clone)]
+#[deriving(Clone, PartialEq)]
 pub struct VertexGeoNorm {
     pub position: Vector3<f32>,
     pub normal: Vector3<f32>
 }
 
-#[deriving(Clone)]
+#[deriving(Clone, PartialEq)]
 pub struct VertexGeoTex {
     pub position: Vector3<f32>,
     pub texture: Vector2<f32>
 }
 
-#[deriving(Clone)]
+#[deriving(Clone, PartialEq)]
 pub struct VertexGeoTexNorm {
     pub position: Vector3<f32>,
     pub texture: Vector2<f32>,
     pub normal: Vector3<f32>
 }
 
-#[deriving(Clone)]
+#[deriving(Clone, PartialEq)]
 pub struct VertexGeoTexNormTan {
     pub position: Vector3<f32>,
     pub texture: Vector2<f32>,
@@ -44,7 +46,7 @@ pub struct VertexGeoTexNormTan {
     pub tangent: Vector3<f32>,
 }
 
-#[deriving(Clone)]
+#[deriving(Clone, PartialEq)]
 pub enum Vertex {
     Geo(Vec<VertexGeo>),
     GeoTex(Vec<VertexGeoTex>),
@@ -59,14 +61,14 @@ impl Default for Vertex {
     }
 }
 
-#[deriving(Clone, Default)]
+#[deriving(Clone, Default, PartialEq)]
 pub struct VertexBuffer {
     pub vertex: Vertex,
     pub index: Vec<u32>
 }
 
 
-#[deriving(Clone, Default)]
+#[deriving(Clone, Default, PartialEq)]
 pub struct Geometry {
     pub vb: ObjectKey,
     pub count: uint, // number of index elements
@@ -162,6 +164,173 @@ impl Geometry {
     }
 }
 
+impl Snapshot for Primative {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        w.write_u8(match *self {
+            Point => 0,
+            Line => 1,
+            Triangle => 2,
+            TriangleAdjacency => 3
+        })
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Primative> {
+        match try!(r.read_u8()) {
+            0 => Ok(Point),
+            1 => Ok(Line),
+            2 => Ok(Triangle),
+            3 => Ok(TriangleAdjacency),
+            _ => Err(invalid("invalid primative"))
+        }
+    }
+}
+
+impl Snapshot for Geometry {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.vb.save(w));
+        try!(self.count.save(w));
+        try!(self.offset.save(w));
+        self.prim.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Geometry> {
+        let vb = try!(Snapshot::load(r));
+        let count = try!(Snapshot::load(r));
+        let offset = try!(Snapshot::load(r));
+        let prim = try!(Snapshot::load(r));
+        Ok(Geometry {
+            vb: vb,
+            count: count,
+            offset: offset,
+            prim: prim
+        })
+    }
+}
+
+impl Snapshot for VertexGeo {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        self.position.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<VertexGeo> {
+        Ok(VertexGeo {
+            position: try!(Snapshot::load(r))
+        })
+    }
+}
+
+impl Snapshot for VertexGeoNorm {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.position.save(w));
+        self.normal.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<VertexGeoNorm> {
+        let position = try!(Snapshot::load(r));
+        let normal = try!(Snapshot::load(r));
+        Ok(VertexGeoNorm {
+            position: position,
+            normal: normal
+        })
+    }
+}
+
+impl Snapshot for VertexGeoTex {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.position.save(w));
+        self.texture.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<VertexGeoTex> {
+        let position = try!(Snapshot::load(r));
+        let texture = try!(Snapshot::load(r));
+        Ok(VertexGeoTex {
+            position: position,
+            texture: texture
+        })
+    }
+}
+
+impl Snapshot for VertexGeoTexNorm {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.position.save(w));
+        try!(self.texture.save(w));
+        self.normal.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<VertexGeoTexNorm> {
+        let position = try!(Snapshot::load(r));
+        let texture = try!(Snapshot::load(r));
+        let normal = try!(Snapshot::load(r));
+        Ok(VertexGeoTexNorm {
+            position: position,
+            texture: texture,
+            normal: normal
+        })
+    }
+}
+
+impl Snapshot for VertexGeoTexNormTan {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.position.save(w));
+        try!(self.texture.save(w));
+        try!(self.normal.save(w));
+        self.tangent.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<VertexGeoTexNormTan> {
+        let position = try!(Snapshot::load(r));
+        let texture = try!(Snapshot::load(r));
+        let normal = try!(Snapshot::load(r));
+        let tangent = try!(Snapshot::load(r));
+        Ok(VertexGeoTexNormTan {
+            position: position,
+            texture: texture,
+            normal: normal,
+            tangent: tangent
+        })
+    }
+}
+
+impl Snapshot for Vertex {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        match *self {
+            Geo(ref v) => { try!(w.write_u8(0)); v.save(w) }
+            GeoTex(ref v) => { try!(w.write_u8(1)); v.save(w) }
+            GeoNorm(ref v) => { try!(w.write_u8(2)); v.save(w) }
+            GeoTexNorm(ref v) => { try!(w.write_u8(3)); v.save(w) }
+            GeoTexNormTan(ref v) => { try!(w.write_u8(4)); v.save(w) }
+        }
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Vertex> {
+        match try!(r.read_u8()) {
+            0 => Ok(Geo(try!(Snapshot::load(r)))),
+            1 => Ok(GeoTex(try!(Snapshot::load(r)))),
+            2 => Ok(GeoNorm(try!(Snapshot::load(r)))),
+            3 => Ok(GeoTexNorm(try!(Snapshot::load(r)))),
+            4 => Ok(GeoTexNormTan(try!(Snapshot::load(r)))),
+            _ => Err(invalid("invalid vertex type"))
+        }
+    }
+}
+
+impl Snapshot for VertexBuffer {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.vertex.save(w));
+        self.index.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<VertexBuffer> {
+        let vertex = try!(Snapshot::load(r));
+        let index = try!(Snapshot::load(r));
+        Ok(VertexBuffer {
+            vertex: vertex,
+            index: index
+        })
+    }
+}
+
 impl VertexBuffer {
     pub fn new_position(vert: Vec<VertexGeo>, idx: Vec<u32>) -> VertexBuffer {
         VertexBuffer {
@@ -10,13 +10,22 @@ extern crate collision;
 extern crate image = "stb_image";
 
 use std::slice;
+use std::mem;
+use std::iter::AdditiveIterator;
+use std::io::IoResult;
 
 use cgmath::vector::{Vector3, Vector2};
 use cgmath::point::Point3;
 use collision::sphere::Sphere;
 
-use cow::btree::{BTreeMapIterator, BTreeMap};
-use snowmew::common::{Common, ObjectKey};
+use cow::btree::{BTreeMapIterator, BTreeMap, BTreeSet};
+use snowmew::common::{Common, ObjectKey, Tags, ALL_TAGS, Remap};
+use snowmew::snapshot::Snapshot;
+use snowmew::replicate::{Replicate, write_map_delta, write_map_delta_hidden, read_map_delta};
+use snowmew::diff::{Diff, diff, diff_by};
+use snowmew::merge::{Merge, Policy, LastWriterWins, merge_map, pick};
+use snowmew::console::Console;
+use snowmew::stats::{Stats, TableStats, table_stats, table_stats_by, vec_stats};
 
 pub use geometry::{Geometry, VertexBuffer};
 pub use material::Material;
@@ -47,6 +56,22 @@ impl Ord for Drawable {
     }
 }
 
+impl Snapshot for Drawable {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.geometry.save(w));
+        self.material.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Drawable> {
+        let geometry = try!(Snapshot::load(r));
+        let material = try!(Snapshot::load(r));
+        Ok(Drawable {
+            geometry: geometry,
+            material: material
+        })
+    }
+}
+
 #[deriving(Clone)]
 pub struct GraphicsData {
     draw:               BTreeMap<ObjectKey, Drawable>,
@@ -56,10 +81,34 @@ pub struct GraphicsData {
     material:           BTreeMap<ObjectKey, Material>,
     material_index:     BTreeMap<ObjectKey, i32>,
     material_idx_last:  i32,
+    material_idx_free:  Vec<i32>,
     texture:            BTreeMap<ObjectKey, Texture>,
     texture_to_atlas:   BTreeMap<ObjectKey, (uint, uint)>,
     atlases:            Vec<texture_atlas::Atlas>,
-    lights:             BTreeMap<ObjectKey, light::Light>
+    lights:             BTreeMap<ObjectKey, light::Light>,
+    camera_layers:      BTreeMap<ObjectKey, Tags>,
+    policy:             GraphicsPolicy
+}
+
+/// How the tables that are merged per object resolve a key that was
+/// changed by both sides, `new` uses LastWriterWins for all of them.
+#[deriving(Clone)]
+pub struct GraphicsPolicy {
+    pub draw: Policy<Drawable>,
+    pub material: Policy<Material>,
+    pub lights: Policy<light::Light>,
+    pub camera_layers: Policy<Tags>
+}
+
+impl GraphicsPolicy {
+    pub fn new() -> GraphicsPolicy {
+        GraphicsPolicy {
+            draw: LastWriterWins,
+            material: LastWriterWins,
+            lights: LastWriterWins,
+            camera_layers: LastWriterWins
+        }
+    }
 }
 
 impl GraphicsData {
@@ -75,15 +124,286 @@ impl GraphicsData {
             atlases: Vec::new(),
             texture_to_atlas: BTreeMap::new(),
             material_idx_last: 0,
-            sphere: BTreeMap::new()
+            material_idx_free: Vec::new(),
+            sphere: BTreeMap::new(),
+            camera_layers: BTreeMap::new(),
+            policy: GraphicsPolicy::new()
         }
     }
+
+    fn insert_material(&mut self, oid: ObjectKey, material: Material) {
+        self.material.insert(oid, material);
+        let idx = match self.material_idx_free.pop() {
+            Some(idx) => idx,
+            None => {
+                self.material_idx_last += 1;
+                self.material_idx_last - 1
+            }
+        };
+        self.material_index.insert(oid, idx);
+    }
+
+    pub fn diff_drawables<'a>(&'a self, old: &'a GraphicsData) -> Diff<'a, ObjectKey, Drawable> {
+        diff(&old.draw, &self.draw)
+    }
+
+    pub fn diff_materials<'a>(&'a self, old: &'a GraphicsData) -> Diff<'a, ObjectKey, Material> {
+        diff(&old.material, &self.material)
+    }
+
+    pub fn diff_lights<'a>(&'a self, old: &'a GraphicsData) -> Diff<'a, ObjectKey, light::Light> {
+        diff(&old.lights, &self.lights)
+    }
+
+    pub fn diff_geometry<'a>(&'a self, old: &'a GraphicsData) -> Diff<'a, ObjectKey, Geometry> {
+        diff(&old.geometry, &self.geometry)
+    }
+
+    pub fn diff_vertex_buffers<'a>(&'a self, old: &'a GraphicsData) -> Diff<'a, ObjectKey, VertexBuffer> {
+        diff(&old.vertex, &self.vertex)
+    }
+
+    /// the atlas slots that hold a different texture than in old, both
+    /// the slot a changed texture left and the one it is in now
+    pub fn changed_texture_slots(&self, old: &GraphicsData) -> Vec<(uint, uint)> {
+        let mut slots = Vec::new();
+        let changes = diff(&old.texture, &self.texture)
+                .chain(diff(&old.texture_to_atlas, &self.texture_to_atlas));
+        for change in changes {
+            let oid = change.key();
+            for slot in old.texture_to_atlas.find(&oid).iter().chain(self.texture_to_atlas.find(&oid).iter()) {
+                if !slots.contains(*slot) {
+                    slots.push(**slot);
+                }
+            }
+        }
+        slots
+    }
+
+    fn spheres_changed(&self, base: &GraphicsData) -> bool {
+        diff_by(&base.sphere, &self.sphere, |a, b| a.center == b.center && a.radius == b.radius).next().is_some()
+    }
+
+    // anything that is uploaded to the gpu or allocates a material index
+    fn assets_changed(&self, base: &GraphicsData) -> bool {
+        diff(&base.geometry, &self.geometry).next().is_some() ||
+        self.spheres_changed(base) ||
+        diff(&base.vertex, &self.vertex).next().is_some() ||
+        diff(&base.texture, &self.texture).next().is_some() ||
+        diff(&base.material_index, &self.material_index).next().is_some()
+    }
+}
+
+/// Only one side may add or remove assets. Drawables, materials,
+/// lights and camera layers are merged per object, a conflict is
+/// resolved by the policy of ours.
+impl Merge for GraphicsData {
+    fn merge(base: &GraphicsData, ours: &GraphicsData, theirs: &GraphicsData) -> Result<GraphicsData, String> {
+        let assets = try!(pick(base, ours, theirs, |b, x| x.assets_changed(b), "graphics assets"));
+        let policy = &ours.policy;
+        let mut out = assets.clone();
+        out.draw = merge_map(&base.draw, &ours.draw, &theirs.draw, &policy.draw);
+        out.material = merge_map(&base.material, &ours.material, &theirs.material, &policy.material);
+        out.lights = merge_map(&base.lights, &ours.lights, &theirs.lights, &policy.lights);
+        out.camera_layers = merge_map(&base.camera_layers, &ours.camera_layers,
+                                      &theirs.camera_layers, &policy.camera_layers);
+        out.policy = policy.clone();
+        Ok(out)
+    }
+}
+
+// collision does not know about snapshots, the spheres are
+// written out by hand
+fn save_spheres(w: &mut Writer, spheres: &BTreeMap<ObjectKey, Sphere<f32>>) -> IoResult<()> {
+    try!(w.write_le_u32(spheres.len() as u32));
+    for (key, sphere) in spheres.iter() {
+        try!(key.save(w));
+        try!(sphere.center.save(w));
+        try!(sphere.radius.save(w));
+    }
+    Ok(())
+}
+
+fn load_spheres(r: &mut Reader) -> IoResult<BTreeMap<ObjectKey, Sphere<f32>>> {
+    let len = try!(r.read_le_u32());
+    let mut spheres = BTreeMap::new();
+    for _ in range(0, len) {
+        let key: ObjectKey = try!(Snapshot::load(r));
+        let center: Point3<f32> = try!(Snapshot::load(r));
+        let radius: f32 = try!(Snapshot::load(r));
+        spheres.insert(key, Sphere::new(center, radius));
+    }
+    Ok(spheres)
+}
+
+impl Snapshot for GraphicsData {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.draw.save(w));
+        try!(self.geometry.save(w));
+        try!(save_spheres(w, &self.sphere));
+        try!(self.vertex.save(w));
+        try!(self.material.save(w));
+        try!(self.material_index.save(w));
+        try!(self.material_idx_last.save(w));
+        try!(self.material_idx_free.save(w));
+        try!(self.texture.save(w));
+        try!(self.texture_to_atlas.save(w));
+        try!(self.atlases.save(w));
+        try!(self.lights.save(w));
+        self.camera_layers.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<GraphicsData> {
+        let draw = try!(Snapshot::load(r));
+        let geometry = try!(Snapshot::load(r));
+        let sphere = try!(load_spheres(r));
+        let vertex = try!(Snapshot::load(r));
+        let material = try!(Snapshot::load(r));
+        let material_index = try!(Snapshot::load(r));
+        let material_idx_last = try!(Snapshot::load(r));
+        let material_idx_free = try!(Snapshot::load(r));
+        let texture = try!(Snapshot::load(r));
+        let texture_to_atlas = try!(Snapshot::load(r));
+        let atlases = try!(Snapshot::load(r));
+        let lights = try!(Snapshot::load(r));
+        let camera_layers = try!(Snapshot::load(r));
+        Ok(GraphicsData {
+            draw: draw,
+            geometry: geometry,
+            sphere: sphere,
+            vertex: vertex,
+            material: material,
+            material_index: material_index,
+            material_idx_last: material_idx_last,
+            material_idx_free: material_idx_free,
+            texture: texture,
+            texture_to_atlas: texture_to_atlas,
+            atlases: atlases,
+            lights: lights,
+            camera_layers: camera_layers,
+            // merge policies belong to the running game
+            policy: GraphicsPolicy::new()
+        })
+    }
+}
+
+/// Assets are always sent, drawables, lights and cameras of hidden
+/// objects are left out. Spheres and atlases are sent whole when
+/// they changed.
+impl Replicate for GraphicsData {
+    fn write_delta(&self, old: &GraphicsData, old_hidden: &BTreeSet<ObjectKey>, hidden: &BTreeSet<ObjectKey>,
+                   w: &mut Writer) -> IoResult<()> {
+        try!(write_map_delta_hidden(&old.draw, &self.draw, |a, b| a == b, old_hidden, hidden, w));
+        try!(write_map_delta(&old.geometry, &self.geometry, w));
+
+        let spheres = self.spheres_changed(old);
+        try!(spheres.save(w));
+        if spheres {
+            try!(save_spheres(w, &self.sphere));
+        }
+
+        try!(write_map_delta(&old.vertex, &self.vertex, w));
+        try!(write_map_delta(&old.material, &self.material, w));
+        try!(write_map_delta(&old.material_index, &self.material_index, w));
+        try!(self.material_idx_last.save(w));
+        try!(self.material_idx_free.save(w));
+        try!(write_map_delta(&old.texture, &self.texture, w));
+        try!(write_map_delta(&old.texture_to_atlas, &self.texture_to_atlas, w));
+
+        let atlases = old.atlases.len() != self.atlases.len() ||
+                      diff(&old.texture_to_atlas, &self.texture_to_atlas).next().is_some();
+        try!(atlases.save(w));
+        if atlases {
+            try!(self.atlases.save(w));
+        }
+
+        try!(write_map_delta_hidden(&old.lights, &self.lights, |a, b| a == b, old_hidden, hidden, w));
+        write_map_delta_hidden(&old.camera_layers, &self.camera_layers, |a, b| a == b, old_hidden, hidden, w)
+    }
+
+    fn read_delta(&self, r: &mut Reader) -> IoResult<GraphicsData> {
+        let draw = try!(read_map_delta(&self.draw, r));
+        let geometry = try!(read_map_delta(&self.geometry, r));
+        let sphere = if try!(Snapshot::load(r)) {
+            try!(load_spheres(r))
+        } else {
+            self.sphere.clone()
+        };
+        let vertex = try!(read_map_delta(&self.vertex, r));
+        let material = try!(read_map_delta(&self.material, r));
+        let material_index = try!(read_map_delta(&self.material_index, r));
+        let material_idx_last = try!(Snapshot::load(r));
+        let material_idx_free = try!(Snapshot::load(r));
+        let texture = try!(read_map_delta(&self.texture, r));
+        let texture_to_atlas = try!(read_map_delta(&self.texture_to_atlas, r));
+        let atlases = if try!(Snapshot::load(r)) {
+            try!(Snapshot::load(r))
+        } else {
+            self.atlases.clone()
+        };
+        let lights = try!(read_map_delta(&self.lights, r));
+        let camera_layers = try!(read_map_delta(&self.camera_layers, r));
+        Ok(GraphicsData {
+            draw: draw,
+            geometry: geometry,
+            sphere: sphere,
+            vertex: vertex,
+            material: material,
+            material_index: material_index,
+            material_idx_last: material_idx_last,
+            material_idx_free: material_idx_free,
+            texture: texture,
+            texture_to_atlas: texture_to_atlas,
+            atlases: atlases,
+            lights: lights,
+            camera_layers: camera_layers,
+            policy: self.policy.clone()
+        })
+    }
+}
+
+fn vertex_bytes(vb: &VertexBuffer) -> uint {
+    let vertex = match vb.vertex {
+        geometry::Geo(ref v) => v.len() * mem::size_of::<geometry::VertexGeo>(),
+        geometry::GeoTex(ref v) => v.len() * mem::size_of::<geometry::VertexGeoTex>(),
+        geometry::GeoNorm(ref v) => v.len() * mem::size_of::<geometry::VertexGeoNorm>(),
+        geometry::GeoTexNorm(ref v) => v.len() * mem::size_of::<geometry::VertexGeoTexNorm>(),
+        geometry::GeoTexNormTan(ref v) => v.len() * mem::size_of::<geometry::VertexGeoTexNormTan>()
+    };
+    vertex + vb.index.len() * mem::size_of::<u32>()
+}
+
+/// The atlases are counted by the layers in use, their bytes are what
+/// the renderer allocates for all of their layers.
+impl Stats for GraphicsData {
+    fn stats(&self, other: Option<&GraphicsData>) -> Vec<TableStats> {
+        vec!(table_stats("draw", &self.draw, other.map(|o| &o.draw)),
+             table_stats("geometry", &self.geometry, other.map(|o| &o.geometry)),
+             table_stats("sphere", &self.sphere, other.map(|o| &o.sphere)),
+             table_stats_by("vertex", &self.vertex, other.map(|o| &o.vertex), |_, vb| vertex_bytes(vb)),
+             table_stats("material", &self.material, other.map(|o| &o.material)),
+             table_stats("material_index", &self.material_index, other.map(|o| &o.material_index)),
+             table_stats_by("texture", &self.texture, other.map(|o| &o.texture),
+                            |_, t| t.width() * t.height() * t.depth()),
+             table_stats("texture_to_atlas", &self.texture_to_atlas, other.map(|o| &o.texture_to_atlas)),
+             TableStats {
+                 entries: self.atlases.iter().map(|a| a.used()).sum(),
+                 .. vec_stats("atlases", &self.atlases, |a| a.bytes())
+             },
+             table_stats("lights", &self.lights, other.map(|o| &o.lights)),
+             table_stats("camera_layers", &self.camera_layers, other.map(|o| &o.camera_layers)))
+    }
 }
 
 pub trait Graphics: Common {
     fn get_graphics<'a>(&'a self) -> &'a GraphicsData;
     fn get_graphics_mut<'a>(&'a mut self) -> &'a mut GraphicsData;
 
+    /// Sets how the graphics tables resolve a merge conflict
+    fn set_graphics_merge_policy(&mut self, policy: GraphicsPolicy) {
+        self.get_graphics_mut().policy = policy;
+    }
+
     fn drawable<'a>(&'a self, key: ObjectKey) -> Option<&'a Drawable> {
         self.get_graphics().draw.find(&key)
     }
@@ -94,18 +414,30 @@ pub trait Graphics: Common {
         oid
     }
 
+    /// replaces the vertex buffer of oid, the renderer uploads it again
+    fn set_vertex_buffer(&mut self, oid: ObjectKey, vb: VertexBuffer) {
+        self.get_graphics_mut().vertex.insert(oid, vb);
+    }
+
+    fn vertex_buffer<'a>(&'a self, oid: ObjectKey) -> Option<&'a VertexBuffer> {
+        self.get_graphics().vertex.find(&oid)
+    }
+
     fn geometry<'a>(&'a self, oid: ObjectKey) -> Option<&'a Geometry> {
         self.get_graphics().geometry.find(&oid)
     }
 
     fn new_geometry(&mut self, parent: ObjectKey, name: &str, geo: Geometry) -> ObjectKey {
         let oid = self.new_object(Some(parent), name);
+        self.set_geometry(oid, geo);
+        oid
+    }
+
+    fn set_geometry(&mut self, oid: ObjectKey, geo: Geometry) {
         self.get_graphics_mut().geometry.insert(oid, geo);
         let sphere = self.geometry_to_collider(oid)
             .expect("Could not create sphere collider");
-        println!("sphere: {}", sphere);
         self.get_graphics_mut().sphere.insert(oid, sphere);
-        oid
     }
 
     fn sphere(&self, geo: ObjectKey) -> Sphere<f32> {
@@ -128,13 +460,19 @@ pub trait Graphics: Common {
 
     fn new_material(&mut self, parent: ObjectKey, name: &str, material: Material) -> ObjectKey {
         let obj = self.new_object(Some(parent), name);
-        self.get_graphics_mut().material.insert(obj, material);
-        let idx = self.get_graphics().material_idx_last;
-        self.get_graphics_mut().material_idx_last += 1;
-        self.get_graphics_mut().material_index.insert(obj, idx);
+        self.get_graphics_mut().insert_material(obj, material);
         obj
     }
 
+    /// replaces the material of oid, it keeps its material index
+    fn set_material(&mut self, oid: ObjectKey, material: Material) {
+        if self.get_graphics().material_index.find(&oid).is_some() {
+            self.get_graphics_mut().material.insert(oid, material);
+        } else {
+            self.get_graphics_mut().insert_material(oid, material);
+        }
+    }
+
     fn material_iter<'a>(&'a self) -> BTreeMapIterator<'a, ObjectKey, Material> {
         self.get_graphics().material.iter()
     }
@@ -163,6 +501,10 @@ pub trait Graphics: Common {
         self.get_graphics().draw.iter()
     }
 
+    fn drawable_table<'a>(&'a self) -> &'a BTreeMap<ObjectKey, Drawable> {
+        &self.get_graphics().draw
+    }
+
     fn vertex_buffer_iter<'a>(&'a self) -> BTreeMapIterator<'a, ObjectKey, VertexBuffer> {
         self.get_graphics().vertex.iter()
     }
@@ -197,6 +539,25 @@ pub trait Graphics: Common {
 
     fn new_texture(&mut self, parent: ObjectKey, name: &str, texture: Texture) -> ObjectKey {
         let oid = self.new_object(Some(parent), name);
+        self.set_texture(oid, texture);
+        oid
+    }
+
+    /// Replaces the texture of oid. It keeps its place in the atlas if
+    /// the size and depth did not change.
+    fn set_texture(&mut self, oid: ObjectKey, texture: Texture) {
+        let old = self.get_graphics().texture_to_atlas.find(&oid).map(|x| *x);
+        match old {
+            Some((atlas, _)) if self.get_graphics().atlases.get(atlas).check_texture(&texture) => {
+                self.get_graphics_mut().texture.insert(oid, texture);
+                return;
+            }
+            Some((atlas, _)) => {
+                self.get_graphics_mut().atlases.get_mut(atlas).remove_texture(oid);
+            }
+            None => ()
+        }
+
         let mut found = None;
         for (idx, atlas) in self.get_graphics_mut().atlases.mut_iter().enumerate() {
             if atlas.check_texture(&texture) {
@@ -214,7 +575,6 @@ pub trait Graphics: Common {
 
         self.get_graphics_mut().texture.insert(oid, texture);
         self.get_graphics_mut().texture_to_atlas.insert(oid, found.unwrap());
-        oid
     }
 
     fn get_texture<'a>(&'a self, oid: ObjectKey) -> Option<&'a Texture> {
@@ -235,10 +595,15 @@ pub trait Graphics: Common {
 
     fn new_light(&mut self, parent: ObjectKey, name: &str, light: Light) -> ObjectKey {
         let oid = self.new_object(Some(parent), name);
-        self.get_graphics_mut().lights.insert(oid, light);
+        self.set_light(oid, light);
         oid
     }
 
+    /// makes oid a light, replacing the light it was
+    fn set_light(&mut self, oid: ObjectKey, light: Light) {
+        self.get_graphics_mut().lights.insert(oid, light);
+    }
+
     fn get_light<'a>(&'a self, oid: ObjectKey) -> Option<&'a Light> {
         self.get_graphics().lights.find(&oid)
     }
@@ -246,6 +611,94 @@ pub trait Graphics: Common {
     fn light_iter<'a>(&'a self) -> BTreeMapIterator<'a, ObjectKey, Light> {
         self.get_graphics().lights.iter()
     }
+
+    /// the tags a camera will draw, every object is drawn by default
+    fn set_camera_layers(&mut self, camera: ObjectKey, layers: Tags) {
+        self.get_graphics_mut().camera_layers.insert(camera, layers);
+    }
+
+    fn camera_layers(&self, camera: ObjectKey) -> Tags {
+        match self.get_graphics().camera_layers.find(&camera) {
+            Some(layers) => *layers,
+            None => ALL_TAGS
+        }
+    }
+
+    /// Copies the graphics data of an instantiated template. Materials,
+    /// lights and drawables are copied and references to materials
+    /// inside the template are remapped. Geometry, vertex buffers and
+    /// textures are shared with the template.
+    fn copy_graphics(&mut self, remap: &Remap) {
+        for (old, new) in remap.iter() {
+            let material = self.material(*old).map(|m| m.clone());
+            match material {
+                Some(m) => self.get_graphics_mut().insert_material(*new, m),
+                None => ()
+            }
+
+            let light = self.get_light(*old).map(|l| l.clone());
+            match light {
+                Some(l) => { self.get_graphics_mut().lights.insert(*new, l); }
+                None => ()
+            }
+
+            let layers = self.get_graphics().camera_layers.find(old).map(|l| *l);
+            match layers {
+                Some(l) => { self.get_graphics_mut().camera_layers.insert(*new, l); }
+                None => ()
+            }
+        }
+
+        // materials have to exist before the drawables can point at them
+        for (old, new) in remap.iter() {
+            match self.get_draw(*old) {
+                Some(d) => self.set_draw(*new, d.geometry, remap.get(d.material)),
+                None => ()
+            }
+        }
+    }
+
+    fn delete_graphics(&mut self, oid: ObjectKey) {
+        let graphics = self.get_graphics_mut();
+        graphics.draw.remove(&oid);
+        graphics.geometry.remove(&oid);
+        graphics.sphere.remove(&oid);
+        graphics.vertex.remove(&oid);
+        graphics.material.remove(&oid);
+        match graphics.material_index.pop(&oid) {
+            Some(idx) => graphics.material_idx_free.push(idx),
+            None => ()
+        }
+        graphics.lights.remove(&oid);
+        graphics.camera_layers.remove(&oid);
+        graphics.texture.remove(&oid);
+        match graphics.texture_to_atlas.pop(&oid) {
+            Some((atlas, _)) => {
+                graphics.atlases.get_mut(atlas).remove_texture(oid);
+            }
+            None => ()
+        }
+    }
+}
+
+fn inspect_graphics<GD: Graphics>(gd: &GD, key: ObjectKey) -> Option<String> {
+    let mut out = Vec::new();
+    match gd.drawable(key) {
+        Some(d) => out.push(format!("drawable geometry {} material {}",
+                                    gd.name(d.geometry), gd.name(d.material))),
+        None => ()
+    }
+    match (gd.material(key), gd.material_index(key)) {
+        (Some(m), Some(idx)) => out.push(format!("material index {} ka {} kd {} ks {}",
+                                                 idx, m.ka(), m.kd(), m.ks())),
+        _ => ()
+    }
+    if out.len() == 0 { None } else { Some(out.connect("\n")) }
+}
+
+/// adds the drawable and material to `inspect`
+pub fn console_commands<GD: Graphics>(console: &mut Console<GD>) {
+    console.register_inspector(inspect_graphics::<GD>);
 }
 
 pub struct VertexBufferIter<'a> {
@@ -283,4 +736,4 @@ impl<'a> Iterator<(u32, &'a Vector3<f32>, Option<&'a Vector2<f32>>, Option<&'a V
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -1,9 +1,12 @@
 
 use std::default::Default;
+use std::io::IoResult;
 use cgmath::vector::Vector3;
 
+use snowmew::snapshot::{Snapshot, invalid};
 
-#[deriving(Clone)]
+
+#[deriving(Clone, PartialEq)]
 pub struct Point {
     color: Vector3<f32>,
     intensity: f32
@@ -24,7 +27,7 @@ impl Point {
     pub fn intensity(&self) -> f32 {self.intensity.clone()}
 }
 
-#[deriving(Clone)]
+#[deriving(Clone, PartialEq)]
 pub struct Directional {
     normal: Vector3<f32>,
     color: Vector3<f32>,
@@ -48,7 +51,7 @@ impl Directional {
     pub fn intensity(&self) -> f32 {self.intensity.clone()}
 }
 
-#[deriving(Clone)]
+#[deriving(Clone, PartialEq)]
 pub enum Light {
     Directional(Directional),
     Point(Point)
@@ -61,4 +64,39 @@ impl Default for Light {
             intensity: 0.
         })
     }
-}
\ No newline at end of file
+}
+
+impl Snapshot for Light {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        match *self {
+            Directional(ref d) => {
+                try!(w.write_u8(0));
+                try!(d.normal.save(w));
+                try!(d.color.save(w));
+                d.intensity.save(w)
+            }
+            Point(ref p) => {
+                try!(w.write_u8(1));
+                try!(p.color.save(w));
+                p.intensity.save(w)
+            }
+        }
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Light> {
+        match try!(r.read_u8()) {
+            0 => {
+                let normal = try!(Snapshot::load(r));
+                let color = try!(Snapshot::load(r));
+                let intensity = try!(Snapshot::load(r));
+                Ok(Directional(Directional::new(normal, color, intensity)))
+            }
+            1 => {
+                let color = try!(Snapshot::load(r));
+                let intensity = try!(Snapshot::load(r));
+                Ok(Point(Point::new(color, intensity)))
+            }
+            _ => Err(invalid("invalid light type"))
+        }
+    }
+}
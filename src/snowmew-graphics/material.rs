@@ -1,10 +1,12 @@
 use std::default::Default;
+use std::io::IoResult;
 
 use cgmath::vector::Vector3;
 
 use snowmew::ObjectKey;
+use snowmew::snapshot::Snapshot;
 
-#[deriving(Clone)]
+#[deriving(Clone, PartialEq)]
 pub struct Material {
     ka: Vector3<f32>,
     kd: Vector3<f32>,
@@ -33,6 +35,52 @@ impl Default for Material {
     }
 }
 
+impl Snapshot for Material {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.ka.save(w));
+        try!(self.kd.save(w));
+        try!(self.ks.save(w));
+        try!(self.ke.save(w));
+        try!(self.tf.save(w));
+        try!(self.ns.save(w));
+        try!(self.ni.save(w));
+        try!(self.tr.save(w));
+        try!(self.d.save(w));
+        try!(self.illum.save(w));
+        try!(self.map_ka.save(w));
+        try!(self.map_kd.save(w));
+        try!(self.map_ks.save(w));
+        try!(self.map_ke.save(w));
+        try!(self.map_ns.save(w));
+        try!(self.map_d.save(w));
+        try!(self.map_bump.save(w));
+        self.map_refl.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Material> {
+        let mut mat = Material::new();
+        mat.ka = try!(Snapshot::load(r));
+        mat.kd = try!(Snapshot::load(r));
+        mat.ks = try!(Snapshot::load(r));
+        mat.ke = try!(Snapshot::load(r));
+        mat.tf = try!(Snapshot::load(r));
+        mat.ns = try!(Snapshot::load(r));
+        mat.ni = try!(Snapshot::load(r));
+        mat.tr = try!(Snapshot::load(r));
+        mat.d = try!(Snapshot::load(r));
+        mat.illum = try!(Snapshot::load(r));
+        mat.map_ka = try!(Snapshot::load(r));
+        mat.map_kd = try!(Snapshot::load(r));
+        mat.map_ks = try!(Snapshot::load(r));
+        mat.map_ke = try!(Snapshot::load(r));
+        mat.map_ns = try!(Snapshot::load(r));
+        mat.map_d = try!(Snapshot::load(r));
+        mat.map_bump = try!(Snapshot::load(r));
+        mat.map_refl = try!(Snapshot::load(r));
+        Ok(mat)
+    }
+}
+
 impl Material {
     pub fn new() -> Material {
         Material {
@@ -1,6 +1,9 @@
 use std::default;
+use std::io::IoResult;
 
-#[deriving(Clone)]
+use snowmew::snapshot::{Snapshot, read_bytes};
+
+#[deriving(Clone, PartialEq)]
 pub struct Texture {
     width: uint,
     height: uint,
@@ -37,6 +40,25 @@ impl default::Default for Texture {
     }
 }
 
+impl Snapshot for Texture {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.width.save(w));
+        try!(self.height.save(w));
+        try!(self.depth.save(w));
+        try!(w.write_le_u64(self.data.len() as u64));
+        w.write(self.data.as_slice())
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Texture> {
+        let width = try!(Snapshot::load(r));
+        let height = try!(Snapshot::load(r));
+        let depth = try!(Snapshot::load(r));
+        let len = try!(r.read_le_u64());
+        let data = try!(read_bytes(r, len as uint));
+        Ok(Texture::new(width, height, depth, data))
+    }
+}
+
 impl Texture {
     pub fn new(width: uint, height: uint, depth: uint, data: Vec<u8>) -> Texture {
         Texture {
@@ -1,7 +1,10 @@
 
+use std::io::IoResult;
+
 use cow::btree::{BTreeMap, BTreeMapIterator};
 
 use snowmew::common::ObjectKey;
+use snowmew::snapshot::Snapshot;
 
 use Texture;
 
@@ -49,9 +52,53 @@ impl Atlas {
         layer
     }
 
+    pub fn remove_texture(&mut self, id: ObjectKey) -> bool {
+        match self.layers.pop(&id) {
+            Some(layer) => {
+                self.free_layers.push(layer);
+                true
+            }
+            None => false
+        }
+    }
+
     pub fn texture_iter<'a>(&'a self) -> BTreeMapIterator<'a, ObjectKey, uint> {
         self.layers.iter()
     }
 
     pub fn max_layers(&self) -> uint {self.max_layers}
-}
\ No newline at end of file
+
+    /// the layers holding a texture
+    pub fn used(&self) -> uint { self.layers.len() }
+
+    /// the size of every layer together
+    pub fn bytes(&self) -> uint { self.width * self.height * self.depth * self.max_layers }
+}
+
+impl Snapshot for Atlas {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.width.save(w));
+        try!(self.height.save(w));
+        try!(self.depth.save(w));
+        try!(self.max_layers.save(w));
+        try!(self.layers.save(w));
+        self.free_layers.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Atlas> {
+        let width = try!(Snapshot::load(r));
+        let height = try!(Snapshot::load(r));
+        let depth = try!(Snapshot::load(r));
+        let max_layers = try!(Snapshot::load(r));
+        let layers = try!(Snapshot::load(r));
+        let free_layers = try!(Snapshot::load(r));
+        Ok(Atlas {
+            width: width,
+            height: height,
+            depth: depth,
+            max_layers: max_layers,
+            layers: layers,
+            free_layers: free_layers
+        })
+    }
+}
@@ -0,0 +1,87 @@
+use std::io::fs;
+use std::task;
+
+use snowmew::common::ObjectKey;
+use graphics;
+
+use obj::{Obj, Imported};
+
+struct Watched {
+    path: Path,
+    keys: Imported,
+    // the files the obj was loaded from, and when they were modified
+    files: Vec<(Path, u64)>
+}
+
+fn modified(path: &Path) -> u64 {
+    match fs::stat(path) {
+        Ok(stat) => stat.modified,
+        Err(_) => 0
+    }
+}
+
+fn stamp(files: Vec<Path>) -> Vec<(Path, u64)> {
+    files.move_iter().map(|p| {
+        let m = modified(&p);
+        (p, m)
+    }).collect()
+}
+
+/// Remembers which keys every obj was imported into, and imports it
+/// into the same keys again once its obj, mtl or texture files changed
+/// on disk.
+pub struct Assets {
+    watched: Vec<Watched>
+}
+
+impl Assets {
+    pub fn new() -> Assets {
+        Assets {
+            watched: Vec::new()
+        }
+    }
+
+    /// loads the obj at path, imports it below parent and watches its files
+    pub fn load_obj(&mut self, path: &Path, parent: ObjectKey,
+                    db: &mut graphics::Graphics) -> Option<Imported> {
+        let obj = match Obj::load(path) {
+            Some(obj) => obj,
+            None => return None
+        };
+        let keys = obj.import(parent, db);
+        self.watched.push(Watched {
+            path: path.clone(),
+            keys: keys.clone(),
+            files: stamp(obj.files())
+        });
+        Some(keys)
+    }
+
+    /// Imports every obj again whose files changed since the last import,
+    /// returns if anything was imported. An obj that fails to load is
+    /// tried again once it changes again.
+    pub fn reload(&mut self, db: &mut graphics::Graphics) -> bool {
+        let mut reloaded = false;
+        for w in self.watched.mut_iter() {
+            if w.files.iter().all(|&(ref p, m)| modified(p) == m) {
+                continue;
+            }
+
+            // the parser fails on a file that is still being written
+            let path = w.path.clone();
+            match task::try(proc() { Obj::load(&path) }) {
+                Ok(Some(obj)) => {
+                    obj.reimport(&mut w.keys, db);
+                    w.files = stamp(obj.files());
+                    reloaded = true;
+                }
+                _ => {
+                    println!("failed to reload {}", w.path.display());
+                    let files = w.files.iter().map(|&(ref p, _)| p.clone()).collect();
+                    w.files = stamp(files);
+                }
+            }
+        }
+        reloaded
+    }
+}
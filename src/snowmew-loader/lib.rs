@@ -7,11 +7,17 @@ extern crate core;
 extern crate snowmew;
 extern crate cgmath;
 extern crate collections;
+extern crate serialize;
 extern crate graphics = "snowmew-graphics";
+extern crate position = "snowmew-position";
 extern crate image = "stb_image";
 
-pub use obj::Obj;
+pub use obj::{Obj, Imported};
+pub use assets::Assets;
+pub use scene::Scene;
 
 mod obj;
 mod mtl;
-mod texture;
\ No newline at end of file
+mod texture;
+pub mod scene;
+pub mod assets;
\ No newline at end of file
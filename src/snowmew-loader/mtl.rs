@@ -115,12 +115,14 @@ pub fn to_string<'a>(w: &mut Words<'a>) -> Option<String> {
 }
 
 pub struct Mtl {
+    pub path: Path,
     pub materials: Vec<Material>
 }
 
 impl Mtl {
-    fn new() -> Mtl {
+    fn new(path: &Path) -> Mtl {
         Mtl {
+            path: path.clone(),
             materials: Vec::new()
         }
     }
@@ -135,7 +137,7 @@ impl Mtl {
             }
         };
 
-        let mut mtl = Mtl::new();
+        let mut mtl = Mtl::new(path);
         let mut material = None;
         for line in file.lines() {
             let mut words = match line {
@@ -4,7 +4,7 @@ use std::io::{File, Open, Read};
 use std::path::Path;
 use std::from_str::FromStr;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use snowmew;
 use snowmew::common::Common;
@@ -45,6 +45,100 @@ pub struct Obj {
     materials: Vec<Mtl>
 }
 
+/// The keys an import wrote to, by their path below the object the file
+/// was imported into. Importing into the same keys again updates them in
+/// place, anything that is no longer in the file is deleted.
+#[deriving(Clone)]
+pub struct Imported {
+    parent: snowmew::ObjectKey,
+    keys: HashMap<String, snowmew::ObjectKey>,
+    // the paths written by the import that is running
+    written: HashSet<String>
+}
+
+impl Imported {
+    fn new(parent: snowmew::ObjectKey) -> Imported {
+        Imported {
+            parent: parent,
+            keys: HashMap::new(),
+            written: HashSet::new()
+        }
+    }
+
+    pub fn parent(&self) -> snowmew::ObjectKey { self.parent }
+
+    /// the key written for path, like "geometry/cube"
+    pub fn find(&self, path: &str) -> Option<snowmew::ObjectKey> {
+        self.keys.find(&path.to_string()).map(|k| *k)
+    }
+
+    fn dir(&mut self, db: &mut graphics::Graphics, name: &str) -> snowmew::ObjectKey {
+        let path = name.to_string();
+        self.written.insert(path.clone());
+        match self.keys.find_copy(&path) {
+            Some(oid) => oid,
+            None => {
+                let oid = db.new_object(Some(self.parent), name);
+                self.keys.insert(path, oid);
+                oid
+            }
+        }
+    }
+
+    fn object(&mut self, db: &mut graphics::Graphics, dir: &str, name: &str) -> snowmew::ObjectKey {
+        let path = format!("{}/{}", dir, name);
+        self.written.insert(path.clone());
+        match self.keys.find_copy(&path) {
+            Some(oid) => oid,
+            None => {
+                let parent = self.dir(db, dir);
+                let oid = db.new_object(Some(parent), name);
+                self.keys.insert(path, oid);
+                oid
+            }
+        }
+    }
+
+    fn vertex_buffer(&mut self, db: &mut graphics::Graphics, name: &str,
+                     vb: graphics::VertexBuffer) -> snowmew::ObjectKey {
+        let oid = self.object(db, "vertex_buffers", name);
+        db.set_vertex_buffer(oid, vb);
+        oid
+    }
+
+    fn texture(&mut self, db: &mut graphics::Graphics, name: &str,
+               texture: graphics::Texture) -> snowmew::ObjectKey {
+        let oid = self.object(db, "textures", name);
+        db.set_texture(oid, texture);
+        oid
+    }
+
+    fn material(&mut self, db: &mut graphics::Graphics, name: &str,
+                material: graphics::Material) -> snowmew::ObjectKey {
+        let oid = self.object(db, "materials", name);
+        db.set_material(oid, material);
+        oid
+    }
+
+    fn geometry(&mut self, db: &mut graphics::Graphics, name: &str, geo: Geometry) -> snowmew::ObjectKey {
+        let oid = self.object(db, "geometry", name);
+        db.set_geometry(oid, geo);
+        oid
+    }
+
+    // deletes the objects the last import did not write to
+    fn delete_stale(&mut self, db: &mut graphics::Graphics) {
+        let stale: Vec<String> = self.keys.keys()
+                                     .filter(|path| !self.written.contains(*path))
+                                     .map(|path| path.clone()).collect();
+        for path in stale.iter() {
+            let oid = self.keys.pop(path).unwrap();
+            db.delete_subtree(oid);
+        }
+        self.written.clear();
+    }
+}
+
 fn lookup<'a, T: Clone>(s: &'a [T], idx: uint) -> T {
     s[idx].clone()
 }
@@ -444,11 +538,9 @@ impl Obj {
         Some(dat)
     }
 
-    fn write_vbo(&self, parent: snowmew::ObjectKey, db: &mut graphics::Graphics) 
+    fn write_vbo(&self, keys: &mut Imported, db: &mut graphics::Graphics) 
             -> (Option<snowmew::ObjectKey>, Option<snowmew::ObjectKey>,
                 Option<snowmew::ObjectKey>, Option<snowmew::ObjectKey>) {
-        
-        let parent = db.new_object(Some(parent), "vertex_buffers");
 
         let vbo_p = if self.joined_vertices_p.len() != 0 {
             println!("\tvbo_p i {} ix {}",
@@ -470,7 +562,7 @@ impl Obj {
             }
 
             let vb = graphics::VertexBuffer::new_position(vertices, indices);
-            Some(db.new_vertex_buffer(parent, "position", vb))
+            Some(keys.vertex_buffer(db, "position", vb))
         } else {None};
 
         let vbo_pt = if self.joined_vertices_pt.len() != 0 {
@@ -495,7 +587,7 @@ impl Obj {
             }
 
             let vb = graphics::VertexBuffer::new_position_texture(vertices, indices);
-            Some(db.new_vertex_buffer(parent, "position_texture", vb))
+            Some(keys.vertex_buffer(db, "position_texture", vb))
         } else {None};
 
         let vbo_pn = if self.joined_vertices_pn.len() != 0 {
@@ -520,7 +612,7 @@ impl Obj {
             }
 
             let vb = graphics::VertexBuffer::new_position_normal(vertices, indices);
-            Some(db.new_vertex_buffer(parent, "position_normal", vb))
+            Some(keys.vertex_buffer(db, "position_normal", vb))
         } else {None};
 
         let vbo_ptn = if self.joined_vertices_ptn.len() != 0 {
@@ -547,15 +639,20 @@ impl Obj {
             }
 
             let vb = graphics::VertexBuffer::new_position_texture_normal(vertices, indices);
-            Some(db.new_vertex_buffer(parent, "position_texture_normal", vb))
+            Some(keys.vertex_buffer(db, "position_texture_normal", vb))
         } else {None};
 
         (vbo_p, vbo_pt, vbo_pn, vbo_ptn)
     }
 
-    fn write_textures(&self, parent: snowmew::ObjectKey, db: &mut graphics::Graphics)
+    fn texture_path(&self, name: &String) -> Path {
+        let mut path = self.path.clone();
+        drop(path.pop());
+        path.join(&Path::new(name.clone()))
+    }
+
+    fn write_textures(&self, keys: &mut Imported, db: &mut graphics::Graphics)
             -> HashMap<String, snowmew::ObjectKey> {
-        let parent = db.new_object(Some(parent), "textures");
         let mut map = HashMap::new();
         for m_dir in self.materials.iter() {
             for m in m_dir.materials.iter() {
@@ -566,10 +663,8 @@ impl Obj {
                         &Some(ref t) => {
                             let insert = map.find(t).is_none();
                             if insert {
-                                let mut path = self.path.clone();
-                                drop(path.pop());
-                                let text = load_texture(&path.join(&Path::new(t.clone())));
-                                let id = db.new_texture(parent, t.as_slice(), text);
+                                let text = load_texture(&self.texture_path(t));
+                                let id = keys.texture(db, t.as_slice(), text);
                                 map.insert(t.clone(), id);
                             }
                         }
@@ -581,7 +676,7 @@ impl Obj {
     }
 
     fn write_materials(&self,
-                       parent: snowmew::ObjectKey,
+                       keys: &mut Imported,
                        db: &mut graphics::Graphics,
                        text: &HashMap<String, snowmew::ObjectKey>)
             -> HashMap<String, snowmew::ObjectKey> {
@@ -592,7 +687,6 @@ impl Obj {
             *text.find(name).expect("texture not found")
         };
 
-        let parent = db.new_object(Some(parent), "materials");
         for m_dir in self.materials.iter() {
             for m in m_dir.materials.iter() {
                 let mut mat = graphics::Material::new();
@@ -606,7 +700,7 @@ impl Obj {
                 if m.map_kd.is_some() { mat.set_map_kd(lookup(m.map_kd.as_ref().unwrap())); }
                 if m.map_ks.is_some() { mat.set_map_ks(lookup(m.map_ks.as_ref().unwrap())); }
                 if m.map_ke.is_some() { mat.set_map_ke(lookup(m.map_ke.as_ref().unwrap())); }
-                let id = db.new_material(parent, m.name.as_slice(), mat);
+                let id = keys.material(db, m.name.as_slice(), mat);
                 name_to_id.insert(m.name.clone(), id);
             }
         }
@@ -615,18 +709,49 @@ impl Obj {
     }
 
 
-    pub fn import(&self, parent: snowmew::ObjectKey, db: &mut graphics::Graphics) {
+    /// the files this was loaded from, the obj, its mtllibs and textures
+    pub fn files(&self) -> Vec<Path> {
+        let mut files = vec!(self.path.clone());
+        for m_dir in self.materials.iter() {
+            files.push(m_dir.path.clone());
+            for m in m_dir.materials.iter() {
+                for t in [&m.map_ka, &m.map_kd, &m.map_ks, &m.map_ke].iter() {
+                    match **t {
+                        Some(ref t) => {
+                            let path = self.texture_path(t);
+                            if !files.contains(&path) {
+                                files.push(path);
+                            }
+                        }
+                        None => ()
+                    }
+                }
+            }
+        }
+        files
+    }
+
+    pub fn import(&self, parent: snowmew::ObjectKey, db: &mut graphics::Graphics) -> Imported {
+        let mut keys = Imported::new(parent);
+        self.reimport(&mut keys, db);
+        keys
+    }
+
+    /// writes the file into the keys of an earlier import
+    pub fn reimport(&self, keys: &mut Imported, db: &mut graphics::Graphics) {
         println!("v {} t {} n {}",
             self.vertices.len(),
             self.textures.len(),
             self.normals.len()
         );
 
-        let textures = self.write_textures(parent, db);
-        let materials = self.write_materials(parent, db, &textures);
-        let (vbo_p, vbo_pt, vbo_pn, vbo_ptn) = self.write_vbo(parent, db);
-        let geometry = db.add_dir(Some(parent), "geometry");
-        let objects = db.add_dir(Some(parent), "objects");
+        for dir in ["textures", "materials", "vertex_buffers", "geometry", "objects"].iter() {
+            keys.dir(db, *dir);
+        }
+
+        let textures = self.write_textures(keys, db);
+        let materials = self.write_materials(keys, db, &textures);
+        let (vbo_p, vbo_pt, vbo_pn, vbo_ptn) = self.write_vbo(keys, db);
         for &(ref name, ref mat, start, len, vt) in self.objects.iter() {
             println!("{} {}", name, mat);
             let vbo = match vt {
@@ -639,11 +764,11 @@ impl Obj {
             match vbo {
                 None => (),
                 Some(vbo) => {
-                    let geo = db.new_geometry(geometry, name.as_slice(), Geometry::triangles(vbo, start, len));
+                    let geo = keys.geometry(db, name.as_slice(), Geometry::triangles(vbo, start, len));
                     if mat.is_some() {
                         let mat = materials.find(mat.as_ref().unwrap());
                         if mat.is_some() {
-                            let obj = db.new_object(Some(objects), name.as_slice());
+                            let obj = keys.object(db, "objects", name.as_slice());
                             db.set_draw(obj, geo, *mat.unwrap());
                         }
                     }
@@ -652,5 +777,6 @@ impl Obj {
 
         }
 
+        keys.delete_stale(db);
     }
 }
\ No newline at end of file
@@ -0,0 +1,355 @@
+use std::io::{File, IoResult, MemWriter};
+use std::path::Path;
+
+use serialize::{json, Encodable, Decodable};
+
+use cgmath::vector::Vector3;
+use cgmath::quaternion::Quaternion;
+use cgmath::transform::Decomposed;
+
+use snowmew::common::{Common, ObjectKey};
+use position::Positions;
+use graphics::{Graphics, Material};
+use graphics::light;
+
+pub static SCENE_VERSION: u32 = 1;
+
+#[deriving(Clone, Encodable, Decodable)]
+pub struct Transform {
+    pub scale: f32,
+    // s, x, y, z
+    pub rotation: Vec<f32>,
+    pub displacement: Vec<f32>
+}
+
+#[deriving(Clone, Encodable, Decodable)]
+pub struct DrawableRef {
+    pub geometry: String,
+    pub material: String
+}
+
+#[deriving(Clone, Encodable, Decodable)]
+pub struct LightDesc {
+    // "point" or "directional"
+    pub kind: String,
+    pub color: Vec<f32>,
+    pub intensity: f32,
+    pub normal: Option<Vec<f32>>
+}
+
+#[deriving(Clone, Encodable, Decodable)]
+pub struct MaterialDesc {
+    pub path: String,
+    pub ka: Vec<f32>,
+    pub kd: Vec<f32>,
+    pub ks: Vec<f32>,
+    pub ns: f32,
+    pub ni: f32,
+    pub map_ka: Option<String>,
+    pub map_kd: Option<String>,
+    pub map_ks: Option<String>
+}
+
+#[deriving(Clone, Encodable, Decodable)]
+pub struct ObjectDesc {
+    pub path: String,
+    pub transform: Option<Transform>,
+    pub drawable: Option<DrawableRef>,
+    pub light: Option<LightDesc>
+}
+
+/// A text description of part of the object tree. Paths are the same
+/// as used by `Common::find`. Geometry, vertex buffers and textures
+/// are not stored, they are only referenced by path.
+#[deriving(Clone, Encodable, Decodable)]
+pub struct Scene {
+    pub version: u32,
+    pub scenes: Vec<String>,
+    pub materials: Vec<MaterialDesc>,
+    pub objects: Vec<ObjectDesc>
+}
+
+fn to_vec3(v: &Vec<f32>) -> Result<Vector3<f32>, String> {
+    match v.as_slice() {
+        [x, y, z] => Ok(Vector3::new(x, y, z)),
+        _ => Err(format!("expected 3 components found {}", v.len()))
+    }
+}
+
+fn from_vec3(v: Vector3<f32>) -> Vec<f32> {
+    vec!(v.x, v.y, v.z)
+}
+
+// the name of the root object is "base", it is not part of a path
+fn object_path<D: Common>(db: &D, oid: ObjectKey) -> String {
+    let name = db.name(oid);
+    match name.as_slice().find('/') {
+        Some(idx) => name.as_slice().slice_from(idx+1).to_string(),
+        None => name.clone()
+    }
+}
+
+fn find<D: Common>(db: &D, path: &str) -> Result<ObjectKey, String> {
+    match db.find(path) {
+        Some(oid) => Ok(oid),
+        None => Err(format!("could not find {}", path))
+    }
+}
+
+fn find_opt<D: Common>(db: &D, path: &Option<String>) -> Result<Option<ObjectKey>, String> {
+    match *path {
+        Some(ref path) => Ok(Some(try!(find(db, path.as_slice())))),
+        None => Ok(None)
+    }
+}
+
+// creates any missing directory in the path, returning the parent
+// and the name of the last element
+fn make_parent<'a, D: Common>(db: &mut D, path: &'a str) -> (Option<ObjectKey>, &'a str) {
+    let idx = match path.rfind('/') {
+        Some(idx) => idx,
+        None => return (None, path)
+    };
+
+    let mut parent = None;
+    for name in path.slice_to(idx).split('/') {
+        let next = match parent {
+            Some(p) => db.walk_dir(p).find(|&(n, _)| n == name).map(|(_, oid)| oid),
+            None => db.find(name)
+        };
+        parent = match next {
+            Some(oid) => Some(oid),
+            None => Some(db.add_dir(parent, name))
+        };
+    }
+    (parent, path.slice_from(idx+1))
+}
+
+impl Transform {
+    fn from_decomposed(t: &Decomposed<f32, Vector3<f32>, Quaternion<f32>>) -> Transform {
+        Transform {
+            scale: t.scale,
+            rotation: vec!(t.rot.s, t.rot.v.x, t.rot.v.y, t.rot.v.z),
+            displacement: from_vec3(t.disp)
+        }
+    }
+
+    fn to_decomposed(&self) -> Result<Decomposed<f32, Vector3<f32>, Quaternion<f32>>, String> {
+        let rot = match self.rotation.as_slice() {
+            [s, x, y, z] => Quaternion::new(s, x, y, z),
+            _ => return Err(format!("expected 4 rotation components found {}", self.rotation.len()))
+        };
+
+        Ok(Decomposed {
+            scale: self.scale,
+            rot: rot,
+            disp: try!(to_vec3(&self.displacement))
+        })
+    }
+}
+
+impl LightDesc {
+    fn from_light(l: &light::Light) -> LightDesc {
+        match *l {
+            light::Point(ref p) => LightDesc {
+                kind: "point".to_string(),
+                color: from_vec3(p.color()),
+                intensity: p.intensity(),
+                normal: None
+            },
+            light::Directional(ref d) => LightDesc {
+                kind: "directional".to_string(),
+                color: from_vec3(d.color()),
+                intensity: d.intensity(),
+                normal: Some(from_vec3(d.normal()))
+            }
+        }
+    }
+
+    fn to_light(&self) -> Result<light::Light, String> {
+        let color = try!(to_vec3(&self.color));
+        match (self.kind.as_slice(), &self.normal) {
+            ("point", _) => {
+                Ok(light::Point(light::Point::new(color, self.intensity)))
+            }
+            ("directional", &Some(ref normal)) => {
+                let normal = try!(to_vec3(normal));
+                Ok(light::Directional(light::Directional::new(normal, color, self.intensity)))
+            }
+            ("directional", &None) => Err("directional light without a normal".to_string()),
+            (other, _) => Err(format!("unknown light kind {}", other))
+        }
+    }
+}
+
+impl MaterialDesc {
+    fn from_material<D: Common>(db: &D, path: String, m: &Material) -> MaterialDesc {
+        let map = |oid: Option<ObjectKey>| oid.map(|oid| object_path(db, oid));
+
+        MaterialDesc {
+            path: path,
+            ka: from_vec3(m.ka()),
+            kd: from_vec3(m.kd()),
+            ks: from_vec3(m.ks()),
+            ns: m.ns(),
+            ni: m.ni(),
+            map_ka: map(m.map_ka()),
+            map_kd: map(m.map_kd()),
+            map_ks: map(m.map_ks())
+        }
+    }
+
+    fn to_material<D: Common>(&self, db: &D) -> Result<Material, String> {
+        let mut mat = Material::new();
+        mat.set_ka(try!(to_vec3(&self.ka)));
+        mat.set_kd(try!(to_vec3(&self.kd)));
+        mat.set_ks(try!(to_vec3(&self.ks)));
+        mat.set_ns(self.ns);
+        mat.set_ni(self.ni);
+        match try!(find_opt(db, &self.map_ka)) { Some(t) => mat.set_map_ka(t), None => () }
+        match try!(find_opt(db, &self.map_kd)) { Some(t) => mat.set_map_kd(t), None => () }
+        match try!(find_opt(db, &self.map_ks)) { Some(t) => mat.set_map_ks(t), None => () }
+        Ok(mat)
+    }
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene {
+            version: SCENE_VERSION,
+            scenes: Vec::new(),
+            materials: Vec::new(),
+            objects: Vec::new()
+        }
+    }
+
+    pub fn from_str(s: &str) -> Result<Scene, String> {
+        let json = match json::from_str(s) {
+            Ok(json) => json,
+            Err(err) => return Err(format!("{}", err))
+        };
+
+        let mut decoder = json::Decoder::new(json);
+        let scene: Scene = match Decodable::decode(&mut decoder) {
+            Ok(scene) => scene,
+            Err(err) => return Err(format!("{}", err))
+        };
+
+        if scene.version > SCENE_VERSION {
+            return Err(format!("unsupported scene version {}", scene.version));
+        }
+        Ok(scene)
+    }
+
+    pub fn load(path: &Path) -> Result<Scene, String> {
+        match File::open(path).read_to_string() {
+            Ok(s) => Scene::from_str(s.as_slice()),
+            Err(err) => Err(format!("{}", err))
+        }
+    }
+
+    pub fn to_string(&self) -> String {
+        let mut w = MemWriter::new();
+        {
+            let mut encoder = json::PrettyEncoder::new(&mut w);
+            self.encode(&mut encoder).ok().expect("failed to encode scene");
+        }
+        String::from_utf8(w.unwrap()).ok().expect("encoded scene is not utf8")
+    }
+
+    pub fn save(&self, path: &Path) -> IoResult<()> {
+        let mut file = try!(File::create(path));
+        file.write_str(self.to_string().as_slice())
+    }
+
+    /// Walks the tree starting at root and describes it, 0 describes
+    /// the whole tree
+    pub fn export<D: Graphics+Positions>(db: &D, root: ObjectKey) -> Scene {
+        let mut scene = Scene::new();
+        if root == 0 {
+            for (name, child) in db.walk_dir(0) {
+                scene.visit(db, child, name.to_string());
+            }
+        } else {
+            scene.visit(db, root, object_path(db, root));
+        }
+        scene
+    }
+
+    fn visit<D: Graphics+Positions>(&mut self, db: &D, oid: ObjectKey, path: String) {
+        if db.is_scene(oid) {
+            self.scenes.push(path.clone());
+        } else if db.material(oid).is_some() {
+            self.materials.push(MaterialDesc::from_material(db, path.clone(), db.material(oid).unwrap()));
+        } else if db.geometry(oid).is_some() ||
+                  db.vertex_buffer(oid).is_some() ||
+                  db.get_texture(oid).is_some() {
+            // only referenced by path
+        } else {
+            self.objects.push(ObjectDesc {
+                path: path.clone(),
+                transform: db.location(oid).map(|t| Transform::from_decomposed(&t)),
+                drawable: db.get_draw(oid).map(|d| DrawableRef {
+                    geometry: object_path(db, d.geometry),
+                    material: object_path(db, d.material)
+                }),
+                light: db.get_light(oid).map(|l| LightDesc::from_light(l))
+            });
+        }
+
+        for (name, child) in db.walk_dir(oid) {
+            self.visit(db, child, format!("{}/{}", path, name));
+        }
+    }
+
+    /// Builds the described tree, objects that already exist are reused
+    pub fn import<D: Graphics+Positions>(&self, db: &mut D) -> Result<(), String> {
+        for path in self.scenes.iter() {
+            if db.find(path.as_slice()).is_none() {
+                let (parent, name) = make_parent(db, path.as_slice());
+                db.add_scene(parent, name);
+            }
+        }
+
+        for m in self.materials.iter() {
+            if db.find(m.path.as_slice()).is_some() {
+                continue;
+            }
+            let mat = try!(m.to_material(db));
+            match make_parent(db, m.path.as_slice()) {
+                (Some(parent), name) => { db.new_material(parent, name, mat); }
+                (None, _) => return Err(format!("material {} needs a parent", m.path))
+            }
+        }
+
+        for o in self.objects.iter() {
+            let oid = match db.find(o.path.as_slice()) {
+                Some(oid) => oid,
+                None => {
+                    let (parent, name) = make_parent(db, o.path.as_slice());
+                    db.new_object(parent, name)
+                }
+            };
+
+            match o.light {
+                Some(ref l) => db.set_light(oid, try!(l.to_light())),
+                None => ()
+            }
+
+            match o.transform {
+                Some(ref t) => db.update_location(oid, try!(t.to_decomposed())),
+                None => ()
+            }
+
+            match o.drawable {
+                Some(ref d) => {
+                    let geo = try!(find(db, d.geometry.as_slice()));
+                    let mat = try!(find(db, d.material.as_slice()));
+                    db.set_draw(oid, geo, mat);
+                }
+                None => ()
+            }
+        }
+        Ok(())
+    }
+}
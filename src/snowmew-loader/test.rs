@@ -0,0 +1,224 @@
+extern crate snowmew;
+extern crate cgmath;
+extern crate cow;
+extern crate graphics = "snowmew-graphics";
+extern crate position = "snowmew-position";
+extern crate loader = "snowmew-loader";
+
+use cgmath::vector::Vector3;
+use cgmath::quaternion::Quaternion;
+use cgmath::transform::{Transform, Decomposed};
+
+use std::io::IoResult;
+use std::io::timer::sleep;
+use cow::btree::BTreeSet;
+
+use snowmew::ObjectKey;
+use snowmew::common::{Common, CommonData, Remap};
+use snowmew::replicate::Replicate;
+use snowmew::net::{Server, Client};
+use position::{Positions, PositionData};
+use graphics::{Graphics, GraphicsData, Material};
+use graphics::light;
+use loader::Scene;
+
+#[deriving(Clone)]
+struct TestData {
+    common: CommonData,
+    position: PositionData,
+    graphics: GraphicsData
+}
+
+impl TestData {
+    fn new() -> TestData {
+        TestData {
+            common: CommonData::new(),
+            position: PositionData::new(),
+            graphics: GraphicsData::new()
+        }
+    }
+}
+
+impl Common for TestData {
+    fn get_common<'a>(&'a self) -> &'a CommonData { &self.common }
+    fn get_common_mut<'a>(&'a mut self) -> &'a mut CommonData { &mut self.common }
+
+    fn delete_data(&mut self, oid: ObjectKey) {
+        self.delete_position(oid);
+        self.delete_graphics(oid);
+    }
+
+    fn copy_data(&mut self, remap: &Remap) {
+        self.copy_positions(remap);
+        self.copy_graphics(remap);
+    }
+}
+
+impl Replicate for TestData {
+    fn write_delta(&self, old: &TestData, old_hidden: &BTreeSet<ObjectKey>, hidden: &BTreeSet<ObjectKey>,
+                   w: &mut Writer) -> IoResult<()> {
+        try!(self.common.write_delta(&old.common, old_hidden, hidden, w));
+        try!(self.position.write_delta(&old.position, old_hidden, hidden, w));
+        self.graphics.write_delta(&old.graphics, old_hidden, hidden, w)
+    }
+
+    fn read_delta(&self, r: &mut Reader) -> IoResult<TestData> {
+        Ok(TestData {
+            common: try!(self.common.read_delta(r)),
+            position: try!(self.position.read_delta(r)),
+            graphics: try!(self.graphics.read_delta(r))
+        })
+    }
+}
+
+impl Positions for TestData {
+    fn get_position<'a>(&'a self) -> &'a PositionData { &self.position }
+    fn get_position_mut<'a>(&'a mut self) -> &'a mut PositionData { &mut self.position }
+}
+
+impl Graphics for TestData {
+    fn get_graphics<'a>(&'a self) -> &'a GraphicsData { &self.graphics }
+    fn get_graphics_mut<'a>(&'a mut self) -> &'a mut GraphicsData { &mut self.graphics }
+}
+
+fn build() -> TestData {
+    let mut db = TestData::new();
+    let world = db.new_scene("world");
+    db.add_scene(Some(world), "inner");
+    let mats = db.new_object(None, "materials");
+    db.new_material(mats, "red", Material::new());
+
+    let lamp = db.new_light(world, "lamp", light::Point(light::Point::new(Vector3::new(1f32, 0., 0.), 2.)));
+    let mut t: Decomposed<f32, Vector3<f32>, Quaternion<f32>> = Transform::identity();
+    t.disp = Vector3::new(1f32, 2., 3.);
+    db.update_location(lamp, t);
+    db
+}
+
+#[test]
+fn export_root() {
+    let db = build();
+    let scene = Scene::export(&db, 0);
+
+    assert!(scene.scenes == vec!("world".to_string(), "world/inner".to_string()));
+    assert!(scene.materials.iter().all(|m| m.path.as_slice() == "materials/red"));
+    assert!(scene.objects.iter().all(|o| !o.path.as_slice().starts_with("base")));
+}
+
+#[test]
+fn import_roundtrip() {
+    let text = Scene::export(&build(), 0).to_string();
+    let scene = Scene::from_str(text.as_slice()).unwrap();
+
+    let mut db = TestData::new();
+    scene.import(&mut db).unwrap();
+
+    let inner = db.find("world/inner").unwrap();
+    assert!(db.is_scene(inner));
+    assert!(db.object(inner).unwrap().parent == db.find("world").unwrap());
+    assert!(db.find("materials/red").is_some());
+
+    let lamp = db.find("world/lamp").unwrap();
+    assert!(db.get_light(lamp).is_some());
+    assert!(db.location(lamp).unwrap().disp == Vector3::new(1f32, 2., 3.));
+
+    // importing over the existing objects updates them
+    scene.import(&mut db).unwrap();
+    assert!(db.find("world/lamp").unwrap() == lamp);
+}
+
+#[test]
+fn replication_visibility() {
+    let mut server = Server::bind("127.0.0.1", 0, TestData::new()).unwrap();
+    let mut client = Client::connect("127.0.0.1", server.port(), TestData::new()).unwrap();
+
+    let mut db = build();
+    let world = db.find("world").unwrap();
+    let other = db.new_scene("other");
+    let lamp = db.find("world/lamp").unwrap();
+    db.reparent(lamp, Some(other));
+    db.reparent_position(lamp);
+
+    // the subscription arrives at some point, until then everything is sent
+    client.subscribe(&[world]).unwrap();
+    let mut copy = TestData::new();
+    for _ in range(0u, 100) {
+        server.send(&db);
+        copy = client.recv().unwrap();
+        if copy.get_light(lamp).is_none() {
+            break;
+        }
+        sleep(10);
+    }
+    assert!(copy.find("other/lamp") == Some(lamp));
+    assert!(copy.get_light(lamp).is_none());
+    assert!(copy.location(lamp).is_none());
+
+    // moving into the subscribed scene sends the unchanged entries
+    db.reparent(lamp, Some(world));
+    db.reparent_position(lamp);
+    server.send(&db);
+    let copy = client.recv().unwrap();
+    assert!(copy.get_light(lamp).is_some());
+    assert!(copy.location(lamp).unwrap().disp == Vector3::new(1f32, 2., 3.));
+
+    // and moving out again removes them
+    db.reparent(lamp, Some(other));
+    db.reparent_position(lamp);
+    server.send(&db);
+    let copy = client.recv().unwrap();
+    assert!(copy.find("other/lamp") == Some(lamp));
+    assert!(copy.get_light(lamp).is_none());
+    assert!(copy.location(lamp).is_none());
+
+    // between two hidden scenes it stays hidden
+    let third = db.new_scene("third");
+    db.reparent(lamp, Some(third));
+    db.reparent_position(lamp);
+    server.send(&db);
+    let copy = client.recv().unwrap();
+    assert!(copy.find("third/lamp") == Some(lamp));
+    assert!(copy.get_light(lamp).is_none());
+}
+
+#[test]
+fn reused_key_starts_empty() {
+    let mut db = build();
+    let world = db.find("world").unwrap();
+    let red = db.find("materials/red").unwrap();
+    let lamp = db.find("world/lamp").unwrap();
+    db.set_draw(lamp, red, red);
+    assert!(db.delete_object(lamp));
+
+    // the freed key is handed out again without the old data
+    let bulb = db.new_object(Some(world), "bulb");
+    assert!(bulb == lamp);
+    assert!(db.location(bulb).is_none());
+    assert!(db.drawable(bulb).is_none());
+    assert!(db.get_light(bulb).is_none());
+}
+
+#[test]
+fn reimport_deletes_stale() {
+    use std::io::{File, TempDir};
+    use loader::Obj;
+
+    let dir = TempDir::new("snowmew-loader").unwrap();
+    let path = dir.path().join("shapes.obj");
+    let tri = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+    File::create(&path).write_str(format!("{}o a\nf 1 2 3\no b\nf 1 2 3\n", tri).as_slice()).unwrap();
+
+    let mut db = TestData::new();
+    let shapes = db.new_object(None, "shapes");
+    let mut keys = Obj::load(&path).unwrap().import(shapes, &mut db);
+    let a = keys.find("geometry/a").unwrap();
+    let b = keys.find("geometry/b").unwrap();
+
+    // b is no longer in the file
+    File::create(&path).write_str(format!("{}o a\nf 1 2 3\n", tri).as_slice()).unwrap();
+    Obj::load(&path).unwrap().reimport(&mut keys, &mut db);
+    assert!(keys.find("geometry/a") == Some(a));
+    assert!(keys.find("geometry/b").is_none());
+    assert!(db.object(b).is_none());
+    assert!(db.find("shapes/geometry/b").is_none());
+}
@@ -9,7 +9,13 @@ extern crate cgmath;
 extern crate position = "snowmew-position";
 extern crate collision;
 
-use snowmew::common::{ObjectKey, Common};
+use std::io::IoResult;
+
+use snowmew::common::{ObjectKey, Common, Tags, ALL_TAGS, Remap};
+use snowmew::snapshot::Snapshot;
+use snowmew::stats::{Stats, TableStats, table_stats};
+use snowmew::merge::{Merge, Policy, LastWriterWins, merge_map, merge_map_by, pick};
+use snowmew::lockstep::{StateHashDelta, table_hash_delta, value_hash_delta};
 use position::Positions;
 
 use collision::aabb::{Aabb3};
@@ -22,7 +28,7 @@ use cow::btree::BTreeMap;
 pub mod manager;
 
 #[deriving(Clone)]
-struct Collider(Aabb3<f32>);
+pub struct Collider(pub Aabb3<f32>);
 
 impl std::default::Default for Collider {
     fn default() -> Collider {
@@ -31,8 +37,22 @@ impl std::default::Default for Collider {
     }
 }
 
+impl Snapshot for Collider {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        let &Collider(ref aabb) = self;
+        try!(aabb.min.save(w));
+        aabb.max.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Collider> {
+        let min = try!(Snapshot::load(r));
+        let max = try!(Snapshot::load(r));
+        Ok(Collider(Aabb3::new(min, max)))
+    }
+}
+
 #[deriving(Clone)]
-struct Velocity(Vector3<f32>);
+pub struct Velocity(pub Vector3<f32>);
 
 impl std::default::Default for Velocity {
     fn default() -> Velocity {
@@ -40,12 +60,51 @@ impl std::default::Default for Velocity {
     }
 }
 
+impl Snapshot for Velocity {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        let &Velocity(ref v) = self;
+        v.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Velocity> {
+        Ok(Velocity(try!(Snapshot::load(r))))
+    }
+}
+
+/// Posted to both objects when a moving collider is stopped by a
+/// static one, other is the object that was hit.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Collision {
+    pub other: ObjectKey
+}
+
 #[deriving(Clone)]
 pub struct PhysicsData {
     static_colliders: BTreeMap<ObjectKey, Collider>,
     colliders: BTreeMap<ObjectKey, Collider>,
     velocity: BTreeMap<ObjectKey, Velocity>,
-    static_version: uint
+    collision_mask: BTreeMap<ObjectKey, Tags>,
+    static_version: uint,
+    policy: PhysicsPolicy
+}
+
+/// How the tables that are merged per object resolve a key that was
+/// changed by both sides, `new` uses LastWriterWins for all of them.
+#[deriving(Clone)]
+pub struct PhysicsPolicy {
+    pub colliders: Policy<Collider>,
+    pub velocity: Policy<Velocity>,
+    pub collision_mask: Policy<Tags>
+}
+
+impl PhysicsPolicy {
+    pub fn new() -> PhysicsPolicy {
+        PhysicsPolicy {
+            colliders: LastWriterWins,
+            velocity: LastWriterWins,
+            collision_mask: LastWriterWins
+        }
+    }
 }
 
 impl PhysicsData {
@@ -54,15 +113,93 @@ impl PhysicsData {
             static_colliders: BTreeMap::new(),
             colliders: BTreeMap::new(),
             velocity: BTreeMap::new(),
-            static_version: 0
+            collision_mask: BTreeMap::new(),
+            static_version: 0,
+            policy: PhysicsPolicy::new()
         }
     }
 }
 
+fn same_collider(&Collider(ref a): &Collider, &Collider(ref b): &Collider) -> bool {
+    a.min == b.min && a.max == b.max
+}
+
+/// Only one side may change the static colliders, everything else is
+/// merged per object with a conflict resolved by the policy of ours.
+impl Merge for PhysicsData {
+    fn merge(base: &PhysicsData, ours: &PhysicsData, theirs: &PhysicsData) -> Result<PhysicsData, String> {
+        let statics = try!(pick(base, ours, theirs, |b, x| b.static_version != x.static_version,
+                                "the static colliders"));
+        let policy = &ours.policy;
+        let mut out = statics.clone();
+        out.colliders = merge_map_by(&base.colliders, &ours.colliders, &theirs.colliders,
+                                     |a, b| same_collider(a, b), &policy.colliders);
+        out.velocity = merge_map_by(&base.velocity, &ours.velocity, &theirs.velocity,
+                                    |&Velocity(ref a), &Velocity(ref b)| a == b, &policy.velocity);
+        out.collision_mask = merge_map(&base.collision_mask, &ours.collision_mask,
+                                       &theirs.collision_mask, &policy.collision_mask);
+        out.policy = policy.clone();
+        Ok(out)
+    }
+}
+
+impl Stats for PhysicsData {
+    fn stats(&self, other: Option<&PhysicsData>) -> Vec<TableStats> {
+        vec!(table_stats("static_colliders", &self.static_colliders, other.map(|o| &o.static_colliders)),
+             table_stats("colliders", &self.colliders, other.map(|o| &o.colliders)),
+             table_stats("velocity", &self.velocity, other.map(|o| &o.velocity)),
+             table_stats("collision_mask", &self.collision_mask, other.map(|o| &o.collision_mask)))
+    }
+}
+
+impl StateHashDelta for PhysicsData {
+    fn state_hash_delta(&self, old: &PhysicsData) -> u64 {
+        table_hash_delta("static_colliders", &old.static_colliders, &self.static_colliders,
+                         |a, b| same_collider(a, b)) +
+        table_hash_delta("colliders", &old.colliders, &self.colliders, |a, b| same_collider(a, b)) +
+        table_hash_delta("velocity", &old.velocity, &self.velocity,
+                         |&Velocity(ref a), &Velocity(ref b)| a == b) +
+        table_hash_delta("collision_mask", &old.collision_mask, &self.collision_mask, |a, b| a == b) +
+        value_hash_delta("static_version", &old.static_version, &self.static_version)
+    }
+}
+
+impl Snapshot for PhysicsData {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.static_colliders.save(w));
+        try!(self.colliders.save(w));
+        try!(self.velocity.save(w));
+        try!(self.collision_mask.save(w));
+        self.static_version.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<PhysicsData> {
+        let static_colliders = try!(Snapshot::load(r));
+        let colliders = try!(Snapshot::load(r));
+        let velocity = try!(Snapshot::load(r));
+        let collision_mask = try!(Snapshot::load(r));
+        let static_version = try!(Snapshot::load(r));
+        Ok(PhysicsData {
+            static_colliders: static_colliders,
+            colliders: colliders,
+            velocity: velocity,
+            collision_mask: collision_mask,
+            static_version: static_version,
+            // merge policies belong to the running game
+            policy: PhysicsPolicy::new()
+        })
+    }
+}
+
 pub trait Physics: Common + Positions {
     fn get_physics<'a>(&'a self) -> &'a PhysicsData;
     fn get_physics_mut<'a>(&'a mut self) -> &'a mut PhysicsData;
 
+    /// Sets how the physics tables resolve a merge conflict
+    fn set_physics_merge_policy(&mut self, policy: PhysicsPolicy) {
+        self.get_physics_mut().policy = policy;
+    }
+
     fn add_static_collider(&mut self, key: ObjectKey, collider: Aabb3<f32>) {
         self.get_physics_mut().static_version += 1;
         self.get_physics_mut().static_colliders.insert(key, Collider(collider));   
@@ -96,5 +233,52 @@ pub trait Physics: Common + Positions {
             None => None
         }
     }
+
+    /// the tags of static objects this collider is stopped by
+    fn set_collision_mask(&mut self, key: ObjectKey, mask: Tags) {
+        self.get_physics_mut().collision_mask.insert(key, mask);
+    }
+
+    fn collision_mask(&self, key: ObjectKey) -> Tags {
+        match self.get_physics().collision_mask.find(&key) {
+            Some(mask) => *mask,
+            None => ALL_TAGS
+        }
+    }
+
+    // copies the colliders and velocities of an instantiated template
+    fn copy_physics(&mut self, remap: &Remap) {
+        let physics = self.get_physics().clone();
+        for (old, new) in remap.iter() {
+            match physics.static_colliders.find(old) {
+                Some(c) => {
+                    self.get_physics_mut().static_version += 1;
+                    self.get_physics_mut().static_colliders.insert(*new, c.clone());
+                }
+                None => ()
+            }
+            match physics.colliders.find(old) {
+                Some(c) => { self.get_physics_mut().colliders.insert(*new, c.clone()); }
+                None => ()
+            }
+            match physics.velocity.find(old) {
+                Some(v) => { self.get_physics_mut().velocity.insert(*new, v.clone()); }
+                None => ()
+            }
+            match physics.collision_mask.find(old) {
+                Some(m) => { self.get_physics_mut().collision_mask.insert(*new, *m); }
+                None => ()
+            }
+        }
+    }
+
+    fn delete_physics(&mut self, key: ObjectKey) {
+        if self.get_physics_mut().static_colliders.remove(&key) {
+            self.get_physics_mut().static_version += 1;
+        }
+        self.get_physics_mut().colliders.remove(&key);
+        self.get_physics_mut().velocity.remove(&key);
+        self.get_physics_mut().collision_mask.remove(&key);
+    }
 }
 
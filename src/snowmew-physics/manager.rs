@@ -6,16 +6,18 @@ use cgmath::vector::{Vector, Vector4, Vector3};
 use cgmath::matrix::{Matrix, Matrix4};
 use cgmath::transform::Decomposed;
 
-use cow::join::join_maps;
-
-use snowmew::common::{ObjectKey, CommonData, Common};
+use snowmew::common::{ObjectKey, CommonData, Common, Remap, matches_tags};
+use snowmew::query::query;
+use snowmew::common::FrameInfo;
+use snowmew::event::To;
+use snowmew::ActiveManager;
 use collision::bvh::{BvhBuilder, Bvh};
 use collision::aabb::{Aabb3};
 use collision::Merge;
 
 use position::{Positions, ComputedPosition, PositionData};
 
-use {Physics, Velocity, Collider, PhysicsData};
+use {Physics, Velocity, Collider, PhysicsData, Collision};
 
 #[deriving(Clone)]
 struct PhysicsTemp {
@@ -37,6 +39,16 @@ impl PhysicsTemp {
 impl Common for PhysicsTemp {
     fn get_common<'a>(&'a self) -> &'a CommonData { &self.common }
     fn get_common_mut<'a>(&'a mut self) -> &'a mut CommonData { &mut self.common }
+
+    fn delete_data(&mut self, oid: ObjectKey) {
+        self.delete_position(oid);
+        self.delete_physics(oid);
+    }
+
+    fn copy_data(&mut self, remap: &Remap) {
+        self.copy_positions(remap);
+        self.copy_physics(remap);
+    }
 }
 
 impl Positions for PhysicsTemp {
@@ -71,15 +83,16 @@ impl PhysicsManager {
             return;
         }
 
+        // start from an empty builder, colliders may have been removed
+        // since the last time the bvh was built
         let mut bvh = match self.static_builder.take() {
             Some(bvh) => bvh,
-            None => match self.static_bvh.take() {
-                Some(bvh_builder) => bvh_builder.to_builder(),
-                None => fail!("no bvh or builder")
-            }
+            None => BvhBuilder::new()
         };
 
-        for (key, (loc, &Collider(ref coll))) in join_maps(data.location_iter(), data.get_physics().static_colliders.iter()) {
+        // the colliders drive the walk, most located objects have none
+        for (key, (&Collider(ref coll), loc)) in query(data.get_physics().static_colliders.iter())
+                                                     .lookup(data.location_table()) {
             let aabb = recalc_aabb(coll, self.matrix.get(pos.get_loc(*loc)));
             bvh.add(aabb, *key);
         }
@@ -102,24 +115,34 @@ impl PhysicsManager {
         match self.static_bvh {
             None => fail!("Could not unwrap bvh"),
             Some(ref bvh) => {
-                for (key, ((loc, &Velocity(ref vel)), &Collider(ref coll))) in 
-                        join_maps(join_maps(old.location_iter(), old.get_physics().velocity.iter()),
-                                            old.get_physics().colliders.iter()) {
+                for (key, ((&Velocity(ref vel), &Collider(ref coll)), loc)) in
+                        query(old.get_physics().velocity.iter())
+                            .join(old.get_physics().colliders.iter())
+                            .lookup(old.location_table()) {
                     let vel = vel.mul_s(time);
                     let aabb = recalc_aabb_with_vec(coll, self.matrix.get(pos.get_loc(*loc)), &vel);
-                    let mut collided = false;
-                    for _ in bvh.collision_iter(&aabb) {
-                        collided = true;
-                        break;
+                    let mask = old.collision_mask(*key);
+                    let mut hit = None;
+                    for (_, other) in bvh.collision_iter(&aabb) {
+                        if matches_tags(old.tags(*other), mask) {
+                            hit = Some(*other);
+                            break;
+                        }
                     }
-                    if !collided {
-                        let t = data.location(*key).expect("unxpeced missing location");
-                        let disp = t.disp.add_v(&vel);
-                        let scale = t.scale;
-                        let rot = t.rot;
-                        data.update_location(*key, Decomposed{scale: scale,
-                                                              rot:   rot,
-                                                              disp:  disp});
+                    match hit {
+                        None => {
+                            let t = data.location(*key).expect("unxpeced missing location");
+                            let disp = t.disp.add_v(&vel);
+                            let scale = t.scale;
+                            let rot = t.rot;
+                            data.update_location(*key, Decomposed{scale: scale,
+                                                                  rot:   rot,
+                                                                  disp:  disp});
+                        }
+                        Some(other) => {
+                            data.post_event(To(*key), Collision{other: other});
+                            data.post_event(To(other), Collision{other: *key});
+                        }
                     }
                 }                
             }
@@ -128,6 +151,14 @@ impl PhysicsManager {
     }
 }
 
+impl<P: Physics+Send> ActiveManager<P> for PhysicsManager {
+    fn run(&mut self, data: P, frame: &FrameInfo) -> P {
+        let mut data = data;
+        self.step(&mut data, frame.delta as f32);
+        data
+    }
+}
+
 fn aabb_point(idx: uint, aabb: &Aabb3<f32>, mat: &Matrix4<f32>) -> Point3<f32> {
     let v = Vector4::new(if idx & 0x1 == 0x1 {aabb.min.x} else {aabb.max.x},
                          if idx & 0x2 == 0x2 {aabb.min.y} else {aabb.max.y},
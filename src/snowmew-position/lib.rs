@@ -9,20 +9,30 @@ extern crate OpenCL;
 extern crate cow;
 extern crate time;
 
+use std::collections::TreeMap;
 use std::default::Default;
+use std::io::IoResult;
+use std::iter::AdditiveIterator;
 
 use cgmath::transform::{Transform, Decomposed};
 use cgmath::quaternion::Quaternion;
-use cgmath::vector::{Vector3, Vector4};
+use cgmath::vector::{Vector, Vector3, Vector4};
 use cgmath::matrix::{Matrix4, ToMatrix4, Matrix};
 
 use OpenCL::hl::{Device, Context, CommandQueue, Kernel, Event};
 use OpenCL::mem::CLBuffer;
 use OpenCL::CL::{CL_MEM_READ_ONLY};
 
-use cow::btree::{BTreeMap, BTreeMapIterator};
+use cow::btree::{BTreeMap, BTreeMapIterator, BTreeSet};
 
-use snowmew::common::{ObjectKey, Common};
+use snowmew::common::{ObjectKey, Common, Remap};
+use snowmew::snapshot::{Snapshot, invalid};
+use snowmew::diff::{Diff, diff_by, Removed, Modified};
+use snowmew::merge::{Policy, LastWriterWins};
+use snowmew::replicate::{Replicate, write_map_delta_hidden, read_map_delta};
+use snowmew::console::{Console, lookup};
+use snowmew::lockstep::{StateHashDelta, table_hash_delta, value_hash_delta};
+use snowmew::stats::{Stats, TableStats, table_stats, vec_stats};
 
 static opencl_program: &'static str = include_str!("position.c");
 
@@ -51,6 +61,31 @@ impl Clone for Delta {
     }
 }
 
+impl PartialEq for Delta {
+    fn eq(&self, other: &Delta) -> bool {
+        self.parent == other.parent &&
+        self.delta.scale == other.delta.scale &&
+        self.delta.rot == other.delta.rot &&
+        self.delta.disp == other.delta.disp
+    }
+}
+
+impl Snapshot for Delta {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.delta.save(w));
+        self.parent.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Delta> {
+        let delta = try!(Snapshot::load(r));
+        let parent = try!(Snapshot::load(r));
+        Ok(Delta {
+            delta: delta,
+            parent: parent
+        })
+    }
+}
+
 pub trait MatrixManager {
     fn set(&mut self, idx: uint, mat: Matrix4<f32>);
     fn get(&self, idx: uint) -> Matrix4<f32>;
@@ -65,11 +100,42 @@ impl<'r> MatrixManager for &'r mut [Matrix4<f32>] {
 pub struct Deltas {
     gen: Vec<(u32, u32)>,
     delta: BTreeMap<u32, BTreeMap<u32, Delta>>,
+    // slots freed by remove, counted as they are freed
+    holes: uint
 }
 
 #[deriving(Clone, Default, Eq, PartialOrd, PartialEq, Ord)]
 pub struct Id(u32, u32);
 
+impl Snapshot for Id {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        let Id(gen, offset) = *self;
+        try!(gen.save(w));
+        offset.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Id> {
+        let gen = try!(Snapshot::load(r));
+        let offset = try!(Snapshot::load(r));
+        Ok(Id(gen, offset))
+    }
+}
+
+// the generation layout is stored as is so the Ids in
+// PositionData stay valid after a load
+impl Snapshot for Deltas {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.gen.save(w));
+        self.delta.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Deltas> {
+        let gen = try!(Snapshot::load(r));
+        let delta = try!(Snapshot::load(r));
+        Ok(Deltas::from_parts(gen, delta))
+    }
+}
+
 impl Deltas {
     pub fn new() -> Deltas {
         let mut b = BTreeMap::new();
@@ -79,9 +145,20 @@ impl Deltas {
         Deltas {
             gen: vec!((0, 1)),
             delta: b,
+            holes: 0
         }
     }
 
+    fn from_parts(gen: Vec<(u32, u32)>, delta: BTreeMap<u32, BTreeMap<u32, Delta>>) -> Deltas {
+        let mut deltas = Deltas {
+            gen: gen,
+            delta: delta,
+            holes: 0
+        };
+        deltas.holes = deltas.size() - deltas.delta.iter().map(|(_, gen)| gen.len()).sum();
+        deltas
+    }
+
     pub fn root() -> Id { Id(0, 0) }
 
     pub fn get_loc(&self, id: Id) -> uint {
@@ -91,6 +168,15 @@ impl Deltas {
         (gen_offset + offset) as uint
     }
 
+    /// the number of slots, including the holes left by `remove`
+    pub fn size(&self) -> uint {
+        let (s, l) = *self.gen.get(self.gen.len()-1);
+        (s + l) as uint
+    }
+
+    /// slots freed by `remove` that are still counted in the generations
+    pub fn holes(&self) -> uint { self.holes }
+
     fn add_location(&mut self, gen: u32) -> u32 {
         // create a new generation if this is the first in it
         if gen as uint == self.gen.len() {
@@ -133,6 +219,90 @@ impl Deltas {
         Id(gen+1, id)
     }
 
+    /// Removes id and every slot below it, returns the removed Ids in
+    /// order. The slots stay as holes until `compact`, the root can not
+    /// be removed.
+    pub fn remove(&mut self, id: Id) -> Vec<Id> {
+        let Id(gen, offset) = id;
+        if gen == 0 || !self.delta.find_mut(&gen).map_or(false, |slots| slots.remove(&offset)) {
+            return Vec::new();
+        }
+
+        let mut removed = vec!(id);
+        let mut parents = vec!(offset);
+        let mut gen = gen + 1;
+        while parents.len() != 0 {
+            let slots = match self.delta.find_mut(&gen) {
+                Some(slots) => slots,
+                None => break
+            };
+            let children: Vec<u32> = slots.iter()
+                                          .filter(|&(_, d)| parents.as_slice().bsearch_elem(&d.parent).is_some())
+                                          .map(|(off, _)| *off).collect();
+            for off in children.iter() {
+                slots.remove(off);
+                removed.push(Id(gen, *off));
+            }
+            parents = children;
+            gen += 1;
+        }
+        self.holes += removed.len();
+        removed
+    }
+
+    /// Moves the slots of every generation down over the holes and drops
+    /// the generations left empty. Returns the new Id of every slot that
+    /// moved, generations without holes before them are left shared.
+    pub fn compact(&mut self) -> TreeMap<Id, Id> {
+        let mut moved = TreeMap::new();
+        let mut gens = Vec::new();
+        let mut delta = BTreeMap::new();
+        // the new offsets in the generation before, None if none moved
+        let mut parents: Option<TreeMap<u32, u32>> = None;
+        let mut start = 0;
+
+        for (&gen, slots) in self.delta.iter() {
+            // every generation after an empty one is empty too
+            if gen != 0 && slots.len() == 0 {
+                break;
+            }
+
+            let (_, len) = *self.gen.get(gen as uint);
+            let full = slots.len() as u32 == len;
+            let new_slots = if full && parents.is_none() {
+                slots.clone()
+            } else {
+                let mut offsets = TreeMap::new();
+                let mut new_slots = BTreeMap::new();
+                for (idx, (&off, d)) in slots.iter().enumerate() {
+                    let idx = idx as u32;
+                    let mut d = d.clone();
+                    match parents {
+                        Some(ref p) => d.parent = *p.find(&d.parent).expect("slot without a parent"),
+                        None => ()
+                    }
+                    new_slots.insert(idx, d);
+                    offsets.insert(off, idx);
+                    if idx != off {
+                        moved.insert(Id(gen, off), Id(gen, idx));
+                    }
+                }
+                parents = if full { None } else { Some(offsets) };
+                new_slots
+            };
+
+            let len = new_slots.len() as u32;
+            gens.push((start, len));
+            delta.insert(gen, new_slots);
+            start += len;
+        }
+
+        self.gen = gens;
+        self.delta = delta;
+        self.holes = 0;
+        moved
+    }
+
     pub fn get_mut<'a>(&'a mut self, id: Id) -> &'a mut Decomposed<f32, Vector3<f32>, Quaternion<f32>> {
         let Id(gen, id) = id;
         &mut self.delta.find_mut(&gen).unwrap().find_mut(&id).unwrap().delta
@@ -162,6 +332,38 @@ impl Deltas {
         }
     }
 
+    /// the number of slots of every generation, including holes
+    pub fn generations(&self) -> Vec<u32> {
+        self.gen.iter().map(|&(_, len)| len).collect()
+    }
+
+    /// Writes the slots of generation gen from start up to end, the
+    /// generation before has to be written already. The ranges of one
+    /// generation only read the one before, so they can be written at
+    /// the same time.
+    pub fn write_generation<MM: MatrixManager>(&self, gen: uint, start: u32, end: u32, mm: &mut MM) {
+        let slots = match self.delta.find(&(gen as u32)) {
+            Some(slots) => slots,
+            None => return
+        };
+        let (gen_off, _) = *self.gen.get(gen);
+        let last_gen_off = if gen == 0 { 0 } else { let (off, _) = *self.gen.get(gen-1); off };
+
+        for off in range(start, end) {
+            match slots.find(&off) {
+                Some(delta) => {
+                    let parent = if gen == 0 {
+                        Matrix4::identity()
+                    } else {
+                        mm.get((last_gen_off + delta.parent) as uint)
+                    };
+                    mm.set((gen_off + off) as uint, parent.mul_m(&delta.delta.to_matrix4()));
+                }
+                None => ()
+            }
+        }
+    }
+
     #[inline(never)]
     pub fn write_positions<MM: MatrixManager>(&self, mm: &mut MM) {
         let mut last_gen_off = 0;
@@ -177,18 +379,23 @@ impl Deltas {
         }
     }
 
-    pub fn write_positions_cl_vec4x4(&self, cq: &CommandQueue, ctx: &mut CalcPositionsCl,
-                           out: &[CLBuffer<Vector4<f32>>, ..4]) -> Event {
-
-        let last = self.gen.len();
-        let (s, l) = *self.gen.get(last-1);
-        let size = s + l;
+    fn fill_buffers(&self, ctx: &mut CalcPositionsCl) {
+        let size = self.size();
 
         unsafe {
-            ctx.parent_buffer.reserve(size as uint);
-            ctx.parent_buffer.set_len(size as uint);
-            ctx.input_buffer.reserve(size as uint);
-            ctx.input_buffer.set_len(size as uint);
+            ctx.parent_buffer.reserve(size);
+            ctx.parent_buffer.set_len(size);
+            ctx.input_buffer.reserve(size);
+            ctx.input_buffer.set_len(size);
+        }
+
+        // the kernels run over every slot of a generation, holes
+        // need a parent that is in range
+        if self.holes() != 0 {
+            for idx in range(0, size) {
+                *ctx.input_buffer.get_mut(idx) = Transform::identity();
+                *ctx.parent_buffer.get_mut(idx) = 0;
+            }
         }
 
         for (&(gen_off, _), (_, gen)) in self.gen.iter().zip(self.delta.iter()) {
@@ -197,6 +404,12 @@ impl Deltas {
                 *ctx.parent_buffer.get_mut((off + gen_off) as uint) = delta.parent.clone();
             }
         }
+    }
+
+    pub fn write_positions_cl_vec4x4(&self, cq: &CommandQueue, ctx: &mut CalcPositionsCl,
+                           out: &[CLBuffer<Vector4<f32>>, ..4]) -> Event {
+
+        self.fill_buffers(ctx);
 
         let events = &[cq.write_async(&ctx.input, &ctx.input_buffer.as_slice(), ()),
                        cq.write_async(&ctx.parent, &ctx.parent_buffer.as_slice(), ())];
@@ -229,23 +442,7 @@ impl Deltas {
     pub fn write_positions_cl_mat4(&self, cq: &CommandQueue, ctx: &mut CalcPositionsCl,
                            out: &[CLBuffer<Matrix4<f32>>]) -> Event {
 
-        let last = self.gen.len();
-        let (s, l) = *self.gen.get(last-1);
-        let size = s + l;
-
-        unsafe {
-            ctx.parent_buffer.reserve(size as uint);
-            ctx.parent_buffer.set_len(size as uint);
-            ctx.input_buffer.reserve(size as uint);
-            ctx.input_buffer.set_len(size as uint);
-        }
-
-        for (&(gen_off, _), (_, gen)) in self.gen.iter().zip(self.delta.iter()) {
-            for (off, delta) in gen.iter() {
-                *ctx.input_buffer.get_mut((off + gen_off) as uint) = delta.delta;
-                *ctx.parent_buffer.get_mut((off + gen_off) as uint) = delta.parent.clone();
-            }
-        }
+        self.fill_buffers(ctx);
 
         let events = &[cq.write_async(&ctx.input, &ctx.input_buffer.as_slice(), ()),
                        cq.write_async(&ctx.parent, &ctx.parent_buffer.as_slice(), ())];
@@ -369,19 +566,201 @@ impl CalcPositionsCl {
     }
 }
 
+fn same_location(a: &Option<Decomposed<f32, Vector3<f32>, Quaternion<f32>>>,
+                 b: &Option<Decomposed<f32, Vector3<f32>, Quaternion<f32>>>) -> bool {
+    match (a, b) {
+        (&Some(ref a), &Some(ref b)) => a.scale == b.scale && a.rot == b.rot && a.disp == b.disp,
+        (&None, &None) => true,
+        _ => false
+    }
+}
+
 #[deriving(Clone)]
 pub struct PositionData {
     location: BTreeMap<ObjectKey, Id>,
-    position: Deltas
+    position: Deltas,
+    policy: Policy<Decomposed<f32, Vector3<f32>, Quaternion<f32>>>
 }
 
 impl PositionData {
     pub fn new() -> PositionData {
         PositionData {
             location: BTreeMap::new(),
-            position: Deltas::new()
+            position: Deltas::new(),
+            policy: LastWriterWins
+        }
+    }
+
+    /// Removes the location of key and the locations of the objects
+    /// below it. The deltas are compacted once half their slots are holes.
+    /// Finding the objects below key walks every location, removing
+    /// leaves first as `delete_subtree` does never needs to.
+    pub fn remove(&mut self, key: ObjectKey) -> bool {
+        let id = match self.location.find(&key) {
+            Some(id) => *id,
+            None => return false
+        };
+        self.location.remove(&key);
+
+        let removed = self.position.remove(id);
+        if removed.len() > 1 {
+            let below: Vec<ObjectKey> = self.location.iter()
+                                            .filter(|&(_, id)| removed.as_slice().bsearch_elem(id).is_some())
+                                            .map(|(key, _)| *key).collect();
+            for key in below.iter() {
+                self.location.remove(key);
+            }
+        }
+
+        if self.position.holes() * 2 > self.position.size() {
+            self.compact();
+        }
+        true
+    }
+
+    /// moves the deltas over the holes left by `remove`, this changes
+    /// the Ids and the matrix index of the locations
+    pub fn compact(&mut self) {
+        let moved = self.position.compact();
+        if moved.len() == 0 {
+            return;
+        }
+        let changed: Vec<(ObjectKey, Id)> = self.location.iter()
+                                                .filter_map(|(key, id)| moved.find(id).map(|id| (*key, *id)))
+                                                .collect();
+        for &(key, id) in changed.iter() {
+            self.location.insert(key, id);
+        }
+    }
+
+    /// Objects whose location was added, removed or changed since old
+    pub fn diff<'a>(&'a self, old: &'a PositionData) -> Diff<'a, ObjectKey, Id> {
+        diff_by(&old.location, &self.location, |a, b| {
+            let (a, b) = (old.position.get_delta(*a), self.position.get_delta(*b));
+            a.scale == b.scale && a.rot == b.rot && a.disp == b.disp
+        })
+    }
+}
+
+impl Snapshot for PositionData {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.location.save(w));
+        self.position.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<PositionData> {
+        let location = try!(Snapshot::load(r));
+        let position = try!(Snapshot::load(r));
+        Ok(PositionData {
+            location: location,
+            position: position,
+            // merge policies belong to the running game
+            policy: LastWriterWins
+        })
+    }
+}
+
+// the slots of the hidden objects, by generation
+fn hidden_slots(location: &BTreeMap<ObjectKey, Id>, hidden: &BTreeSet<ObjectKey>) -> TreeMap<u32, BTreeSet<u32>> {
+    let mut slots = TreeMap::new();
+    for key in hidden.iter() {
+        match location.find(key) {
+            Some(&Id(gen, idx)) => {
+                if slots.find(&gen).is_none() {
+                    slots.insert(gen, BTreeSet::new());
+                }
+                slots.find_mut(&gen).unwrap().insert(idx);
+            }
+            None => ()
         }
     }
+    slots
+}
+
+/// Each generation of slots is sent as a table of its own, the slots
+/// of hidden objects are left out.
+impl Replicate for PositionData {
+    fn write_delta(&self, old: &PositionData, old_hidden: &BTreeSet<ObjectKey>, hidden: &BTreeSet<ObjectKey>,
+                   w: &mut Writer) -> IoResult<()> {
+        let old_slots_hidden = hidden_slots(&old.location, old_hidden);
+        let slots_hidden = hidden_slots(&self.location, hidden);
+
+        try!(write_map_delta_hidden(&old.location, &self.location, |a, b| a == b, old_hidden, hidden, w));
+        try!(self.position.gen.save(w));
+
+        let empty = BTreeMap::new();
+        let none = BTreeSet::new();
+        try!(w.write_le_u32(self.position.delta.len() as u32));
+        for (gen, slots) in self.position.delta.iter() {
+            let old_slots = old.position.delta.find(gen).unwrap_or(&empty);
+            try!(gen.save(w));
+            try!(write_map_delta_hidden(old_slots, slots, |a, b| a == b,
+                                        old_slots_hidden.find(gen).unwrap_or(&none),
+                                        slots_hidden.find(gen).unwrap_or(&none), w));
+        }
+        Ok(())
+    }
+
+    fn read_delta(&self, r: &mut Reader) -> IoResult<PositionData> {
+        let location = try!(read_map_delta(&self.location, r));
+        let gen: Vec<(u32, u32)> = try!(Snapshot::load(r));
+
+        let empty = BTreeMap::new();
+        let mut delta = BTreeMap::new();
+        let len = try!(r.read_le_u32());
+        for _ in range(0, len) {
+            let g: u32 = try!(Snapshot::load(r));
+            let slots = try!(read_map_delta(self.position.delta.find(&g).unwrap_or(&empty), r));
+            delta.insert(g, slots);
+        }
+        if delta.len() != gen.len() {
+            return Err(invalid("position generations do not match their slots"));
+        }
+
+        Ok(PositionData {
+            location: location,
+            position: Deltas::from_parts(gen, delta),
+            policy: self.policy.clone()
+        })
+    }
+}
+
+/// Each generation of slots is hashed as a table of its own
+impl StateHashDelta for PositionData {
+    fn state_hash_delta(&self, old: &PositionData) -> u64 {
+        let mut delta = table_hash_delta("location", &old.location, &self.location, |a, b| a == b) +
+                        value_hash_delta("generations", &old.position.gen, &self.position.gen);
+
+        let empty = BTreeMap::new();
+        for (gen, slots) in self.position.delta.iter() {
+            let old_slots = old.position.delta.find(gen).unwrap_or(&empty);
+            delta += table_hash_delta(format!("deltas/{}", gen).as_slice(), old_slots, slots, |a, b| a == b);
+        }
+        for (gen, slots) in old.position.delta.iter() {
+            if self.position.delta.find(gen).is_none() {
+                delta += table_hash_delta(format!("deltas/{}", gen).as_slice(), slots, &empty, |a, b| a == b);
+            }
+        }
+        delta
+    }
+}
+
+impl Stats for PositionData {
+    fn stats(&self, other: Option<&PositionData>) -> Vec<TableStats> {
+        let mut deltas = TableStats::new("deltas");
+        for (gen, table) in self.position.delta.iter() {
+            let old = other.and_then(|o| o.position.delta.find(gen));
+            deltas.add(&table_stats("deltas", table, old));
+        }
+        // a generation is its (offset, len) and its entry in the map
+        // of delta tables
+        let mut generations = table_stats("generations", &self.position.delta,
+                                          other.map(|o| &o.position.delta));
+        generations.bytes += vec_stats("generations", &self.position.gen, |_| 0).bytes;
+        vec!(table_stats("location", &self.location, other.map(|o| &o.location)),
+             generations,
+             deltas)
+    }
 }
 
 pub trait Positions: Common {
@@ -437,6 +816,20 @@ pub trait Positions: Common {
         }
     }
 
+    /// the location between the one in last and self, alpha is
+    /// from FrameInfo
+    fn interpolated_location(&self, last: &Self, key: ObjectKey, alpha: f32)
+            -> Option<Decomposed<f32, Vector3<f32>, Quaternion<f32>>> {
+        match (last.location(key), self.location(key)) {
+            (Some(a), Some(b)) => Some(Decomposed {
+                scale: a.scale + (b.scale - a.scale) * alpha,
+                rot: a.rot.nlerp(&b.rot, alpha),
+                disp: a.disp.add_v(&b.disp.sub_v(&a.disp).mul_s(alpha))
+            }),
+            (_, b) => b
+        }
+    }
+
     fn position(&self, oid: ObjectKey) -> Matrix4<f32> {
         let obj = self.object(oid);
         let p_mat = match obj {
@@ -455,6 +848,14 @@ pub trait Positions: Common {
         self.get_position().position.write_positions(mm)
     }
 
+    fn position_generations(&self) -> Vec<u32> {
+        self.get_position().position.generations()
+    }
+
+    fn write_position_generation<MM: MatrixManager>(&self, gen: uint, start: u32, end: u32, mm: &mut MM) {
+        self.get_position().position.write_generation(gen, start, end, mm)
+    }
+
     fn write_positions_cl_vec4x4(&self, cq: &CommandQueue,
                         ctx: &mut CalcPositionsCl, out: &[CLBuffer<Vector4<f32>>, ..4]) -> Event {
         self.get_position().position.write_positions_cl_vec4x4(cq, ctx, out)
@@ -469,13 +870,128 @@ pub trait Positions: Common {
         self.get_position().position.compute_positions()
     }
 
+    // the locations of the objects below key are removed with it
+    fn delete_position(&mut self, key: ObjectKey) {
+        self.get_position_mut().remove(key);
+    }
+
+    /// frees the holes left by deleted positions, the matrix index of
+    /// the locations changes
+    fn compact_positions(&mut self) {
+        self.get_position_mut().compact();
+    }
+
+    // the object was moved in the tree, the deltas of it and its
+    // children are moved to the matching generation
+    fn reparent_position(&mut self, key: ObjectKey) {
+        let subtree = self.subtree(key);
+        let moved: Vec<(ObjectKey, Decomposed<f32, Vector3<f32>, Quaternion<f32>>)> =
+            subtree.iter().filter_map(|k| self.location(*k).map(|d| (*k, d))).collect();
+        // children first, so each one removed is a leaf
+        for &(k, _) in moved.iter().rev() {
+            self.get_position_mut().remove(k);
+        }
+        for &(k, delta) in moved.iter() {
+            self.update_location(k, delta);
+        }
+    }
+
+    // copies the locations of an instantiated template
+    fn copy_positions(&mut self, remap: &Remap) {
+        for (old, new) in remap.iter() {
+            match self.location(*old) {
+                Some(delta) => self.update_location(*new, delta),
+                None => ()
+            }
+        }
+    }
+
+    /// Sets how a location that was changed by both sides of a merge
+    /// is resolved, LastWriterWins by default
+    fn set_position_merge_policy(&mut self, policy: Policy<Decomposed<f32, Vector3<f32>, Quaternion<f32>>>) {
+        self.get_position_mut().policy = policy;
+    }
+
+    /// Merges the locations of ours and theirs, two generations with
+    /// base as their parent. self must already hold the merged objects.
+    /// A location that was changed by both is resolved by the policy
+    /// of ours.
+    fn merge_positions(&mut self, base: &Self, ours: &Self, theirs: &Self) {
+        *self.get_position_mut() = ours.get_position().clone();
+        let policy = ours.get_position().policy.clone();
+
+        // objects theirs removed or moved in the tree
+        let tree: Vec<(ObjectKey, bool)> = self.get_common().diff(ours.get_common()).filter_map(|c| {
+            match c {
+                Removed(key) => Some((key, false)),
+                Modified(key) => Some((key, true)),
+                _ => None
+            }
+        }).collect();
+        for &(key, alive) in tree.iter() {
+            if !alive {
+                self.delete_position(key);
+            } else if self.object(key).map(|o| o.parent) != ours.object(key).map(|o| o.parent) {
+                self.reparent_position(key);
+            }
+        }
+
+        let changed: Vec<ObjectKey> = theirs.get_position().diff(base.get_position())
+                                            .map(|c| c.key()).collect();
+        for key in changed.iter() {
+            let (b, o, t) = (base.location(*key), ours.location(*key), theirs.location(*key));
+            if same_location(&o, &t) {
+                continue;
+            }
+            let value = if same_location(&b, &o) {
+                t
+            } else {
+                policy.resolve(b.as_ref(), o.as_ref(), t.as_ref())
+            };
+            match value {
+                Some(v) if self.object(*key).is_some() => self.update_location(*key, v),
+                _ => self.delete_position(*key)
+            }
+        }
+    }
+
     fn location_iter<'a>(&'a self) -> BTreeMapIterator<'a, ObjectKey, Id> {
         self.get_position().location.iter()
     }
 
+    fn location_table<'a>(&'a self) -> &'a BTreeMap<ObjectKey, Id> {
+        &self.get_position().location
+    }
+
     fn position_count(&self) -> uint {
-        let last = self.get_position().position.gen.len();
-        let (s, l) = *self.get_position().position.gen.get(last-1);
-        (s + l) as uint
+        self.get_position().position.size()
     }
 }
+
+fn set_position<GD: Positions>(gd: &mut GD, args: &[&str]) -> Result<String, String> {
+    if args.len() != 4 {
+        return Err("usage: set position <path> x y z".to_string());
+    }
+    let key = try!(lookup(gd, args[0]));
+    let mut v = [0f32, ..3];
+    for (i, arg) in args.slice_from(1).iter().enumerate() {
+        v[i] = match from_str(*arg) {
+            Some(f) => f,
+            None => return Err(format!("{} is not a number", arg))
+        };
+    }
+    gd.set_displacement(key, Vector3::new(v[0], v[1], v[2]));
+    Ok(String::new())
+}
+
+fn inspect_position<GD: Positions>(gd: &GD, key: ObjectKey) -> Option<String> {
+    gd.location(key).map(|loc| {
+        format!("position {} rotation {} scale {}", loc.disp, loc.rot, loc.scale)
+    })
+}
+
+/// adds `set position` to the console and the location to `inspect`
+pub fn console_commands<GD: Positions>(console: &mut Console<GD>) {
+    console.register("set position", "set position <path> x y z, moves path", set_position::<GD>);
+    console.register_inspector(inspect_position::<GD>);
+}
@@ -8,16 +8,53 @@ extern crate OpenCL;
 extern crate cow;
 extern crate position = "snowmew-position";
 
-use position::Deltas;
+use std::io::{MemWriter, MemReader};
+
+use snowmew::snapshot::Snapshot;
+use snowmew::ObjectKey;
+use snowmew::common::{Common, CommonData, Remap};
+use snowmew::lockstep::{StateHash, StateHashDelta};
+
+use position::{Deltas, Positions, PositionData};
 use position::CalcPositionsCl;
 
 use cgmath::matrix::{Matrix4, Matrix};
-use cgmath::transform::Decomposed;
+use cgmath::transform::{Transform, Decomposed};
 use cgmath::quaternion::Quaternion;
 use cgmath::vector::{Vector3, Vector4};
 
 use OpenCL::hl::EventList;
 
+#[deriving(Clone)]
+struct TestData {
+    common: CommonData,
+    position: PositionData
+}
+
+impl Common for TestData {
+    fn get_common<'a>(&'a self) -> &'a CommonData { &self.common }
+    fn get_common_mut<'a>(&'a mut self) -> &'a mut CommonData { &mut self.common }
+
+    fn delete_data(&mut self, oid: ObjectKey) {
+        self.delete_position(oid);
+    }
+
+    fn copy_data(&mut self, remap: &Remap) {
+        self.copy_positions(remap);
+    }
+}
+
+impl Positions for TestData {
+    fn get_position<'a>(&'a self) -> &'a PositionData { &self.position }
+    fn get_position_mut<'a>(&'a mut self) -> &'a mut PositionData { &mut self.position }
+}
+
+impl StateHashDelta for TestData {
+    fn state_hash_delta(&self, old: &TestData) -> u64 {
+        self.common.state_hash_delta(&old.common) + self.position.state_hash_delta(&old.position)
+    }
+}
+
 #[test]
 fn insert_children() {
     let mut pos = Deltas::new();
@@ -129,6 +166,156 @@ fn to_positions_tree() {
     assert!(mat3.mul_v(&vec) == Vector4::new(-2f32, -2f32, -2f32, 1f32));
 }
 
+#[test]
+fn write_generations() {
+    let mut pos = Deltas::new();
+    let mut all: &mut [Matrix4<f32>] = &mut [Matrix4::identity(), ..8];
+    let mut split: &mut [Matrix4<f32>] = &mut [Matrix4::identity(), ..8];
+
+    let id0 = pos.insert(Deltas::root(), Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(1f32, 1f32, 1f32)});
+    let id1 = pos.insert(Deltas::root(), Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(-1f32, -1f32, -1f32)});
+    pos.insert(id0, Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(1f32, 1f32, 1f32)});
+    pos.insert(id1, Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(1f32, 2f32, 3f32)});
+    pos.insert(id1, Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(-1f32, -1f32, -1f32)});
+
+    // every generation written in two ranges, the last one first
+    pos.write_positions(&mut all);
+    for (gen, len) in pos.generations().iter().enumerate() {
+        pos.write_generation(gen, len / 2, *len, &mut split);
+        pos.write_generation(gen, 0, len / 2, &mut split);
+    }
+
+    for idx in range(0u, 6) {
+        assert!(all[idx] == split[idx]);
+    }
+}
+
+#[test]
+fn remove_compact() {
+    let mut pos = Deltas::new();
+    let mut vec: &mut [Matrix4<f32>] = &mut [Matrix4::identity(), Matrix4::identity(), Matrix4::identity(), Matrix4::identity(),
+                                             Matrix4::identity(), Matrix4::identity(), Matrix4::identity(), Matrix4::identity()];
+
+    let id0 = pos.insert(Deltas::root(), Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(1f32, 1f32, 1f32)});
+    let id1 = pos.insert(Deltas::root(), Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(-1f32, -1f32, -1f32)});
+
+    let id0_0 = pos.insert(id0, Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(1f32, 1f32, 1f32)});
+    let id1_0 = pos.insert(id1, Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(1f32, 1f32, 1f32)});
+    let id1_1 = pos.insert(id1, Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(-1f32, -1f32, -1f32)});
+
+    assert!(pos.remove(id0) == vec!(id0, id0_0));
+    assert!(pos.holes() == 2);
+    assert!(pos.remove(Deltas::root()).len() == 0);
+
+    let moved = pos.compact();
+    assert!(pos.holes() == 0);
+    assert!(pos.size() == 4);
+
+    let id1 = *moved.find(&id1).unwrap();
+    let id1_0 = *moved.find(&id1_0).unwrap();
+    let id1_1 = *moved.find(&id1_1).unwrap();
+
+    pos.write_positions(&mut vec);
+    let pos = pos.compute_positions();
+
+    let v = Vector4::new(0f32, 0f32, 0f32, 1f32);
+    assert!(vec[pos.get_loc(id1)].mul_v(&v) == Vector4::new(-1f32, -1f32, -1f32, 1f32));
+    assert!(vec[pos.get_loc(id1_0)].mul_v(&v) == Vector4::new(0f32, 0f32, 0f32, 1f32));
+    assert!(vec[pos.get_loc(id1_1)].mul_v(&v) == Vector4::new(-2f32, -2f32, -2f32, 1f32));
+}
+
+#[test]
+fn remove_locations() {
+    let mut db = TestData {
+        common: CommonData::new(),
+        position: PositionData::new()
+    };
+    let scene = db.new_scene("scene");
+    let mut keys = Vec::new();
+    for i in range(0u, 4) {
+        let key = db.new_object(Some(scene), format!("{}", i).as_slice());
+        let mut t: Decomposed<f32, Vector3<f32>, Quaternion<f32>> = Transform::identity();
+        t.disp = Vector3::new(i as f32, 0f32, 0f32);
+        db.update_location(key, t);
+        keys.push(key);
+    }
+    assert!(db.position_count() == 5);
+
+    // the third removal leaves more holes than slots, the last
+    // location moves to the front
+    for key in keys.slice_to(3).iter() {
+        assert!(db.get_position_mut().remove(*key));
+    }
+    assert!(!db.get_position_mut().remove(*keys.get(0)));
+    assert!(db.position_count() == 2);
+
+    let last = *keys.get(3);
+    let id = *db.location_table().find(&last).unwrap();
+    assert!(db.compute_positions().get_loc(id) == 1);
+    assert!(db.location(last).unwrap().disp == Vector3::new(3f32, 0f32, 0f32));
+    for key in keys.slice_to(3).iter() {
+        assert!(db.location(*key).is_none());
+    }
+}
+
+#[test]
+fn state_hash_positions() {
+    let empty = TestData {
+        common: CommonData::new(),
+        position: PositionData::new()
+    };
+    let mut db = empty.clone();
+    let scene = db.new_scene("scene");
+    let a = db.new_object(Some(scene), "a");
+    db.set_displacement(a, Vector3::new(1f32, 0f32, 0f32));
+    let mut hash = StateHash::new(empty.clone());
+    let last = hash.update(&db);
+
+    // only the position differs between the peers
+    let mut peer = db.clone();
+    peer.set_displacement(a, Vector3::new(2f32, 0f32, 0f32));
+    assert!(hash.update(&peer) != last);
+    assert!(hash.update(&peer) == StateHash::new(empty.clone()).update(&peer));
+    assert!(hash.update(&db) == last);
+}
+
+#[test]
+fn position_stats() {
+    use snowmew::stats::Stats;
+
+    let mut db = TestData {
+        common: CommonData::new(),
+        position: PositionData::new()
+    };
+    let a = db.new_object(None, "a");
+    db.set_scale(a, 2f32);
+
+    // every generation is counted, the root one included
+    let stats = db.position.stats(None);
+    let generations = stats.iter().find(|t| t.name.as_slice() == "generations").unwrap();
+    assert!(generations.entries == db.position_generations().len());
+    assert!(generations.bytes > 0);
+}
+
+#[test]
+fn snapshot_deltas() {
+    let mut pos = Deltas::new();
+
+    let id0 = pos.insert(Deltas::root(), Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(1f32, 1f32, 1f32)});
+    let id1 = pos.insert(Deltas::root(), Decomposed{scale: 2f32, rot: Quaternion::identity(), disp: Vector3::new(-1f32, -1f32, -1f32)});
+    let id0_0 = pos.insert(id0, Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(1f32, 1f32, 1f32)});
+
+    let mut w = MemWriter::new();
+    pos.save(&mut w).unwrap();
+    let mut r = MemReader::new(w.unwrap());
+    let loaded: Deltas = Snapshot::load(&mut r).unwrap();
+
+    assert!(pos.get_mat(id0) == loaded.get_mat(id0));
+    assert!(pos.get_mat(id1) == loaded.get_mat(id1));
+    assert!(pos.get_mat(id0_0) == loaded.get_mat(id0_0));
+    assert!(pos.get_loc(id0_0) == loaded.get_loc(id0_0));
+}
+
 fn fetch_matrixs(queue: &OpenCL::hl::CommandQueue,
                  buffers: &[OpenCL::mem::CLBuffer<Vector4<f32>>, ..4]) -> Vec<Matrix4<f32>> {
 
@@ -29,13 +29,13 @@ use sync::Arc;
 
 use position::Positions;
 use graphics::Graphics;
-use snowmew::common::ObjectKey;
+use snowmew::common::{ObjectKey, matches_tags};
 use snowmew::io::Window;
 
 use graphics::geometry::{Vertex, VertexGeo, VertexGeoTex, VertexGeoNorm, VertexGeoTexNorm, VertexGeoTexNormTan};
 use graphics::geometry::{Geo, GeoTex, GeoNorm, GeoTexNorm, GeoTexNormTan};
 
-use cow::join::{join_set_to_map, join_maps};
+use snowmew::query::query;
 
 static VERTEX_SRC: &'static [u8] = b"
     #version 150 core
@@ -217,6 +217,7 @@ impl RenderManager {
         };
         self.client.clear(cdata, None);
 
+        let layers = db.camera_layers(camera);
         let camera_trans = db.position(camera);
         let camera = snowmew::camera::Camera::new(camera_trans);
 
@@ -244,9 +245,10 @@ impl RenderManager {
             )
         );
 
-        for (id, (draw, pos)) in join_set_to_map(db.scene_iter(scene),
-                                                 join_maps(db.drawable_iter(),
-                                                           db.location_iter())) {
+        for (id, (draw, pos)) in query(db.drawable_iter())
+                                    .join(db.location_iter())
+                                    .in_scene(db, scene)
+                                    .filter(|&(id, _)| matches_tags(db.tags(*id), layers)) {
 
             let geo = db.geometry(draw.geometry).expect("failed to find geometry");
             let mat = db.material(draw.material).expect("Could not find material");
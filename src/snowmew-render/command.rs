@@ -3,9 +3,6 @@ use std::ptr;
 use std::mem;
 use std::slice::raw::mut_buf_as_slice;
 
-use cow::join::{join_set_to_map};
-
-
 use libc::c_void;
 
 use gl;
@@ -17,7 +14,9 @@ use cgmath::vector::{Vector, EuclideanVector};
 
 use config::Config;
 use graphics::Graphics;
-use snowmew::common::ObjectKey;
+use snowmew::common::{ObjectKey, Tags};
+use RenderData;
+use model::scene_models;
 
 
 use db::GlState;
@@ -103,7 +102,7 @@ impl CommandBufferIndirect {
         gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
     }
 
-    pub fn build<GD: Graphics>(&mut self, db: &GD, scene: ObjectKey, instanced_is_enabled: bool) {
+    pub fn build(&mut self, db: &RenderData, scene: ObjectKey, layers: Tags, instanced_is_enabled: bool) {
         let mut batch = Batch {
             vbo: 0,
             offset: 0,
@@ -123,7 +122,7 @@ impl CommandBufferIndirect {
         unsafe {
             self.batches.truncate(0);
             mut_buf_as_slice(self.ptr, self.size, |b| {
-                for (count, (_, draw)) in join_set_to_map(db.scene_iter(scene), db.drawable_iter()).enumerate() {
+                for (count, &(_, draw, _)) in scene_models(db, scene, layers).iter().enumerate() {
                     if idx == -1 {
                         let draw_geo = db.geometry(draw.geometry).expect("geometry not found");
                         last_geo = Some(draw.geometry);
@@ -238,7 +237,7 @@ impl CommandBufferEmulated {
     pub fn map(&mut self) {}
     pub fn unmap(&mut self) {}
 
-    pub fn build<GD: Graphics>(&mut self, db: &GD, scene: ObjectKey, instanced_is_enabled: bool) {
+    pub fn build(&mut self, db: &RenderData, scene: ObjectKey, layers: Tags, instanced_is_enabled: bool) {
         let mut batch = Batch {
             vbo: 0,
             offset: 0,
@@ -247,7 +246,7 @@ impl CommandBufferEmulated {
 
         self.batches.truncate(0);
         self.commands.truncate(0);
-        for (count, (_, draw)) in join_set_to_map(db.scene_iter(scene), db.drawable_iter()).enumerate() {
+        for (count, &(_, draw, _)) in scene_models(db, scene, layers).iter().enumerate() {
             let draw_geo = db.geometry(draw.geometry).expect("geometry not found");
 
             self.commands.push(DrawElementsIndirectCommand {
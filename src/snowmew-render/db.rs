@@ -1,5 +1,6 @@
 use cow::btree::BTreeMap;
-use graphics::{Graphics};
+use graphics::{Graphics, GraphicsData};
+use snowmew::diff::{Added, Modified, Removed};
 use snowmew::common::{Common};
 use snowmew::common::ObjectKey;
 use {RenderData};
@@ -28,7 +29,10 @@ pub struct GlState {
     pub defered_shader_point_light: Option<Shader>,
     pub ovr_shader: Option<Shader>,
     pub compute_cull: Option<Shader>,
-    pub texture: TextureAtlas
+    pub texture: TextureAtlas,
+    // the graphics data the vertex buffers and textures were last
+    // uploaded from
+    uploaded: Option<GraphicsData>
 }
 
 impl GlState {
@@ -41,12 +45,22 @@ impl GlState {
             defered_shader_point_light: None,
             ovr_shader: None,
             compute_cull: None,
-            texture: TextureAtlas::new()
-
+            texture: TextureAtlas::new(),
+            uploaded: None
         }
     }
 
     fn load_textures(&mut self, db: &RenderData, _: &Config) {
+        // slots that hold a replaced texture are uploaded again
+        match self.uploaded {
+            Some(ref old) => {
+                for &(atlas, idx) in db.get_graphics().changed_texture_slots(old).iter() {
+                    self.texture.unload(atlas, idx);
+                }
+            }
+            None => ()
+        }
+
         for (atlas_idx, atlas) in db.texture_atlas_iter().enumerate() {
             for (oid, idx) in atlas.texture_iter() {
                 let texture = db.get_texture(*oid)
@@ -59,6 +73,20 @@ impl GlState {
     fn load_vertex(&mut self, db: &RenderData, _: &Config) {
         let mut vertex = self.vertex.clone();
 
+        // buffers that were replaced, by a reloaded asset for example,
+        // are uploaded again
+        match self.uploaded {
+            Some(ref old) => {
+                for change in db.get_graphics().diff_vertex_buffers(old) {
+                    match change {
+                        Modified(oid) | Removed(oid) => { vertex.remove(&oid); }
+                        Added(_) => ()
+                    }
+                }
+            }
+            None => ()
+        }
+
         for (oid, vbo) in db.vertex_buffer_iter() {
             match vertex.find(oid) {
                 Some(_) => (),
@@ -69,7 +97,7 @@ impl GlState {
             }
         }
 
-        self.vertex = vertex; 
+        self.vertex = vertex;
     }
 
     fn load_shaders(&mut self, _: &RenderData, cfg: &Config) {
@@ -105,5 +133,6 @@ impl GlState {
         self.load_shaders(db, cfg);
         self.load_vertex(db, cfg);
         self.load_textures(db, cfg);
+        self.uploaded = Some(db.get_graphics().clone());
     }
 }
\ No newline at end of file
@@ -11,14 +11,15 @@ use gl;
 
 use position::{Positions, PositionData};
 use graphics::{Graphics, GraphicsData};
-use snowmew::common::{Common, CommonData};
+use snowmew::common::{Common, CommonData, Remap, Tags};
 use snowmew::ObjectKey;
+use snowmew::parallel::split;
 
 use db::GlState;
 use {Config, RenderData};
 use material::MaterialBuffer;
 use light::LightsBuffer;
-use model::{ModelInfoTextureBuffer, ModelInfoSSBOBuffer};
+use model::{ModelInfoTextureBuffer, ModelInfoSSBOBuffer, scene_models};
 use matrix::{MatrixSSBOBuffer, MatrixTextureBuffer};
 use command::{CommandBufferIndirect, CommandBufferEmulated};
 
@@ -31,7 +32,7 @@ pub trait Drawlist: RenderData {
     // data from the scene graph into the any mapped buffers. This can also
     // spawn multiple workers. One of the threads must send the drawlist
     // back to the server
-    fn setup_compute(~self, db: &RenderData, tp: &mut TaskPool<Sender<Box<Drawlist+Send>>>, scene: ObjectKey);
+    fn setup_compute(~self, db: &RenderData, tp: &mut TaskPool<Sender<Box<Drawlist+Send>>>, scene: ObjectKey, layers: Tags);
 
     // setup on the OpenGL thread, this will unmap and sync anything that
     // is needed to be done
@@ -53,6 +54,16 @@ pub trait Drawlist: RenderData {
 impl Common for DrawlistSSBOCompute {
     fn get_common<'a>(&'a self) -> &'a CommonData { &self.data.common }
     fn get_common_mut<'a>(&'a mut self) -> &'a mut CommonData { &mut self.data.common }
+
+    fn delete_data(&mut self, oid: ObjectKey) {
+        self.delete_position(oid);
+        self.delete_graphics(oid);
+    }
+
+    fn copy_data(&mut self, remap: &Remap) {
+        self.copy_positions(remap);
+        self.copy_graphics(remap);
+    }
 }
 
 impl Graphics for DrawlistSSBOCompute {
@@ -70,6 +81,16 @@ impl RenderData for DrawlistSSBOCompute {}
 impl Common for DrawlistNoSSBO {
     fn get_common<'a>(&'a self) -> &'a CommonData { &self.data.common }
     fn get_common_mut<'a>(&'a mut self) -> &'a mut CommonData { &mut self.data.common }
+
+    fn delete_data(&mut self, oid: ObjectKey) {
+        self.delete_position(oid);
+        self.delete_graphics(oid);
+    }
+
+    fn copy_data(&mut self, remap: &Remap) {
+        self.copy_positions(remap);
+        self.copy_graphics(remap);
+    }
 }
 
 impl Graphics for DrawlistNoSSBO {
@@ -94,6 +115,16 @@ struct DrawlistGraphicsData {
 impl Common for DrawlistGraphicsData {
     fn get_common<'a>(&'a self) -> &'a CommonData { &self.common }
     fn get_common_mut<'a>(&'a mut self) -> &'a mut CommonData { &mut self.common }
+
+    fn delete_data(&mut self, oid: ObjectKey) {
+        self.delete_position(oid);
+        self.delete_graphics(oid);
+    }
+
+    fn copy_data(&mut self, remap: &Remap) {
+        self.copy_positions(remap);
+        self.copy_graphics(remap);
+    }
 }
 
 impl Graphics for DrawlistGraphicsData {
@@ -119,7 +150,9 @@ pub struct DrawlistNoSSBO {
 
     size: uint,
     start: f64,
-    instanced_is_enabled: bool
+    instanced_is_enabled: bool,
+    // the model info and matrices are built by this many tasks
+    workers: uint
 }
 
 impl DrawlistNoSSBO {
@@ -139,7 +172,8 @@ impl DrawlistNoSSBO {
             matrix: MatrixTextureBuffer::new(cfg, cl),
             command: CommandBufferEmulated::new(cfg),
             start: 0.,
-            instanced_is_enabled: cfg.instanced()
+            instanced_is_enabled: cfg.instanced(),
+            workers: cfg.thread_pool_size()
         }
     }
 }
@@ -153,7 +187,7 @@ impl Drawlist for DrawlistNoSSBO {
         self.command.map();
     }
 
-    fn setup_compute(~self, db: &RenderData, tp: &mut TaskPool<Sender<Box<Drawlist+Send>>>, scene: ObjectKey) {
+    fn setup_compute(~self, db: &RenderData, tp: &mut TaskPool<Sender<Box<Drawlist+Send>>>, scene: ObjectKey, layers: Tags) {
         let DrawlistNoSSBO {
             data: _,
             size: size,
@@ -163,7 +197,8 @@ impl Drawlist for DrawlistNoSSBO {
             matrix: matrix,
             command: command,
             instanced_is_enabled: instanced_is_enabled,
-            start: _
+            start: _,
+            workers: workers
         } = *self;
 
         let data = DrawlistGraphicsData {
@@ -173,21 +208,26 @@ impl Drawlist for DrawlistNoSSBO {
         };
 
         let start = precise_time_s();
-        let db0 = data.clone();
-        let (sender, receiver0) = channel();
-        tp.execute(proc(_) {
-            let db = db0;
-            let mut matrix = matrix;
-            matrix.build(&db);
-            sender.send(matrix)
-        });
+        let receiver0 = matrix.build_par(&data, tp, workers);
+
+        // the models are found once, the chunks look up their part. The
+        // chunks are queued before the task that waits for them
+        let keys: Vec<ObjectKey> = scene_models(&data, scene, layers).iter().map(|&(id, _, _)| id).collect();
+        let mut model_chunks = Vec::new();
+        for (start, take) in split(keys.len(), workers).move_iter() {
+            let db = data.clone();
+            let keys = Vec::from_slice(keys.slice(start, start + take));
+            let (sender, receiver) = channel();
+            tp.execute(proc(_) {
+                sender.send(ModelInfoTextureBuffer::build_chunk(&db, keys.as_slice()));
+            });
+            model_chunks.push(receiver);
+        }
 
-        let db1 = data.clone();
         let (sender, receiver1) = channel();
         tp.execute(proc(_) {
-            let db = db1;
             let mut model = model;
-            model.build(&db, scene);
+            model.write(model_chunks.iter().map(|r| r.recv()).collect());
             sender.send(model);
         });
 
@@ -214,7 +254,7 @@ impl Drawlist for DrawlistNoSSBO {
         tp.execute(proc(_) {
             let db = db4;
             let mut command = command;
-            command.build(&db, scene, instanced_is_enabled);
+            command.build(&db, scene, layers, instanced_is_enabled);
             sender.send(command);
         });
 
@@ -235,7 +275,8 @@ impl Drawlist for DrawlistNoSSBO {
                             size: size,
                             start: start,
                             data: data,
-                            instanced_is_enabled: instanced_is_enabled
+                            instanced_is_enabled: instanced_is_enabled,
+                            workers: workers
                         } as Box<Drawlist+Send>
                     }
                 }
@@ -329,7 +370,9 @@ pub struct DrawlistSSBOCompute {
     start: f64,
 
     culling_is_enabled: bool,
-    instanced_is_enabled: bool
+    instanced_is_enabled: bool,
+    // the matrices are written by this many tasks
+    workers: uint
 }
 
 impl DrawlistSSBOCompute {
@@ -350,7 +393,8 @@ impl DrawlistSSBOCompute {
             command: CommandBufferIndirect::new(cfg),
             start: 0.,
             culling_is_enabled: cfg.culling(),
-            instanced_is_enabled: cfg.instanced()
+            instanced_is_enabled: cfg.instanced(),
+            workers: cfg.thread_pool_size()
         }
     }
 }
@@ -364,7 +408,7 @@ impl Drawlist for DrawlistSSBOCompute {
         self.command.map();
     }
 
-    fn setup_compute(~self, db: &RenderData, tp: &mut TaskPool<Sender<Box<Drawlist+Send>>>, scene: ObjectKey) {
+    fn setup_compute(~self, db: &RenderData, tp: &mut TaskPool<Sender<Box<Drawlist+Send>>>, scene: ObjectKey, layers: Tags) {
         let DrawlistSSBOCompute {
             data: _,
             size: size,
@@ -375,7 +419,8 @@ impl Drawlist for DrawlistSSBOCompute {
             command: command,
             culling_is_enabled: culling_is_enabled,
             instanced_is_enabled: instanced_is_enabled,
-            start: _
+            start: _,
+            workers: workers
         } = *self;
 
         let data = DrawlistGraphicsData {
@@ -385,21 +430,14 @@ impl Drawlist for DrawlistSSBOCompute {
         };
 
         let start = precise_time_s();
-        let db0 = data.clone();
-        let (sender, receiver0) = channel();
-        tp.execute(proc(_) {
-            let db = db0;
-            let mut matrix = matrix;
-            matrix.build(&db);
-            sender.send(matrix)
-        });
+        let receiver0 = matrix.build_par(&data, tp, workers);
 
         let db1 = data.clone();
         let (sender, receiver1) = channel();
         tp.execute(proc(_) {
             let db = db1;
             let mut model = model;
-            model.build(&db, scene);
+            model.build(&db, scene, layers);
             sender.send(model);
         });
 
@@ -426,7 +464,7 @@ impl Drawlist for DrawlistSSBOCompute {
         tp.execute(proc(_) {
             let db = db4;
             let mut command = command;
-            command.build(&db, scene, instanced_is_enabled);
+            command.build(&db, scene, layers, instanced_is_enabled);
             sender.send(command);
         });
 
@@ -448,7 +486,8 @@ impl Drawlist for DrawlistSSBOCompute {
                             start: start,
                             data: data,
                             culling_is_enabled: culling_is_enabled,
-                            instanced_is_enabled: instanced_is_enabled
+                            instanced_is_enabled: instanced_is_enabled,
+                            workers: workers
                         } as Box<Drawlist+Send>
                     }
                 }
@@ -223,7 +223,8 @@ fn render_server(command: Receiver<RenderCommand>,
 
         if drawlists_ready.len() > 0 && scene != 0 {
             let dl = drawlists_ready.pop().unwrap();
-            dl.setup_compute(db, &mut taskpool, scene);
+            let layers = db.camera_layers(camera);
+            dl.setup_compute(db, &mut taskpool, scene, layers);
             scene = 0;           
         }
     }
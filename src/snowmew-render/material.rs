@@ -96,9 +96,11 @@ impl MaterialBuffer {
     pub fn build(&mut self, graphics: &Graphics) {
         unsafe {
             mut_buf_as_slice(self.ptr, self.size, |b| {
-                for (id, (_, mat)) in graphics.material_iter().enumerate() {
-                    b[id] = MaterialStd140::from(mat, graphics);
-                } 
+                for (oid, mat) in graphics.material_iter() {
+                    let idx = graphics.material_index(*oid)
+                        .expect("material without an index");
+                    b[idx as uint] = MaterialStd140::from(mat, graphics);
+                }
             });
         }
     }
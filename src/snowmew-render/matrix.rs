@@ -1,6 +1,7 @@
 use std::mem;
 use std::ptr;
 use std::slice::raw::mut_buf_as_slice;
+use std::sync::TaskPool;
 use sync::Arc;
 
 use OpenCL::hl::{CommandQueue, Context, Device, Event, EventList};
@@ -16,6 +17,7 @@ use gl_cl::AcquireRelease;
 use position::{CalcPositionsCl, MatrixManager};
 
 use position::Positions;
+use snowmew::parallel::split;
 
 use {Config, RenderData};
 
@@ -64,6 +66,100 @@ impl<'r> MatrixManager for GLSSBOMatrix<'r> {
     }
 }
 
+// a mapped buffer the matrices of a range of slots are written to,
+// every task writes to different slots
+trait MatrixTarget: Clone+Send {
+    fn write<RD: RenderData>(&self, db: &RD, gen: uint, start: u32, end: u32);
+}
+
+#[deriving(Clone)]
+struct SSBOTarget {
+    mat: *mut Matrix4<f32>,
+    size: uint
+}
+
+impl MatrixTarget for SSBOTarget {
+    fn write<RD: RenderData>(&self, db: &RD, gen: uint, start: u32, end: u32) {
+        unsafe {
+            mut_buf_as_slice(self.mat, self.size, |mat| {
+                let mut mat = GLSSBOMatrix {
+                    mat: mat
+                };
+                db.write_position_generation(gen, start, end, &mut mat);
+            })
+        }
+    }
+}
+
+#[deriving(Clone)]
+struct TextureTarget {
+    x: *mut Vector4<f32>,
+    y: *mut Vector4<f32>,
+    z: *mut Vector4<f32>,
+    w: *mut Vector4<f32>,
+    size: uint
+}
+
+impl MatrixTarget for TextureTarget {
+    fn write<RD: RenderData>(&self, db: &RD, gen: uint, start: u32, end: u32) {
+        unsafe {
+            mut_buf_as_slice(self.x, self.size, |x| {
+            mut_buf_as_slice(self.y, self.size, |y| {
+            mut_buf_as_slice(self.z, self.size, |z| {
+            mut_buf_as_slice(self.w, self.size, |w| {
+                let mut mat = GLTextureMatrix {
+                    x: x, y: y, z: z, w: w
+                };
+                db.write_position_generation(gen, start, end, &mut mat);
+            })})})})
+        }
+    }
+}
+
+/// Queues the tasks that write the matrices of db to target. Every
+/// generation is split into up to workers ranges, the tasks of a
+/// generation wait for all of the generation before. Returns the
+/// receiver that hears from each task of the last generation, and how
+/// many of them there are.
+fn write_par<RD: RenderData+Clone+Send, MT: MatrixTarget, T>
+    (db: &RD, target: MT, tp: &mut TaskPool<T>, workers: uint) -> (Receiver<()>, uint) {
+    let ranges: Vec<Vec<(uint, uint)>> = db.position_generations().iter()
+            .map(|len| split(*len as uint, workers)).collect();
+
+    // built from the last generation back, each task needs to know
+    // who to tell once it is done
+    let (done, finished) = channel();
+    let mut notify = vec!(done);
+    let mut tasks = Vec::new();
+    for (gen, gen_ranges) in ranges.iter().enumerate().rev() {
+        let waits = if gen == 0 { 0 } else { ranges.get(gen-1).len() };
+        let mut senders = Vec::new();
+        for &(start, take) in gen_ranges.iter() {
+            let (send, recv) = channel();
+            senders.push(send);
+            tasks.push((gen, start as u32, (start + take) as u32, recv, waits, notify.clone()));
+        }
+        notify = senders;
+    }
+
+    // queued in generation order, no task waits for one queued after it
+    for (gen, start, end, wait, waits, notify) in tasks.move_iter().rev() {
+        let db = db.clone();
+        let target = target.clone();
+        tp.execute(proc(_) {
+            for _ in range(0, waits) {
+                wait.recv();
+            }
+            target.write(&db, gen, start, end);
+            for n in notify.iter() {
+                n.send(());
+            }
+        });
+    }
+
+    (finished, ranges.last().map_or(0, |r| r.len()))
+}
+
 pub struct MatrixSSBOBuffer {
     model_matrix: GLuint,
     ptr_model_matrix: *mut Matrix4<f32>,
@@ -158,6 +254,35 @@ impl MatrixSSBOBuffer {
         };
     }
 
+    /// Like `build`, but on the pool. Without OpenCL the slots are
+    /// written by up to workers tasks at a time. The buffer is sent
+    /// back once all are written.
+    pub fn build_par<RD: RenderData+Clone+Send, T>(self, db: &RD, tp: &mut TaskPool<T>,
+                                                   workers: uint) -> Receiver<MatrixSSBOBuffer> {
+        let (send, recv) = channel();
+        if self.cl.is_some() {
+            let db = db.clone();
+            tp.execute(proc(_) {
+                let mut matrix = self;
+                matrix.build(&db);
+                send.send(matrix);
+            });
+        } else {
+            let target = SSBOTarget {
+                mat: self.ptr_model_matrix,
+                size: self.size
+            };
+            let (finished, count) = write_par(db, target, tp, workers);
+            tp.execute(proc(_) {
+                for _ in range(0, count) {
+                    finished.recv();
+                }
+                send.send(self);
+            });
+        }
+        recv
+    }
+
     pub fn id(&self) -> GLuint { self.model_matrix }
 }
 
@@ -274,5 +399,37 @@ impl MatrixTextureBuffer {
         };
     }
 
+    /// Like `build`, but on the pool. Without OpenCL the slots are
+    /// written by up to workers tasks at a time. The buffer is sent
+    /// back once all are written.
+    pub fn build_par<RD: RenderData+Clone+Send, T>(self, db: &RD, tp: &mut TaskPool<T>,
+                                                   workers: uint) -> Receiver<MatrixTextureBuffer> {
+        let (send, recv) = channel();
+        if self.cl.is_some() {
+            let db = db.clone();
+            tp.execute(proc(_) {
+                let mut matrix = self;
+                matrix.build(&db);
+                send.send(matrix);
+            });
+        } else {
+            let target = TextureTarget {
+                x: self.ptr_model_matrix[0],
+                y: self.ptr_model_matrix[1],
+                z: self.ptr_model_matrix[2],
+                w: self.ptr_model_matrix[3],
+                size: self.size
+            };
+            let (finished, count) = write_par(db, target, tp, workers);
+            tp.execute(proc(_) {
+                for _ in range(0, count) {
+                    finished.recv();
+                }
+                send.send(self);
+            });
+        }
+        recv
+    }
+
     pub fn ids<'a>(&'a self) -> &'a [GLuint] { self.texture_model_matrix.as_slice() }
 }
\ No newline at end of file
@@ -3,8 +3,6 @@ use std::ptr;
 use std::mem;
 use std::slice::raw::mut_buf_as_slice;
 
-use cow::join::{join_set_to_map, join_maps};
-
 use gl;
 use gl::types::{GLsizeiptr, GLuint};
 
@@ -14,6 +12,34 @@ use Config;
 use RenderData;
 
 use snowmew::ObjectKey;
+use snowmew::common::{Tags, matches_tags};
+use snowmew::query::{query, query_set};
+use graphics::Drawable;
+use position::Id;
+
+/// The drawables of the scene that match layers with their location,
+/// in key order. The commands and the model info are both built from
+/// it so their indices agree. The walk is driven by the smaller of the
+/// scene set and the drawable table.
+pub fn scene_models<'a>(db: &'a RenderData, scene: ObjectKey, layers: Tags) -> Vec<(ObjectKey, &'a Drawable, Id)> {
+    let scene = db.scene_set(scene).expect("Failed to find scene");
+    let mut out = Vec::new();
+    if scene.len() < db.drawable_count() {
+        for (id, ((_, draw), pos)) in query_set(scene).lookup(db.drawable_table())
+                                                      .lookup(db.location_table()) {
+            if matches_tags(db.tags(*id), layers) {
+                out.push((*id, draw, *pos));
+            }
+        }
+    } else {
+        for (id, (draw, pos)) in query(db.drawable_iter()).join(db.location_iter()).has(scene) {
+            if matches_tags(db.tags(*id), layers) {
+                out.push((*id, draw, *pos));
+            }
+        }
+    }
+    out
+}
 
 struct ModelInfoSSBO {
     id: u32,
@@ -66,15 +92,15 @@ impl ModelInfoSSBOBuffer {
         self.ptr_model_info = ptr::mut_null();
     }
 
-    pub fn build(&mut self, db: &RenderData, scene: ObjectKey) {
+    pub fn build(&mut self, db: &RenderData, scene: ObjectKey, layers: Tags) {
         let position = db.compute_positions();
+        let models = scene_models(db, scene, layers);
         unsafe {
             mut_buf_as_slice(self.ptr_model_info, self.size, |info| {
-                for (idx, (id, (draw, pos))) in join_set_to_map(db.scene_iter(scene),
-                                                join_maps(db.drawable_iter(), db.location_iter())).enumerate() {
+                for (idx, &(id, draw, pos)) in models.iter().enumerate() {
                     info[idx] = ModelInfoSSBO {
-                        id: id.clone(),
-                        matrix: position.get_loc(*pos) as u32,
+                        id: id,
+                        matrix: position.get_loc(pos) as u32,
                         material: db.material_index(draw.material).unwrap() as u32,
                         sphere: db.sphere(draw.geometry),
                         _padd: 0
@@ -87,7 +113,7 @@ impl ModelInfoSSBOBuffer {
     pub fn id(&self) -> GLuint {self.model_info}
 }
 
-struct ModelInfoTexture {
+pub struct ModelInfoTexture {
     id: u32,
     matrix: u32,
     material: u32
@@ -142,17 +168,37 @@ impl ModelInfoTextureBuffer {
         self.ptr_model_info = ptr::mut_null();
     }
 
-    pub fn build(&mut self, db: &RenderData, scene: ObjectKey) {
+    pub fn build(&mut self, db: &RenderData, scene: ObjectKey, layers: Tags) {
+        let keys: Vec<ObjectKey> = scene_models(db, scene, layers).iter().map(|&(id, _, _)| id).collect();
+        self.write(vec!(ModelInfoTextureBuffer::build_chunk(db, keys.as_slice())));
+    }
+
+    /// the model info of a run of the keys found by `scene_models`,
+    /// each is looked up on its own so the runs can be built at the
+    /// same time
+    pub fn build_chunk(db: &RenderData, keys: &[ObjectKey]) -> Vec<ModelInfoTexture> {
         let position = db.compute_positions();
+        keys.iter().map(|id| {
+            let draw = db.drawable(*id).expect("model without a drawable");
+            let pos = db.location_table().find(id).expect("model without a location");
+            ModelInfoTexture {
+                id: *id,
+                matrix: position.get_loc(*pos) as u32,
+                material: db.material_index(draw.material).unwrap() as u32
+            }
+        }).collect()
+    }
+
+    /// writes the chunks built by `build_chunk` one after the other
+    pub fn write(&mut self, chunks: Vec<Vec<ModelInfoTexture>>) {
         unsafe {
             mut_buf_as_slice(self.ptr_model_info, self.size, |info| {
-                for (idx, (id, (draw, pos))) in join_set_to_map(db.scene_iter(scene),
-                                                join_maps(db.drawable_iter(), db.location_iter())).enumerate() {
-                    info[idx] = ModelInfoTexture {
-                        id: id.clone(),
-                        matrix: position.get_loc(*pos) as u32,
-                        material: db.material_index(draw.material).unwrap() as u32
-                    };
+                let mut idx = 0;
+                for chunk in chunks.move_iter() {
+                    for m in chunk.move_iter() {
+                        info[idx] = m;
+                        idx += 1;
+                    }
                 }
             });
         }
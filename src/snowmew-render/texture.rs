@@ -144,7 +144,11 @@ impl TextureAtlas {
 
         array.load(texture_index, text);
         self.loaded.insert((texture_atlas, texture_index));
+    }
 
+    /// the slot is uploaded again by the next load
+    pub fn unload(&mut self, texture_atlas: uint, texture_index: uint) {
+        self.loaded.remove(&(texture_atlas, texture_index));
     }
 
     pub fn textures(&self) -> Vec<u32> {
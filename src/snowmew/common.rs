@@ -1,33 +1,120 @@
+use std::io::IoResult;
+use std::mem;
+use std::vec::MoveItems;
+
 use cow::btree::{BTreeMap, BTreeMapIterator, BTreeSet, BTreeSetIterator};
 
+use snapshot::{Snapshot, VERSION};
+use diff::{Diff, diff, diff_by, same_map, same_set};
+use component::ComponentData;
+use lockstep::{StateHashDelta, table_hash_delta, value_hash_delta};
+use event::{EventData, Events, Target};
+use merge::{Merge, Policy, Custom, merge_map, pick};
+use replicate::{Replicate, write_map_delta, write_map_delta_by, read_map_delta, read_map_delta_with};
+use stats::{Stats, TableStats, table_stats, table_stats_by};
+
 #[deriving(Clone, Default)]
 pub struct FrameInfo {
-    count: uint,  /* unique frame identifier */
-    time: f64,    /* current time in seconds */
-    delta: f64,   /* time from last frame */
+    pub count: uint,  /* unique frame identifier */
+    pub time: f64,    /* current time in seconds */
+    pub delta: f64,   /* time from last frame */
+    pub alpha: f64    /* how far the display is between the last two frames */
 }
 
 
-#[deriving(Clone, Default)]
+#[deriving(Clone, Default, PartialEq)]
 pub struct Object {
     pub parent: ObjectKey,
     pub name: ObjectKey,
+    pub tags: Tags,
+    pub gen: u32
 }
 
 pub type ObjectKey = u32;
 pub type StringKey = u32;
 
+/// An ObjectKey that remembers which object it was created for. If
+/// the object is deleted the handle no longer resolves, even if the
+/// key is given to a new object.
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+pub struct Handle {
+    key: ObjectKey,
+    gen: u32
+}
+
+impl Handle {
+    pub fn key(&self) -> ObjectKey { self.key }
+}
+
+/// A bitmask of layers/tags, an object can have any number of them
+pub type Tags = u32;
+pub static ALL_TAGS: Tags = 0xFFFF_FFFF;
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.get(0), name.get(0)) {
+        (None, None) => true,
+        (Some(&b'*'), _) => {
+            glob_match(pattern.slice_from(1), name) ||
+            (name.len() != 0 && glob_match(pattern, name.slice_from(1)))
+        }
+        (Some(&b'?'), Some(_)) => glob_match(pattern.slice_from(1), name.slice_from(1)),
+        (Some(p), Some(n)) if p == n => glob_match(pattern.slice_from(1), name.slice_from(1)),
+        _ => false
+    }
+}
+
+/// true if tags has any bit of mask, `ALL_TAGS` matches everything
+/// including untagged objects
+pub fn matches_tags(tags: Tags, mask: Tags) -> bool {
+    mask == ALL_TAGS || tags & mask != 0
+}
+
+impl Snapshot for Object {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.parent.save(w));
+        try!(self.name.save(w));
+        try!(self.tags.save(w));
+        self.gen.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Object> {
+        Snapshot::load_version(r, VERSION)
+    }
+
+    // version 1 has no tags, versions before 3 no generations
+    fn load_version(r: &mut Reader, version: u32) -> IoResult<Object> {
+        let parent = try!(Snapshot::load(r));
+        let name = try!(Snapshot::load(r));
+        let tags = if version >= 2 { try!(Snapshot::load(r)) } else { 0 };
+        let gen = if version >= 3 { try!(Snapshot::load(r)) } else { 0 };
+        Ok(Object {
+            parent: parent,
+            name: name,
+            tags: tags,
+            gen: gen
+        })
+    }
+}
+
 #[deriving(Clone)]
 pub struct CommonData {
     last_sid:       StringKey,
     strings:        BTreeMap<StringKey, String>,
     string_to_key:  BTreeMap<String, StringKey>,
+    string_refs:    BTreeMap<StringKey, uint>,
 
     last_oid:       ObjectKey,
+    free_oids:      BTreeSet<ObjectKey>,
+    last_gen:       u32,
     objects:        BTreeMap<ObjectKey, Object>,
     parent_child:   BTreeMap<ObjectKey, BTreeMap<StringKey, ObjectKey>>,
 
-    scene_children: BTreeMap<ObjectKey, BTreeSet<ObjectKey>>
+    scene_children: BTreeMap<ObjectKey, BTreeSet<ObjectKey>>,
+
+    tag_names:      BTreeMap<String, Tags>,
+
+    components:     ComponentData,
+    events:         EventData
 }
 
 impl CommonData {
@@ -36,15 +123,40 @@ impl CommonData {
             last_sid:           1,
             strings:            BTreeMap::new(),
             string_to_key:      BTreeMap::new(),
+            string_refs:        BTreeMap::new(),
 
             last_oid:           1,
+            free_oids:          BTreeSet::new(),
+            last_gen:           1,
             objects:            BTreeMap::new(),
             parent_child:       BTreeMap::new(),
 
-            scene_children:     BTreeMap::new()
+            scene_children:     BTreeMap::new(),
+
+            tag_names:          BTreeMap::new(),
+
+            components:         ComponentData::new(),
+            events:             EventData::new()
         }   
     }
 
+    /// Objects that were added, removed, renamed or moved since old
+    pub fn diff<'a>(&'a self, old: &'a CommonData) -> Diff<'a, ObjectKey, Object> {
+        diff(&old.objects, &self.objects)
+    }
+
+    /// Scenes that were added, removed or whose members changed since old
+    pub fn scene_diff<'a>(&'a self, old: &'a CommonData) -> Diff<'a, ObjectKey, BTreeSet<ObjectKey>> {
+        diff_by(&old.scene_children, &self.scene_children, |a, b| same_set(a, b))
+    }
+
+    // true if objects were added, removed, renamed or moved since
+    // base, or a tag was allocated
+    fn tree_changed(&self, base: &CommonData) -> bool {
+        diff_by(&base.objects, &self.objects, |a, b| a.parent == b.parent && a.name == b.name).next().is_some() ||
+        diff(&base.tag_names, &self.tag_names).next().is_some()
+    }
+
     fn ifind(&self, node: Option<ObjectKey>, str_key: &str) -> Option<ObjectKey> {
         let node = match node {
             Some(key) => key,
@@ -67,6 +179,40 @@ impl CommonData {
         }
     }
 
+    // ** matches any number of levels, * and ? work inside of a name
+    fn glob(&self, node: ObjectKey, segs: &[&str], out: &mut Vec<ObjectKey>) {
+        if segs.len() == 0 {
+            if node != 0 {
+                out.push(node);
+            }
+            return;
+        }
+
+        let children = match self.parent_child.find(&node) {
+            Some(children) => children,
+            None => {
+                if segs[0] == "**" {
+                    self.glob(node, segs.slice_from(1), out);
+                }
+                return;
+            }
+        };
+
+        if segs[0] == "**" {
+            self.glob(node, segs.slice_from(1), out);
+            for (_, child) in children.iter() {
+                self.glob(*child, segs, out);
+            }
+        } else {
+            for (sid, child) in children.iter() {
+                let name = self.strings.find(sid).expect("Found StringKey w/o Key");
+                if glob_match(segs[0].as_bytes(), name.as_bytes()) {
+                    self.glob(*child, segs.slice_from(1), out);
+                }
+            }
+        }
+    }
+
     fn name(&self, key: ObjectKey) -> String {
         match self.objects.find(&key) {
             Some(node) => {
@@ -77,10 +223,40 @@ impl CommonData {
     }
 
 
+    fn scene_of(&self, oid: ObjectKey) -> Option<ObjectKey> {
+        let mut node = oid;
+        while node != 0 {
+            if self.scene_children.find(&node).is_some() {
+                return Some(node);
+            }
+            node = match self.objects.find(&node) {
+                Some(obj) => obj.parent,
+                None => 0
+            };
+        }
+        None
+    }
+
+    // the smallest key of a deleted object is given out first
     fn new_key(&mut self) -> ObjectKey {
-        let new_key = self.last_oid;
-        self.last_oid += 1;
-        new_key        
+        let free = self.free_oids.iter().next().map(|k| *k);
+        match free {
+            Some(key) => {
+                self.free_oids.remove(&key);
+                key
+            }
+            None => {
+                let new_key = self.last_oid;
+                self.last_oid += 1;
+                new_key
+            }
+        }
+    }
+
+    fn new_gen(&mut self) -> u32 {
+        let gen = self.last_gen;
+        self.last_gen += 1;
+        gen
     }
 
     fn update_parent_child(&mut self, parent: ObjectKey, child_name: StringKey, child: ObjectKey) {
@@ -102,6 +278,8 @@ impl CommonData {
         }
     }
 
+    // every call takes a reference on the string, it must be
+    // given back with release_string once the object is removed
     fn new_string(&mut self, s: &str) -> StringKey {
         let (update, name) = match self.string_to_key.find(&s.to_string()) {
             None => {
@@ -112,7 +290,7 @@ impl CommonData {
             }
         };
 
-        if update {
+        let name = if update {
             let name = self.last_sid;
             self.last_sid += 1;
             self.strings.insert(name, s.to_string());
@@ -120,7 +298,377 @@ impl CommonData {
             name
         } else {
             name
+        };
+
+        let count = match self.string_refs.find(&name) {
+            Some(count) => *count,
+            None => 0
+        };
+        self.string_refs.insert(name, count + 1);
+        name
+    }
+
+    fn release_string(&mut self, sid: StringKey) {
+        let count = match self.string_refs.find(&sid) {
+            Some(count) => *count,
+            None => return
+        };
+
+        if count > 1 {
+            self.string_refs.insert(sid, count - 1);
+        } else {
+            self.string_refs.remove(&sid);
+            match self.strings.pop(&sid) {
+                Some(s) => { self.string_to_key.remove(&s); },
+                None => ()
+            }
+        }
+    }
+
+    // collects the keys of the subtree starting at oid, parents
+    // are always placed before their children
+    fn collect_subtree(&self, oid: ObjectKey, out: &mut Vec<ObjectKey>) {
+        out.push(oid);
+        match self.parent_child.find(&oid) {
+            Some(children) => {
+                for (_, child) in children.iter() {
+                    self.collect_subtree(*child, out);
+                }
+            }
+            None => ()
+        }
+    }
+
+    // the scene the object is a member of
+    fn member_of(&self, oid: ObjectKey) -> Option<ObjectKey> {
+        match self.objects.find(&oid) {
+            Some(obj) => self.scene_of(obj.parent),
+            None => None
+        }
+    }
+
+    fn unlink_child(&mut self, parent: ObjectKey, name: StringKey, child: ObjectKey) {
+        match self.parent_child.find_mut(&parent) {
+            Some(children) => {
+                let owned = children.find(&name).map(|k| *k) == Some(child);
+                if owned {
+                    children.remove(&name);
+                }
+            }
+            None => ()
+        }
+    }
+
+    fn delete_leaf(&mut self, oid: ObjectKey) -> bool {
+        match self.parent_child.find(&oid) {
+            Some(children) => if children.len() != 0 { return false; },
+            None => ()
+        }
+
+        let obj = match self.objects.find(&oid) {
+            Some(obj) => obj.clone(),
+            None => return false
+        };
+
+        match self.scene_of(obj.parent) {
+            Some(scene) => {
+                self.scene_children.find_mut(&scene).unwrap().remove(&oid);
+            }
+            None => ()
         }
+        self.scene_children.remove(&oid);
+        self.parent_child.remove(&oid);
+        self.unlink_child(obj.parent, obj.name, oid);
+
+        self.objects.remove(&oid);
+        self.free_oids.insert(oid);
+        self.components.delete(oid);
+        self.release_string(obj.name);
+        true
+    }
+
+    fn rename_object(&mut self, oid: ObjectKey, name: &str) -> bool {
+        let obj = match self.objects.find(&oid) {
+            Some(obj) => obj.clone(),
+            None => return false
+        };
+
+        // a sibling already owns the name
+        match self.ifind(Some(obj.parent), name) {
+            Some(other) => return other == oid,
+            None => ()
+        }
+
+        let sid = self.new_string(name);
+        self.unlink_child(obj.parent, obj.name, oid);
+        self.update_parent_child(obj.parent, sid, oid);
+        self.objects.insert(oid, Object {
+            parent: obj.parent,
+            name: sid,
+            tags: obj.tags,
+            gen: obj.gen
+        });
+        self.release_string(obj.name);
+        true
+    }
+
+    fn reparent_object(&mut self, oid: ObjectKey, parent: ObjectKey) -> bool {
+        let obj = match self.objects.find(&oid) {
+            Some(obj) => obj.clone(),
+            None => return false
+        };
+
+        if parent != 0 && self.objects.find(&parent).is_none() {
+            return false;
+        }
+
+        // an object can not be moved below itself
+        let mut node = parent;
+        while node != 0 {
+            if node == oid {
+                return false;
+            }
+            node = self.objects.find(&node).unwrap().parent;
+        }
+
+        let clash = match self.parent_child.find(&parent) {
+            Some(children) => children.find(&obj.name).is_some(),
+            None => false
+        };
+        if clash {
+            return obj.parent == parent;
+        }
+
+        let mut keys = Vec::new();
+        self.collect_subtree(oid, &mut keys);
+        let old_scenes: Vec<Option<ObjectKey>> = keys.iter().map(|k| self.member_of(*k)).collect();
+
+        self.unlink_child(obj.parent, obj.name, oid);
+        self.update_parent_child(parent, obj.name, oid);
+        self.objects.insert(oid, Object {
+            parent: parent,
+            name: obj.name,
+            tags: obj.tags,
+            gen: obj.gen
+        });
+
+        for (key, old) in keys.iter().zip(old_scenes.iter()) {
+            let new = self.member_of(*key);
+            if new == *old {
+                continue;
+            }
+            match *old {
+                Some(scene) => { self.scene_children.find_mut(&scene).unwrap().remove(key); }
+                None => ()
+            }
+            match new {
+                Some(scene) => { self.scene_children.find_mut(&scene).unwrap().insert(*key); }
+                None => ()
+            }
+        }
+        true
+    }
+}
+
+fn load_objects(r: &mut Reader, version: u32) -> IoResult<BTreeMap<ObjectKey, Object>> {
+    let len = try!(r.read_le_u32());
+    let mut objects = BTreeMap::new();
+    for _ in range(0, len) {
+        let key: ObjectKey = try!(Snapshot::load(r));
+        let obj: Object = try!(Snapshot::load_version(r, version));
+        objects.insert(key, obj);
+    }
+    Ok(objects)
+}
+
+// the keys below last_oid that no object uses, snapshots older then
+// version 5 did not save them
+fn free_keys(last_oid: ObjectKey, objects: &BTreeMap<ObjectKey, Object>) -> BTreeSet<ObjectKey> {
+    let mut free = BTreeSet::new();
+    for key in range(1, last_oid) {
+        if objects.find(&key).is_none() {
+            free.insert(key);
+        }
+    }
+    free
+}
+
+impl Snapshot for CommonData {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(self.last_sid.save(w));
+        try!(self.strings.save(w));
+        try!(self.string_refs.save(w));
+        try!(self.last_oid.save(w));
+        try!(self.free_oids.save(w));
+        try!(self.last_gen.save(w));
+        try!(self.objects.save(w));
+        try!(self.parent_child.save(w));
+        try!(self.scene_children.save(w));
+        try!(self.tag_names.save(w));
+        self.components.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<CommonData> {
+        Snapshot::load_version(r, VERSION)
+    }
+
+    fn load_version(r: &mut Reader, version: u32) -> IoResult<CommonData> {
+        let last_sid = try!(Snapshot::load(r));
+        let strings: BTreeMap<StringKey, String> = try!(Snapshot::load(r));
+        let string_refs = try!(Snapshot::load(r));
+        let last_oid = try!(Snapshot::load(r));
+        let free_oids = if version >= 5 { Some(try!(Snapshot::load(r))) } else { None };
+        let last_gen = if version >= 3 { try!(Snapshot::load(r)) } else { 1 };
+        let objects = try!(load_objects(r, version));
+        let parent_child = try!(Snapshot::load(r));
+        let scene_children = try!(Snapshot::load(r));
+        let tag_names = if version >= 2 { try!(Snapshot::load(r)) } else { BTreeMap::new() };
+        let components = if version >= 4 { try!(Snapshot::load(r)) } else { ComponentData::new() };
+        let free_oids = match free_oids {
+            Some(free) => free,
+            None => free_keys(last_oid, &objects)
+        };
+
+        // the reverse lookup is rebuilt rather then stored
+        let mut string_to_key = BTreeMap::new();
+        for (sid, s) in strings.iter() {
+            string_to_key.insert(s.clone(), *sid);
+        }
+
+        Ok(CommonData {
+            last_sid: last_sid,
+            strings: strings,
+            string_to_key: string_to_key,
+            string_refs: string_refs,
+            last_oid: last_oid,
+            free_oids: free_oids,
+            last_gen: last_gen,
+            objects: objects,
+            parent_child: parent_child,
+            scene_children: scene_children,
+            tag_names: tag_names,
+            // events only live for a frame
+            components: components,
+            events: EventData::new()
+        })
+    }
+}
+
+/// Covers what a snapshot saves
+impl StateHashDelta for CommonData {
+    fn state_hash_delta(&self, old: &CommonData) -> u64 {
+        value_hash_delta("last_sid", &old.last_sid, &self.last_sid) +
+        table_hash_delta("strings", &old.strings, &self.strings, |a, b| a == b) +
+        table_hash_delta("string_refs", &old.string_refs, &self.string_refs, |a, b| a == b) +
+        value_hash_delta("last_oid", &old.last_oid, &self.last_oid) +
+        value_hash_delta("last_gen", &old.last_gen, &self.last_gen) +
+        table_hash_delta("objects", &old.objects, &self.objects, |a, b| a == b) +
+        table_hash_delta("parent_child", &old.parent_child, &self.parent_child, |a, b| same_map(a, b)) +
+        table_hash_delta("scene_children", &old.scene_children, &self.scene_children, |a, b| same_set(a, b)) +
+        table_hash_delta("tag_names", &old.tag_names, &self.tag_names, |a, b| a == b) +
+        self.components.state_hash_delta(&old.components)
+    }
+}
+
+impl Stats for CommonData {
+    fn stats(&self, other: Option<&CommonData>) -> Vec<TableStats> {
+        vec!(table_stats_by("strings", &self.strings, other.map(|o| &o.strings), |_, s| s.len()),
+             table_stats_by("string_to_key", &self.string_to_key, other.map(|o| &o.string_to_key),
+                            |s, _| s.len()),
+             table_stats("string_refs", &self.string_refs, other.map(|o| &o.string_refs)),
+             table_stats("objects", &self.objects, other.map(|o| &o.objects)),
+             table_stats_by("parent_child", &self.parent_child, other.map(|o| &o.parent_child),
+                            |_, c| c.len() * mem::size_of::<(StringKey, ObjectKey)>()),
+             table_stats_by("scene_children", &self.scene_children, other.map(|o| &o.scene_children),
+                            |_, c| c.len() * mem::size_of::<ObjectKey>()),
+             table_stats_by("tag_names", &self.tag_names, other.map(|o| &o.tag_names), |s, _| s.len()),
+             self.components.stats(other.map(|o| &o.components)))
+    }
+}
+
+// the parent and name come from the side that moved the object, the
+// tags from the side that retagged it. A removal always wins.
+fn merge_object(base: Option<&Object>, ours: Option<&Object>, theirs: Option<&Object>) -> Option<Object> {
+    match (base, ours, theirs) {
+        (Some(b), Some(o), Some(t)) => {
+            let mut out = if o.parent != b.parent || o.name != b.name { o.clone() } else { t.clone() };
+            out.tags = if t.tags != b.tags { t.tags } else { o.tags };
+            Some(out)
+        }
+        (Some(_), _, _) => None,
+        (None, o, t) => o.or(t).map(|v| v.clone())
+    }
+}
+
+/// Only one side may change the object tree, both sides can change
+/// tags and components and post events.
+impl Merge for CommonData {
+    fn merge(base: &CommonData, ours: &CommonData, theirs: &CommonData) -> Result<CommonData, String> {
+        let tree = try!(pick(base, ours, theirs, |b, x| x.tree_changed(b), "the object tree"));
+        let mut out = tree.clone();
+        out.objects = merge_map(&base.objects, &ours.objects, &theirs.objects, &Custom(merge_object));
+        out.components = try!(ComponentData::merge(&base.components, &ours.components, &theirs.components));
+        out.events = EventData::merge(&base.events, &ours.events, &theirs.events);
+        Ok(out)
+    }
+}
+
+/// The object tree is always sent whole, hidden only applies to the
+/// tables of the other subsystems. Components and events are local.
+impl Replicate for CommonData {
+    fn write_delta(&self, old: &CommonData, _: &BTreeSet<ObjectKey>, _: &BTreeSet<ObjectKey>,
+                   w: &mut Writer) -> IoResult<()> {
+        try!(self.last_sid.save(w));
+        try!(write_map_delta(&old.strings, &self.strings, w));
+        try!(write_map_delta(&old.string_to_key, &self.string_to_key, w));
+        try!(write_map_delta(&old.string_refs, &self.string_refs, w));
+        try!(self.last_oid.save(w));
+        try!(self.last_gen.save(w));
+        try!(write_map_delta(&old.objects, &self.objects, w));
+        try!(write_map_delta_by(&old.parent_child, &self.parent_child, |a, b| same_map(a, b), w));
+        try!(write_map_delta_by(&old.scene_children, &self.scene_children, |a, b| same_set(a, b), w));
+        write_map_delta(&old.tag_names, &self.tag_names, w)
+    }
+
+    fn read_delta(&self, r: &mut Reader) -> IoResult<CommonData> {
+        let last_sid = try!(Snapshot::load(r));
+        let strings = try!(read_map_delta(&self.strings, r));
+        let string_to_key = try!(read_map_delta(&self.string_to_key, r));
+        let string_refs = try!(read_map_delta(&self.string_refs, r));
+        let last_oid: ObjectKey = try!(Snapshot::load(r));
+        let last_gen = try!(Snapshot::load(r));
+
+        // keys handed out since the last delta start free, the object
+        // changes then fix up only the keys they touch
+        let mut free_oids = self.free_oids.clone();
+        for key in range(self.last_oid, last_oid) {
+            free_oids.insert(key);
+        }
+        let objects = try!(read_map_delta_with(&self.objects, r, |key, has| {
+            if has {
+                free_oids.remove(key);
+            } else {
+                free_oids.insert(*key);
+            }
+        }));
+        let parent_child = try!(read_map_delta(&self.parent_child, r));
+        let scene_children = try!(read_map_delta(&self.scene_children, r));
+        let tag_names = try!(read_map_delta(&self.tag_names, r));
+        Ok(CommonData {
+            last_sid: last_sid,
+            strings: strings,
+            string_to_key: string_to_key,
+            string_refs: string_refs,
+            last_oid: last_oid,
+            free_oids: free_oids,
+            last_gen: last_gen,
+            objects: objects,
+            parent_child: parent_child,
+            scene_children: scene_children,
+            tag_names: tag_names,
+            components: self.components.clone(),
+            events: self.events.clone()
+        })
     }
 }
 
@@ -133,39 +681,34 @@ pub trait Common {
     }
 
     fn new_scene(&mut self, name: &str) -> ObjectKey {
-        let oid = self.new_object(None, name);
+        self.add_scene(None, name)
+    }
+
+    /// a scene that is a child of parent, new_scene adds one to the root
+    fn add_scene(&mut self, parent: Option<ObjectKey>, name: &str) -> ObjectKey {
+        let oid = self.new_object(parent, name);
         self.get_common_mut().scene_children.insert(oid, BTreeSet::new());
         oid
     }
 
     fn new_object(&mut self, parent: Option<ObjectKey>, name: &str) -> ObjectKey {
         let new_key = self.get_common_mut().new_key();
-        let mut parent = match parent {
+        let parent = match parent {
             Some(key) => key,
             None => 0
         };
 
         let object = Object {
             name: self.get_common_mut().new_string(name),
-            parent: parent
+            parent: parent,
+            tags: 0,
+            gen: self.get_common_mut().new_gen()
         };
 
         self.get_common_mut().objects.insert(new_key, object);
         self.get_common_mut().update_parent_child(parent, object.name, new_key);
 
-        let mut scene_id = None;
-        while parent != 0 {
-            match self.get_common().scene_children.find(&parent) {
-                None => {
-                    parent = self.get_common().objects.find(&parent).unwrap().parent;
-                }
-                Some(_) => {
-                    scene_id = Some(parent);
-                    parent = 0;
-                }
-            }
-        }
-
+        let scene_id = self.get_common().scene_of(parent);
         match scene_id {
             Some(id) => {
                 let sc = self.get_common_mut().scene_children.find_mut(&id).unwrap();
@@ -177,12 +720,130 @@ pub trait Common {
         new_key
     }
 
+    /// Called for every object `delete_object` and `delete_subtree`
+    /// remove, before it is removed. Deletes the data the other
+    /// subsystems keep for the object, eg. with `delete_position` and
+    /// `delete_graphics`. Keys are reused, so anything left behind is
+    /// inherited by the next object.
+    fn delete_data(&mut self, oid: ObjectKey);
+
+    /// Called by `instantiate` once the objects are copied, copies the
+    /// data of the other subsystems, eg. with `copy_positions` and
+    /// `copy_graphics`.
+    fn copy_data(&mut self, remap: &Remap);
+
+    /// Removes a single object, this will fail if the object has
+    /// children.
+    fn delete_object(&mut self, oid: ObjectKey) -> bool {
+        if self.object(oid).is_none() || self.walk_dir(oid).next().is_some() {
+            return false;
+        }
+        self.delete_data(oid);
+        self.get_common_mut().delete_leaf(oid)
+    }
+
+    /// Removes the object and all of its descendants, the removed keys
+    /// are returned.
+    fn delete_subtree(&mut self, oid: ObjectKey) -> Vec<ObjectKey> {
+        let mut keys = Vec::new();
+        if self.object(oid).is_none() {
+            return keys;
+        }
+
+        self.get_common().collect_subtree(oid, &mut keys);
+        for key in keys.iter().rev() {
+            self.delete_data(*key);
+            self.get_common_mut().delete_leaf(*key);
+        }
+        keys
+    }
+
+    /// Changes the name of the object, fails if a sibling already
+    /// uses the name.
+    fn rename(&mut self, oid: ObjectKey, name: &str) -> bool {
+        self.get_common_mut().rename_object(oid, name)
+    }
+
+    /// Moves the object and its children below a new parent. Scene
+    /// membership of the subtree follows the new parent. Positions
+    /// have to be updated with `reparent_position`.
+    fn reparent(&mut self, oid: ObjectKey, parent: Option<ObjectKey>) -> bool {
+        let parent = match parent {
+            Some(key) => key,
+            None => 0
+        };
+        self.get_common_mut().reparent_object(oid, parent)
+    }
+
+    /// All keys below and including oid, parents come before their children.
+    fn subtree(&self, oid: ObjectKey) -> Vec<ObjectKey> {
+        let mut keys = Vec::new();
+        if self.object(oid).is_some() {
+            self.get_common().collect_subtree(oid, &mut keys);
+        }
+        keys
+    }
+
     fn scene_iter<'a>(&'a self, oid: ObjectKey) -> BTreeSetIterator<'a, u32> {
         let sc = self.get_common().scene_children.find(&oid)
             .expect("Failed to find scene");
         sc.iter()
     }
 
+    fn scene_set<'a>(&'a self, oid: ObjectKey) -> Option<&'a BTreeSet<ObjectKey>> {
+        self.get_common().scene_children.find(&oid)
+    }
+
+    /// members of the scene that have any of the tags in mask
+    fn scene_iter_tagged<'a>(&'a self, oid: ObjectKey, mask: Tags) -> TaggedIter<'a, BTreeSetIterator<'a, u32>> {
+        TaggedIter {
+            common: self.get_common(),
+            iter: self.scene_iter(oid),
+            mask: mask
+        }
+    }
+
+    /// every object that has any of the tags in mask
+    fn tagged_iter<'a>(&'a self, mask: Tags) -> TaggedIter<'a, ObjectKeys<'a>> {
+        TaggedIter {
+            common: self.get_common(),
+            iter: ObjectKeys { iter: self.get_common().objects.iter() },
+            mask: mask
+        }
+    }
+
+    fn handle(&self, oid: ObjectKey) -> Option<Handle> {
+        self.object(oid).map(|obj| Handle { key: oid, gen: obj.gen })
+    }
+
+    /// the key of the object, None if it was deleted
+    fn resolve(&self, handle: &Handle) -> Option<ObjectKey> {
+        match self.object(handle.key) {
+            Some(obj) if obj.gen == handle.gen => Some(handle.key),
+            _ => None
+        }
+    }
+
+    fn is_alive(&self, handle: &Handle) -> bool {
+        self.resolve(handle).is_some()
+    }
+
+    fn object_by_handle<'a>(&'a self, handle: &Handle) -> Option<&'a Object> {
+        match self.resolve(handle) {
+            Some(oid) => self.object(oid),
+            None => None
+        }
+    }
+
+    /// Removes the object the handle was created for and its
+    /// descendants, nothing is removed if the key was reused.
+    fn delete_handle(&mut self, handle: &Handle) -> Vec<ObjectKey> {
+        match self.resolve(handle) {
+            Some(oid) => self.delete_subtree(oid),
+            None => Vec::new()
+        }
+    }
+
     fn object<'a>(&'a self, oid: ObjectKey) -> Option<&'a Object> {
         self.get_common().objects.find(&oid)
     }
@@ -199,32 +860,213 @@ pub trait Common {
         node
     }
 
+    /// Every object matching a path pattern, `*` matches one level
+    /// and `**` any number of levels, `*` and `?` can also be used
+    /// as part of a name. eg. `scene/*/lights/**`
+    fn find_all(&self, pattern: &str) -> MoveItems<ObjectKey> {
+        self.find_all_where(pattern, |_, _| true)
+    }
+
+    /// find_all limited to objects that pass filter
+    fn find_all_where(&self, pattern: &str, filter: |&Self, ObjectKey| -> bool) -> MoveItems<ObjectKey> {
+        let segs: Vec<&str> = pattern.split('/').filter(|s| s.len() != 0).collect();
+        let mut found = Vec::new();
+        self.get_common().glob(0, segs.as_slice(), &mut found);
+        found.sort();
+        found.dedup();
+
+        let mut out = Vec::new();
+        for key in found.move_iter() {
+            if filter(self, key) {
+                out.push(key);
+            }
+        }
+        out.move_iter()
+    }
+
     fn walk_dir<'a>(&'a self, oid: ObjectKey) -> DirIter<'a> {
-        let dir = self.get_common().parent_child.find(&oid).unwrap();
+        let dir = self.get_common().parent_child.find(&oid);
         DirIter {
             common: self.get_common(),
-            iter: dir.iter()
+            iter: dir.map(|d| d.iter())
         }
     }
 
+    fn is_scene(&self, oid: ObjectKey) -> bool {
+        self.get_common().scene_children.find(&oid).is_some()
+    }
+
     fn name(&self, key: ObjectKey) -> String {
         self.get_common().name(key)
     }
+
+    /// Copies the subtree at template to a new object called name. The
+    /// names, tags and components are copied, the other subsystems
+    /// copy their data in `copy_data`. Fails if the parent already has
+    /// a child called name.
+    fn instantiate(&mut self, template: ObjectKey, parent: Option<ObjectKey>, name: &str) -> Option<Remap> {
+        if self.get_common().ifind(parent, name).is_some() {
+            return None;
+        }
+
+        let keys = self.subtree(template);
+        if keys.len() == 0 {
+            return None;
+        }
+
+        let mut remap = BTreeMap::new();
+        for key in keys.iter() {
+            let obj = self.object(*key).unwrap().clone();
+            let new = if *key == template {
+                self.new_object(parent, name)
+            } else {
+                let obj_name = self.get_common().strings.find(&obj.name).unwrap().clone();
+                let new_parent = *remap.find(&obj.parent).unwrap();
+                self.new_object(Some(new_parent), obj_name.as_slice())
+            };
+            self.set_tags(new, obj.tags);
+            self.get_common_mut().components.copy(*key, new);
+            remap.insert(*key, new);
+        }
+
+        let remap = Remap {
+            keys: remap
+        };
+        self.copy_data(&remap);
+        Some(remap)
+    }
+
+    /// the bit used for a named tag, allocated on first use
+    fn tag(&mut self, name: &str) -> Tags {
+        match self.find_tag(name) {
+            Some(tag) => return tag,
+            None => ()
+        }
+
+        let count = self.get_common().tag_names.len();
+        if count >= 32 {
+            fail!("out of tags, {} can not be allocated", name);
+        }
+        let tag = 1 << count;
+        self.get_common_mut().tag_names.insert(name.to_string(), tag);
+        tag
+    }
+
+    fn find_tag(&self, name: &str) -> Option<Tags> {
+        self.get_common().tag_names.find(&name.to_string()).map(|t| *t)
+    }
+
+    fn tags(&self, oid: ObjectKey) -> Tags {
+        match self.object(oid) {
+            Some(obj) => obj.tags,
+            None => 0
+        }
+    }
+
+    fn set_tags(&mut self, oid: ObjectKey, tags: Tags) -> bool {
+        match self.get_common_mut().objects.find_mut(&oid) {
+            Some(obj) => {
+                obj.tags = tags;
+                true
+            }
+            None => false
+        }
+    }
+
+    fn add_tags(&mut self, oid: ObjectKey, tags: Tags) -> bool {
+        let old = self.tags(oid);
+        self.set_tags(oid, old | tags)
+    }
+
+    fn remove_tags(&mut self, oid: ObjectKey, tags: Tags) -> bool {
+        let old = self.tags(oid);
+        self.set_tags(oid, old & !tags)
+    }
+
+    fn has_tags(&self, oid: ObjectKey, mask: Tags) -> bool {
+        matches_tags(self.tags(oid), mask)
+    }
+
+    fn set_component<T: Clone+PartialEq+Send+Share+'static>(&mut self, oid: ObjectKey, value: T) {
+        self.get_common_mut().components.insert(oid, value);
+    }
+
+    fn component<'a, T: Clone+PartialEq+Send+Share+'static>(&'a self, oid: ObjectKey) -> Option<&'a T> {
+        self.get_common().components.find(oid)
+    }
+
+    fn component_mut<'a, T: Clone+PartialEq+Send+Share+'static>(&'a mut self, oid: ObjectKey) -> Option<&'a mut T> {
+        self.get_common_mut().components.find_mut(oid)
+    }
+
+    fn remove_component<T: Clone+PartialEq+Send+Share+'static>(&mut self, oid: ObjectKey) -> bool {
+        self.get_common_mut().components.remove::<T>(oid)
+    }
+
+    fn component_table<'a, T: Clone+PartialEq+Send+Share+'static>(&'a self) -> Option<&'a BTreeMap<ObjectKey, T>> {
+        self.get_common().components.table()
+    }
+
+    fn component_iter<'a, T: Clone+PartialEq+Send+Share+'static>(&'a self) -> Option<BTreeMapIterator<'a, ObjectKey, T>> {
+        self.get_common().components.iter()
+    }
+
+    /// Sets how a component of type T that was changed by both sides
+    /// of a merge is resolved
+    fn set_merge_policy<T: Clone+PartialEq+Send+Share+'static>(&mut self, policy: Policy<T>) {
+        self.get_common_mut().components.set_merge_policy(policy)
+    }
+
+    /// Components of type T are saved in snapshots under name. Has to
+    /// be called again after loading, the loaded components of T are
+    /// decoded then.
+    fn set_component_snapshot<T: Snapshot+Clone+PartialEq+Send+Share+'static>(&mut self, name: &str) -> IoResult<()> {
+        self.get_common_mut().components.set_snapshot::<T>(name)
+    }
+
+    /// Posts an event, it can be read once the next frame started
+    fn post_event<T: Clone+Send+Share+'static>(&mut self, target: Target, event: T) {
+        self.get_common_mut().events.post(target, event)
+    }
+
+    /// events of type T posted during the last frame
+    fn events<'a, T: Clone+Send+Share+'static>(&'a self) -> Events<'a, T> {
+        self.get_common().events.iter()
+    }
+
+    /// events of type T posted during the last frame to oid or broadcast
+    fn events_for<'a, T: Clone+Send+Share+'static>(&'a self, oid: ObjectKey) -> Events<'a, T> {
+        self.get_common().events.iter_for(oid)
+    }
+
+    /// called at the start of a frame, the events posted during the
+    /// last frame become readable and the older ones are dropped
+    fn swap_events(&mut self) {
+        self.get_common_mut().events.swap()
+    }
 }
 
+// components are kept by CommonData itself, there is nothing else
 impl Common for CommonData {
     fn get_common<'a>(&'a self) -> &'a CommonData {self}
     fn get_common_mut<'a>(&'a mut self) -> &'a mut CommonData {self}
+    fn delete_data(&mut self, _: ObjectKey) {}
+    fn copy_data(&mut self, _: &Remap) {}
 }
 
 pub struct DirIter<'a> {
     common: &'a CommonData,
-    iter: BTreeMapIterator<'a, StringKey, ObjectKey>,
+    iter: Option<BTreeMapIterator<'a, StringKey, ObjectKey>>,
 }
 
 impl<'a> Iterator<(&'a str, ObjectKey)> for DirIter<'a> {
     fn next(&mut self) -> Option<(&'a str, ObjectKey)> {
-        match self.iter.next() {
+        let next = match self.iter {
+            Some(ref mut iter) => iter.next(),
+            None => None
+        };
+
+        match next {
             Some((sid, oid)) => {
                 Some((self.common.strings
                         .find(sid).expect("Found StringKey w/o Key").as_slice(),
@@ -234,4 +1076,53 @@ impl<'a> Iterator<(&'a str, ObjectKey)> for DirIter<'a> {
             None => None
         }
     }
-}
\ No newline at end of file
+}
+
+/// Maps the keys of a template subtree to the keys of its copy
+pub struct Remap {
+    keys: BTreeMap<ObjectKey, ObjectKey>
+}
+
+impl Remap {
+    /// the copy of key, keys outside of the template are unchanged
+    pub fn get(&self, key: ObjectKey) -> ObjectKey {
+        match self.keys.find(&key) {
+            Some(new) => *new,
+            None => key
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> BTreeMapIterator<'a, ObjectKey, ObjectKey> {
+        self.keys.iter()
+    }
+}
+
+pub struct ObjectKeys<'a> {
+    iter: BTreeMapIterator<'a, ObjectKey, Object>
+}
+
+impl<'a> Iterator<&'a ObjectKey> for ObjectKeys<'a> {
+    fn next(&mut self) -> Option<&'a ObjectKey> {
+        self.iter.next().map(|(k, _)| k)
+    }
+}
+
+pub struct TaggedIter<'a, I> {
+    common: &'a CommonData,
+    iter: I,
+    mask: Tags
+}
+
+impl<'a, I: Iterator<&'a ObjectKey>> Iterator<&'a ObjectKey> for TaggedIter<'a, I> {
+    fn next(&mut self) -> Option<&'a ObjectKey> {
+        loop {
+            let key = match self.iter.next() {
+                Some(key) => key,
+                None => return None
+            };
+            if matches_tags(self.common.tags(*key), self.mask) {
+                return Some(key);
+            }
+        }
+    }
+}
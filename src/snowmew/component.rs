@@ -0,0 +1,390 @@
+use std::any::{Any, AnyRefExt, AnyMutRefExt};
+use std::collections::HashMap;
+use std::intrinsics::TypeId;
+use std::io::{IoResult, MemWriter, MemReader};
+
+use cow::btree::{BTreeMap, BTreeMapIterator};
+
+use common::ObjectKey;
+use diff::diff;
+use lockstep::table_hash_delta;
+use snapshot::{Snapshot, invalid, read_bytes};
+use merge::{Policy, merge_map};
+use stats::{TableStats, table_stats};
+
+trait Table {
+    fn as_any<'a>(&'a self) -> &'a Any;
+    fn as_any_mut<'a>(&'a mut self) -> &'a mut Any;
+    fn clone_table(&self) -> Box<Table+Send+Share>;
+    fn remove_key(&mut self, key: ObjectKey) -> bool;
+    fn copy_key(&mut self, from: ObjectKey, to: ObjectKey);
+    fn changed_since(&self, base: Option<&Table>) -> bool;
+    fn table_stats(&self, other: Option<&Table>) -> TableStats;
+}
+
+impl<T: Clone+PartialEq+Send+Share+'static> Table for BTreeMap<ObjectKey, T> {
+    fn as_any<'a>(&'a self) -> &'a Any { self as &Any }
+    fn as_any_mut<'a>(&'a mut self) -> &'a mut Any { self as &mut Any }
+    fn clone_table(&self) -> Box<Table+Send+Share> { box self.clone() as Box<Table+Send+Share> }
+    fn remove_key(&mut self, key: ObjectKey) -> bool { self.remove(&key) }
+    fn copy_key(&mut self, from: ObjectKey, to: ObjectKey) {
+        let value = self.find(&from).map(|v| v.clone());
+        match value {
+            Some(v) => { self.insert(to, v); }
+            None => ()
+        }
+    }
+
+    fn changed_since(&self, base: Option<&Table>) -> bool {
+        match base.and_then(|b| b.as_any().as_ref::<BTreeMap<ObjectKey, T>>()) {
+            Some(base) => diff(base, self).next().is_some(),
+            None => self.iter().next().is_some()
+        }
+    }
+
+    fn table_stats(&self, other: Option<&Table>) -> TableStats {
+        let other = other.and_then(|o| o.as_any().as_ref::<BTreeMap<ObjectKey, T>>());
+        table_stats("components", self, other)
+    }
+}
+
+trait Merger {
+    fn merge_tables(&self, base: Option<&Table>, ours: &Table, theirs: &Table) -> Box<Table+Send+Share>;
+    fn clone_merger(&self) -> Box<Merger+Send+Share>;
+}
+
+impl<T: Clone+PartialEq+Send+Share+'static> Merger for Policy<T> {
+    fn merge_tables(&self, base: Option<&Table>, ours: &Table, theirs: &Table) -> Box<Table+Send+Share> {
+        let empty = BTreeMap::new();
+        let base = base.and_then(|b| b.as_any().as_ref::<BTreeMap<ObjectKey, T>>()).unwrap_or(&empty);
+        let ours = ours.as_any().as_ref::<BTreeMap<ObjectKey, T>>()
+                       .expect("component table has the wrong type");
+        let theirs = theirs.as_any().as_ref::<BTreeMap<ObjectKey, T>>()
+                           .expect("component table has the wrong type");
+        box merge_map(base, ours, theirs, self) as Box<Table+Send+Share>
+    }
+
+    fn clone_merger(&self) -> Box<Merger+Send+Share> {
+        box self.clone() as Box<Merger+Send+Share>
+    }
+}
+
+// every value is written on its own with its length in front, so a
+// table can be kept as bytes until its type is registered
+type Blobs = BTreeMap<ObjectKey, Vec<u8>>;
+
+fn save_blobs(blobs: &Blobs, w: &mut Writer) -> IoResult<()> {
+    try!(w.write_le_u32(blobs.len() as u32));
+    for (key, bytes) in blobs.iter() {
+        try!(key.save(w));
+        try!(w.write_le_u32(bytes.len() as u32));
+        try!(w.write(bytes.as_slice()));
+    }
+    Ok(())
+}
+
+fn load_blobs(r: &mut Reader) -> IoResult<Blobs> {
+    let len = try!(r.read_le_u32());
+    let mut blobs = BTreeMap::new();
+    for _ in range(0, len) {
+        let key = try!(Snapshot::load(r));
+        let size = try!(r.read_le_u32());
+        blobs.insert(key, try!(read_bytes(r, size as uint)));
+    }
+    Ok(blobs)
+}
+
+fn save_table<T: Snapshot+Clone+PartialEq+Send+Share+'static>(table: &Table) -> IoResult<Blobs> {
+    let table = table.as_any().as_ref::<BTreeMap<ObjectKey, T>>()
+                     .expect("component table has the wrong type");
+    let mut blobs = BTreeMap::new();
+    for (key, value) in table.iter() {
+        let mut w = MemWriter::new();
+        try!(value.save(&mut w));
+        blobs.insert(*key, w.unwrap());
+    }
+    Ok(blobs)
+}
+
+fn load_table<T: Snapshot+Clone+PartialEq+Send+Share+'static>(blobs: &Blobs, table: &mut Table) -> IoResult<()> {
+    let table = table.as_any_mut().as_mut::<BTreeMap<ObjectKey, T>>()
+                     .expect("component table has the wrong type");
+    for (key, bytes) in blobs.iter() {
+        let mut r = MemReader::new(bytes.clone());
+        table.insert(*key, try!(Snapshot::load(&mut r)));
+    }
+    Ok(())
+}
+
+fn hash_table<T: Snapshot+Clone+PartialEq+Send+Share+'static>(name: &str, base: Option<&Table>, table: &Table) -> u64 {
+    let empty = BTreeMap::new();
+    let base = base.and_then(|b| b.as_any().as_ref::<BTreeMap<ObjectKey, T>>()).unwrap_or(&empty);
+    let table = table.as_any().as_ref::<BTreeMap<ObjectKey, T>>()
+                     .expect("component table has the wrong type");
+    table_hash_delta(name, base, table, |a, b| a == b)
+}
+
+// the name a component table is saved under
+struct Format {
+    name: String,
+    save: fn(&Table) -> IoResult<Blobs>,
+    load: fn(&Blobs, &mut Table) -> IoResult<()>,
+    hash: fn(&str, Option<&Table>, &Table) -> u64
+}
+
+impl Clone for Format {
+    fn clone(&self) -> Format {
+        Format {
+            name: self.name.clone(),
+            save: self.save,
+            load: self.load,
+            hash: self.hash
+        }
+    }
+}
+
+struct Entry {
+    table: Box<Table+Send+Share>,
+    merger: Option<Box<Merger+Send+Share>>,
+    format: Option<Format>
+}
+
+// cloning a table only clones the root of the BTreeMap
+impl Clone for Entry {
+    fn clone(&self) -> Entry {
+        Entry {
+            table: self.table.clone_table(),
+            merger: self.merger.as_ref().map(|m| m.clone_merger()),
+            format: self.format.clone()
+        }
+    }
+}
+
+/// Tables of user defined components, one `BTreeMap<ObjectKey, T>`
+/// per component type.
+///
+/// Only tables that were given a name with `set_snapshot` are saved.
+/// A loaded table stays undecoded in `pending` until its type is
+/// registered again.
+#[deriving(Clone)]
+pub struct ComponentData {
+    tables: HashMap<TypeId, Entry>,
+    pending: HashMap<String, Blobs>
+}
+
+impl ComponentData {
+    pub fn new() -> ComponentData {
+        ComponentData {
+            tables: HashMap::new(),
+            pending: HashMap::new()
+        }
+    }
+
+    pub fn table<'a, T: Clone+PartialEq+Send+Share+'static>(&'a self) -> Option<&'a BTreeMap<ObjectKey, T>> {
+        match self.tables.find(&TypeId::of::<T>()) {
+            Some(entry) => entry.table.as_any().as_ref::<BTreeMap<ObjectKey, T>>(),
+            None => None
+        }
+    }
+
+    pub fn table_mut<'a, T: Clone+PartialEq+Send+Share+'static>(&'a mut self) -> &'a mut BTreeMap<ObjectKey, T> {
+        let tid = TypeId::of::<T>();
+        if self.tables.find(&tid).is_none() {
+            let table: BTreeMap<ObjectKey, T> = BTreeMap::new();
+            self.tables.insert(tid, Entry{table: box table as Box<Table+Send+Share>,
+                                          merger: None,
+                                          format: None});
+        }
+
+        let entry = self.tables.find_mut(&tid).unwrap();
+        entry.table.as_any_mut().as_mut::<BTreeMap<ObjectKey, T>>()
+             .expect("component table has the wrong type")
+    }
+
+    /// every component table counted as one, component values that own
+    /// heap data are undercounted
+    pub fn stats(&self, other: Option<&ComponentData>) -> TableStats {
+        let mut stats = TableStats::new("components");
+        for (tid, entry) in self.tables.iter() {
+            let other = other.and_then(|o| o.tables.find(tid)).map(|e| &*e.table as &Table);
+            stats.add(&entry.table.table_stats(other));
+        }
+        stats
+    }
+
+    pub fn insert<T: Clone+PartialEq+Send+Share+'static>(&mut self, key: ObjectKey, value: T) -> bool {
+        self.table_mut::<T>().insert(key, value)
+    }
+
+    pub fn find<'a, T: Clone+PartialEq+Send+Share+'static>(&'a self, key: ObjectKey) -> Option<&'a T> {
+        match self.table::<T>() {
+            Some(table) => table.find(&key),
+            None => None
+        }
+    }
+
+    pub fn find_mut<'a, T: Clone+PartialEq+Send+Share+'static>(&'a mut self, key: ObjectKey) -> Option<&'a mut T> {
+        self.table_mut::<T>().find_mut(&key)
+    }
+
+    pub fn remove<T: Clone+PartialEq+Send+Share+'static>(&mut self, key: ObjectKey) -> bool {
+        match self.tables.find_mut(&TypeId::of::<T>()) {
+            Some(entry) => entry.table.remove_key(key),
+            None => false
+        }
+    }
+
+    pub fn iter<'a, T: Clone+PartialEq+Send+Share+'static>(&'a self) -> Option<BTreeMapIterator<'a, ObjectKey, T>> {
+        self.table::<T>().map(|table| table.iter())
+    }
+
+    /// copies every component attached to from onto to
+    pub fn copy(&mut self, from: ObjectKey, to: ObjectKey) {
+        for (_, entry) in self.tables.mut_iter() {
+            entry.table.copy_key(from, to);
+        }
+        for (_, blobs) in self.pending.mut_iter() {
+            let value = blobs.find(&from).map(|v| v.clone());
+            match value {
+                Some(v) => { blobs.insert(to, v); }
+                None => ()
+            }
+        }
+    }
+
+    /// Saves the table of T under name, anything that was loaded
+    /// under name is decoded into the table.
+    pub fn set_snapshot<T: Snapshot+Clone+PartialEq+Send+Share+'static>(&mut self, name: &str) -> IoResult<()> {
+        self.table_mut::<T>();
+        let entry = self.tables.find_mut(&TypeId::of::<T>()).unwrap();
+        entry.format = Some(Format {
+            name: name.to_string(),
+            save: save_table::<T>,
+            load: load_table::<T>,
+            hash: hash_table::<T>
+        });
+        match self.pending.pop(&name.to_string()) {
+            Some(blobs) => load_table::<T>(&blobs, &mut *entry.table),
+            None => Ok(())
+        }
+    }
+
+    /// What the change from old to self adds to the state hash. Only
+    /// named and pending tables are hashed, as only they are saved. A
+    /// table that was named since old is hashed whole.
+    pub fn state_hash_delta(&self, old: &ComponentData) -> u64 {
+        let empty = BTreeMap::new();
+        let mut delta = 0u64;
+        for (name, blobs) in self.pending.iter() {
+            let base = old.pending.find(name).unwrap_or(&empty);
+            delta += table_hash_delta(name.as_slice(), base, blobs, |a, b| a == b);
+        }
+        for (name, blobs) in old.pending.iter() {
+            if !self.pending.contains_key(name) {
+                delta += table_hash_delta(name.as_slice(), blobs, &empty, |a, b| a == b);
+            }
+        }
+        for (tid, entry) in self.tables.iter() {
+            match entry.format {
+                Some(ref format) => {
+                    let base = old.tables.find(tid).and_then(|e| {
+                        e.format.as_ref().map(|_| &*e.table as &Table)
+                    });
+                    delta += (format.hash)(format.name.as_slice(), base, &*entry.table);
+                }
+                None => ()
+            }
+        }
+        delta
+    }
+
+    /// Sets how a key of T that was changed by both sides of a merge
+    /// is resolved. Without a policy a table that was changed by both
+    /// sides fails the merge.
+    pub fn set_merge_policy<T: Clone+PartialEq+Send+Share+'static>(&mut self, policy: Policy<T>) {
+        self.table_mut::<T>();
+        let entry = self.tables.find_mut(&TypeId::of::<T>()).unwrap();
+        entry.merger = Some(box policy as Box<Merger+Send+Share>);
+    }
+
+    pub fn merge(base: &ComponentData, ours: &ComponentData, theirs: &ComponentData) -> Result<ComponentData, String> {
+        let mut out = ours.clone();
+        for (tid, t) in theirs.tables.iter() {
+            let b = base.tables.find(tid).map(|e| &*e.table as &Table);
+            let entry = match ours.tables.find(tid) {
+                None => t.clone(),
+                Some(o) => {
+                    let merger = o.merger.as_ref().or(t.merger.as_ref());
+                    match merger {
+                        Some(m) => Entry {
+                            table: m.merge_tables(b, &*o.table, &*t.table),
+                            merger: Some(m.clone_merger()),
+                            format: o.format.clone().or(t.format.clone())
+                        },
+                        None if !t.table.changed_since(b) => o.clone(),
+                        None if !o.table.changed_since(b) => t.clone(),
+                        None => return Err(format!("component table {} was changed by both sides \
+                                                    and has no merge policy", tid))
+                    }
+                }
+            };
+            out.tables.insert(*tid, entry);
+        }
+        for (name, blobs) in theirs.pending.iter() {
+            if !out.pending.contains_key(name) {
+                out.pending.insert(name.clone(), blobs.clone());
+            }
+        }
+        Ok(out)
+    }
+
+    /// removes every component attached to key
+    pub fn delete(&mut self, key: ObjectKey) {
+        for (_, entry) in self.tables.mut_iter() {
+            entry.table.remove_key(key);
+        }
+        for (_, blobs) in self.pending.mut_iter() {
+            blobs.remove(&key);
+        }
+    }
+}
+
+/// Tables are written sorted by name so equal data always gives the
+/// same bytes. Saving fails if a table that holds components has no
+/// name, rather then losing them.
+impl Snapshot for ComponentData {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        let mut tables = Vec::new();
+        for (_, entry) in self.tables.iter() {
+            match entry.format {
+                Some(ref format) => tables.push((format.name.clone(), try!((format.save)(&*entry.table)))),
+                None if entry.table.changed_since(None) => {
+                    return Err(invalid("component table has no snapshot name"));
+                }
+                None => ()
+            }
+        }
+        for (name, blobs) in self.pending.iter() {
+            tables.push((name.clone(), blobs.clone()));
+        }
+        tables.sort_by(|&(ref a, _), &(ref b, _)| a.cmp(b));
+
+        try!(w.write_le_u32(tables.len() as u32));
+        for &(ref name, ref blobs) in tables.iter() {
+            try!(name.save(w));
+            try!(save_blobs(blobs, w));
+        }
+        Ok(())
+    }
+
+    fn load(r: &mut Reader) -> IoResult<ComponentData> {
+        let len = try!(r.read_le_u32());
+        let mut out = ComponentData::new();
+        for _ in range(0, len) {
+            let name: String = try!(Snapshot::load(r));
+            let blobs = try!(load_blobs(r));
+            out.pending.insert(name, blobs);
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,267 @@
+use std::io::stdio::stdin;
+use std::task::TaskBuilder;
+
+use glfw::{WindowEvent, CharEvent, KeyEvent, KeyEnter, KeyBackspace, KeyGraveAccent, Press, Repeat};
+
+use common::{Common, ObjectKey};
+use io::InputState;
+
+/// Runs a command, args are the words after the command's name
+pub type CommandFn<GD> = fn(&mut GD, &[&str]) -> Result<String, String>;
+
+/// A line of `inspect` output for an object, None if there is nothing
+/// to say about it
+pub type InspectFn<GD> = fn(&GD, ObjectKey) -> Option<String>;
+
+struct Command<GD> {
+    name: Vec<String>,
+    help: String,
+    run: CommandFn<GD>
+}
+
+/// Splits a line into words, double quotes group words with spaces
+pub fn parse(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut started = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    words.push(word.clone());
+                    word.truncate(0);
+                    started = false;
+                }
+            }
+            c => {
+                word.push_char(c);
+                started = true;
+            }
+        }
+    }
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+    if started {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// the object at path, "/" is the root
+pub fn lookup<GD: Common>(gd: &GD, path: &str) -> Result<ObjectKey, String> {
+    let path = path.trim_chars('/');
+    if path.len() == 0 {
+        return Ok(0);
+    }
+    match gd.find(path) {
+        Some(key) => Ok(key),
+        None => Err(format!("{} not found", path))
+    }
+}
+
+fn ls<GD: Common>(gd: &mut GD, args: &[&str]) -> Result<String, String> {
+    let dir = try!(lookup(gd, if args.len() == 0 { "/" } else { args[0] }));
+    let mut out = Vec::new();
+    for (name, key) in gd.walk_dir(dir) {
+        let slash = if gd.walk_dir(key).next().is_some() { "/" } else { "" };
+        out.push(format!("{}{}\t{}", name, slash, key));
+    }
+    Ok(out.connect("\n"))
+}
+
+fn find<GD: Common>(gd: &mut GD, args: &[&str]) -> Result<String, String> {
+    if args.len() != 1 {
+        return Err("usage: find <pattern>".to_string());
+    }
+    let found: Vec<String> = gd.find_all(args[0]).map(|key| format!("{}\t{}", gd.name(key), key)).collect();
+    Ok(found.connect("\n"))
+}
+
+fn spawn<GD: Common>(gd: &mut GD, args: &[&str]) -> Result<String, String> {
+    if args.len() < 2 || args.len() > 3 {
+        return Err("usage: spawn <template> <name> [parent]".to_string());
+    }
+    let template = try!(lookup(gd, args[0]));
+    let parent = if args.len() == 3 { Some(try!(lookup(gd, args[2]))) } else { None };
+    match gd.instantiate(template, parent, args[1]) {
+        Some(remap) => Ok(format!("{}", remap.get(template))),
+        None => Err(format!("could not spawn {}", args[1]))
+    }
+}
+
+fn delete<GD: Common>(gd: &mut GD, args: &[&str]) -> Result<String, String> {
+    if args.len() != 1 {
+        return Err("usage: delete <path>".to_string());
+    }
+    let key = try!(lookup(gd, args[0]));
+    let deleted = gd.delete_subtree(key);
+    Ok(format!("deleted {} objects", deleted.len()))
+}
+
+/// A command line into the running game. The built-in commands only use
+/// `Common`, the other subsystems and games register their own. A command
+/// registered under the name of another one replaces it.
+pub struct Console<GD> {
+    commands: Vec<Command<GD>>,
+    inspectors: Vec<InspectFn<GD>>,
+    line: String,
+    open: bool,
+    stdin: Option<Receiver<String>>
+}
+
+impl<GD: Common> Console<GD> {
+    pub fn new() -> Console<GD> {
+        let mut console = Console {
+            commands: Vec::new(),
+            inspectors: Vec::new(),
+            line: String::new(),
+            open: false,
+            stdin: None
+        };
+        console.register("ls", "ls [path], lists the children of path", ls::<GD>);
+        console.register("find", "find <pattern>, lists the objects matching a path pattern", find::<GD>);
+        console.register("spawn", "spawn <template> <name> [parent], copies template", spawn::<GD>);
+        console.register("delete", "delete <path>, deletes path and its children", delete::<GD>);
+        console
+    }
+
+    /// name can be more than one word, eg. "set position"
+    pub fn register(&mut self, name: &str, help: &str, run: CommandFn<GD>) {
+        let name: Vec<String> = name.words().map(|w| w.to_string()).collect();
+        self.commands.retain(|c| c.name != name);
+        self.commands.push(Command {
+            name: name,
+            help: help.to_string(),
+            run: run
+        });
+    }
+
+    /// adds to what `inspect` prints about an object
+    pub fn register_inspector(&mut self, inspect: InspectFn<GD>) {
+        self.inspectors.push(inspect);
+    }
+
+    fn help(&self) -> String {
+        let mut out = vec!("help, lists the commands".to_string(),
+                           "inspect <path>, prints what is known about path".to_string());
+        for c in self.commands.iter() {
+            out.push(c.help.clone());
+        }
+        out.connect("\n")
+    }
+
+    fn inspect(&self, gd: &GD, args: &[&str]) -> Result<String, String> {
+        if args.len() != 1 {
+            return Err("usage: inspect <path>".to_string());
+        }
+        let key = try!(lookup(gd, args[0]));
+        let mut out = vec!(format!("{}\tkey {} tags {:x}", gd.name(key), key, gd.tags(key)));
+        for inspect in self.inspectors.iter() {
+            match (*inspect)(gd, key) {
+                Some(line) => out.push(line),
+                None => ()
+            }
+        }
+        Ok(out.connect("\n"))
+    }
+
+    /// runs a line, the command with the longest matching name is used
+    pub fn run(&self, gd: &mut GD, line: &str) -> Result<String, String> {
+        let words = try!(parse(line));
+        let words: Vec<&str> = words.iter().map(|w| w.as_slice()).collect();
+        match words.as_slice() {
+            [] => return Ok(String::new()),
+            ["help", ..] => return Ok(self.help()),
+            ["inspect", ..args] => return self.inspect(gd, args),
+            _ => ()
+        }
+
+        let mut best: Option<&Command<GD>> = None;
+        for c in self.commands.iter() {
+            let matches = c.name.len() <= words.len() &&
+                          c.name.iter().zip(words.iter()).all(|(a, b)| a.as_slice() == *b);
+            if matches && best.map_or(true, |b| b.name.len() < c.name.len()) {
+                best = Some(c);
+            }
+        }
+
+        match best {
+            Some(c) => (c.run)(gd, words.slice_from(c.name.len())),
+            None => Err(format!("unknown command {}, try help", words[0]))
+        }
+    }
+
+    /// lines typed on stdin are run by `poll` as well
+    pub fn read_stdin(&mut self) {
+        let (send, recv) = channel();
+        TaskBuilder::new().named("console stdin".to_string()).spawn(proc() {
+            for line in stdin().lines() {
+                match line {
+                    Ok(line) => if send.send_opt(line).is_err() { break },
+                    Err(_) => break
+                }
+            }
+        });
+        self.stdin = Some(recv);
+    }
+
+    /// while open the console takes the typed characters, the game
+    /// should ignore the keyboard
+    pub fn is_open(&self) -> bool { self.open }
+
+    fn execute(&self, gd: &mut GD, line: &str) {
+        match self.run(gd, line) {
+            Ok(ref out) if out.len() == 0 => (),
+            Ok(out) => println!("{}", out),
+            Err(err) => println!("error: {}", err)
+        }
+    }
+
+    /// Runs the lines typed since last. The console is opened and closed
+    /// with the grave accent key, enter runs the typed line.
+    pub fn poll(&mut self, gd: &mut GD, input: &InputState, last: &InputState) {
+        let mut events: Vec<WindowEvent> = input.iter_delta(last).map(|(_, e)| e).collect();
+        events.reverse();
+        for event in events.move_iter() {
+            match event {
+                KeyEvent(KeyGraveAccent, _, Press, _) => {
+                    self.open = !self.open;
+                    self.line.truncate(0);
+                }
+                _ if !self.open => (),
+                KeyEvent(KeyEnter, _, Press, _) => {
+                    let line = self.line.clone();
+                    self.line.truncate(0);
+                    println!("> {}", line);
+                    self.execute(gd, line.as_slice());
+                }
+                KeyEvent(KeyBackspace, _, Press, _) | KeyEvent(KeyBackspace, _, Repeat, _) => {
+                    self.line.pop_char();
+                }
+                CharEvent('`') => (),
+                CharEvent(c) => self.line.push_char(c),
+                _ => ()
+            }
+        }
+
+        let mut lines = Vec::new();
+        match self.stdin {
+            Some(ref stdin) => loop {
+                match stdin.try_recv() {
+                    Ok(line) => lines.push(line),
+                    Err(_) => break
+                }
+            },
+            None => ()
+        }
+        for line in lines.iter() {
+            self.execute(gd, line.as_slice());
+        }
+    }
+}
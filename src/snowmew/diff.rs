@@ -0,0 +1,94 @@
+use std::iter::Peekable;
+
+use cow::btree::{BTreeMap, BTreeMapIterator, BTreeSet};
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum Change<K> {
+    Added(K),
+    Removed(K),
+    Modified(K)
+}
+
+impl<K: Clone> Change<K> {
+    pub fn key(&self) -> K {
+        match *self {
+            Added(ref k) | Removed(ref k) | Modified(ref k) => k.clone()
+        }
+    }
+}
+
+/// true if both values are the same slot in memory. Equal values
+/// can be in different slots, a write copies the entries next to the
+/// written one as well, so this is only a shortcut for a comparison.
+pub fn shared<V>(a: &V, b: &V) -> bool {
+    a as *const V == b as *const V
+}
+
+/// true if both maps hold equal entries
+pub fn same_map<K: Clone+Ord+Send+Share, V: Clone+PartialEq+Send+Share>
+    (a: &BTreeMap<K, V>, b: &BTreeMap<K, V>) -> bool {
+    shared(a, b) || (a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x == y))
+}
+
+pub fn same_set<K: Clone+Ord+Send+Share>(a: &BTreeSet<K>, b: &BTreeSet<K>) -> bool {
+    shared(a, b) || (a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x == y))
+}
+
+/// Walks two generations of the same table in key order and reports
+/// the keys for which `same` returns false.
+///
+/// Every entry is visited. Skipping subtrees the two generations share
+/// needs node identity from `cow::btree`, which only exposes entries,
+/// so that part of the request is out of scope until cow grows it.
+/// The `shared` check still skips the comparison of shared values.
+pub struct Diff<'a, K, V> {
+    old: Peekable<(&'a K, &'a V), BTreeMapIterator<'a, K, V>>,
+    new: Peekable<(&'a K, &'a V), BTreeMapIterator<'a, K, V>>,
+    same: |&V, &V|:'a -> bool
+}
+
+/// keys whose values are not equal, added or removed
+pub fn diff<'a, K: Clone+Ord+Send+Share, V: Clone+PartialEq+Send+Share>
+    (old: &'a BTreeMap<K, V>, new: &'a BTreeMap<K, V>) -> Diff<'a, K, V> {
+    diff_by(old, new, |a, b| shared(a, b) || a == b)
+}
+
+pub fn diff_by<'a, K: Clone+Ord+Send+Share, V: Clone+Send+Share>
+    (old: &'a BTreeMap<K, V>, new: &'a BTreeMap<K, V>, same: |&V, &V|:'a -> bool) -> Diff<'a, K, V> {
+    Diff {
+        old: old.iter().peekable(),
+        new: new.iter().peekable(),
+        same: same
+    }
+}
+
+impl<'a, K: Clone+Ord, V> Iterator<Change<K>> for Diff<'a, K, V> {
+    fn next(&mut self) -> Option<Change<K>> {
+        loop {
+            let order = match (self.old.peek(), self.new.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => Less,
+                (None, Some(_)) => Greater,
+                (Some(&(ko, _)), Some(&(kn, _))) => ko.cmp(kn)
+            };
+
+            match order {
+                Less => {
+                    let (k, _) = self.old.next().unwrap();
+                    return Some(Removed(k.clone()));
+                }
+                Greater => {
+                    let (k, _) = self.new.next().unwrap();
+                    return Some(Added(k.clone()));
+                }
+                Equal => {
+                    let (k, vo) = self.old.next().unwrap();
+                    let (_, vn) = self.new.next().unwrap();
+                    if !(self.same)(vo, vn) {
+                        return Some(Modified(k.clone()));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::any::{Any, AnyRefExt, AnyMutRefExt};
+use std::collections::HashMap;
+use std::intrinsics::TypeId;
+use std::mem;
+use std::slice::Items;
+
+use sync::Arc;
+
+use common::ObjectKey;
+
+/// Who an event is for
+#[deriving(Clone, PartialEq, Show)]
+pub enum Target {
+    Broadcast,
+    To(ObjectKey)
+}
+
+trait Queue {
+    fn as_any<'a>(&'a self) -> &'a Any;
+    fn as_any_mut<'a>(&'a mut self) -> &'a mut Any;
+    fn clone_queue(&self) -> Box<Queue+Send+Share>;
+    fn len(&self) -> uint;
+    fn append_from(&mut self, other: &Queue, skip: uint);
+}
+
+impl<T: Clone+Send+Share+'static> Queue for Vec<(Target, T)> {
+    fn as_any<'a>(&'a self) -> &'a Any { self as &Any }
+    fn as_any_mut<'a>(&'a mut self) -> &'a mut Any { self as &mut Any }
+    fn clone_queue(&self) -> Box<Queue+Send+Share> { box self.clone() as Box<Queue+Send+Share> }
+    fn len(&self) -> uint { self.len() }
+    fn append_from(&mut self, other: &Queue, skip: uint) {
+        let other = other.as_any().as_ref::<Vec<(Target, T)>>()
+                         .expect("event queue has the wrong type");
+        self.push_all(other.slice_from(skip));
+    }
+}
+
+struct Entry {
+    queue: Box<Queue+Send+Share>
+}
+
+impl Clone for Entry {
+    fn clone(&self) -> Entry {
+        Entry {
+            queue: self.queue.clone_queue()
+        }
+    }
+}
+
+/// Typed events, one queue per event type. Events posted during a
+/// frame are read during the next one, once `swap` was called.
+///
+/// The queues are shared between the generations they were cloned
+/// into, the first post to a shared generation copies them.
+#[deriving(Clone)]
+pub struct EventData {
+    current: Arc<HashMap<TypeId, Entry>>,
+    previous: Arc<HashMap<TypeId, Entry>>
+}
+
+impl EventData {
+    pub fn new() -> EventData {
+        EventData {
+            current: Arc::new(HashMap::new()),
+            previous: Arc::new(HashMap::new())
+        }
+    }
+
+    pub fn post<T: Clone+Send+Share+'static>(&mut self, target: Target, event: T) {
+        let tid = TypeId::of::<T>();
+        let current = self.current.make_unique();
+        if current.find(&tid).is_none() {
+            let queue: Vec<(Target, T)> = Vec::new();
+            current.insert(tid, Entry{queue: box queue as Box<Queue+Send+Share>});
+        }
+
+        let entry = current.find_mut(&tid).unwrap();
+        entry.queue.as_any_mut().as_mut::<Vec<(Target, T)>>()
+             .expect("event queue has the wrong type")
+             .push((target, event));
+    }
+
+    /// events of type T posted during the last frame
+    pub fn iter<'a, T: Clone+Send+Share+'static>(&'a self) -> Events<'a, T> {
+        Events {
+            iter: self.queue::<T>().map(|q| q.iter()),
+            key: None
+        }
+    }
+
+    /// events of type T posted during the last frame to key, or to everyone
+    pub fn iter_for<'a, T: Clone+Send+Share+'static>(&'a self, key: ObjectKey) -> Events<'a, T> {
+        Events {
+            iter: self.queue::<T>().map(|q| q.iter()),
+            key: Some(key)
+        }
+    }
+
+    fn queue<'a, T: Clone+Send+Share+'static>(&'a self) -> Option<&'a Vec<(Target, T)>> {
+        match self.previous.find(&TypeId::of::<T>()) {
+            Some(entry) => entry.queue.as_any().as_ref::<Vec<(Target, T)>>(),
+            None => None
+        }
+    }
+
+    /// the events posted so far become the ones that are read
+    pub fn swap(&mut self) {
+        self.previous = mem::replace(&mut self.current, Arc::new(HashMap::new()));
+    }
+
+    /// the queues only grow during a frame, anything theirs posted
+    /// after base is appended to what ours posted
+    pub fn merge(base: &EventData, ours: &EventData, theirs: &EventData) -> EventData {
+        let mut out = ours.clone();
+        for (tid, t) in theirs.current.iter() {
+            let skip = match base.current.find(tid) {
+                Some(entry) => entry.queue.len(),
+                None => 0
+            };
+            let current = out.current.make_unique();
+            if current.contains_key(tid) {
+                current.find_mut(tid).unwrap().queue.append_from(&*t.queue, skip);
+            } else {
+                current.insert(*tid, t.clone());
+            }
+        }
+        out
+    }
+}
+
+pub struct Events<'a, T> {
+    iter: Option<Items<'a, (Target, T)>>,
+    key: Option<ObjectKey>
+}
+
+impl<'a, T> Iterator<(Target, &'a T)> for Events<'a, T> {
+    fn next(&mut self) -> Option<(Target, &'a T)> {
+        let iter = match self.iter {
+            Some(ref mut iter) => iter,
+            None => return None
+        };
+
+        loop {
+            match iter.next() {
+                None => return None,
+                Some(&(target, ref event)) => match (self.key, target) {
+                    (None, _) | (_, Broadcast) => return Some((target, event)),
+                    (Some(key), To(to)) if key == to => return Some((target, event)),
+                    _ => ()
+                }
+            }
+        }
+    }
+}
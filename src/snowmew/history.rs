@@ -0,0 +1,78 @@
+/// A bounded list of labelled generations of the game state. Every
+/// generation is a clone of the database, which shares everything
+/// that was not written since with the generations around it.
+pub struct History<T> {
+    entries: Vec<(String, T)>,
+    current: uint,
+    capacity: uint
+}
+
+impl<T: Clone> History<T> {
+    /// capacity is the number of generations kept, including the current one
+    pub fn new(capacity: uint, label: &str, state: &T) -> History<T> {
+        assert!(capacity > 0);
+        History {
+            entries: vec!((label.to_string(), state.clone())),
+            current: 0,
+            capacity: capacity
+        }
+    }
+
+    /// Records a new generation, anything that could be redone is lost
+    pub fn checkpoint(&mut self, label: &str, state: &T) {
+        self.entries.truncate(self.current + 1);
+        self.entries.push((label.to_string(), state.clone()));
+        if self.entries.len() > self.capacity {
+            self.entries.remove(0);
+        }
+        self.current = self.entries.len() - 1;
+    }
+
+    pub fn undo<'a>(&'a mut self) -> Option<&'a T> {
+        if self.current == 0 {
+            return None;
+        }
+        self.current -= 1;
+        Some(self.current())
+    }
+
+    pub fn redo<'a>(&'a mut self) -> Option<&'a T> {
+        if self.current + 1 >= self.entries.len() {
+            return None;
+        }
+        self.current += 1;
+        Some(self.current())
+    }
+
+    pub fn current<'a>(&'a self) -> &'a T {
+        let &(_, ref state) = self.entries.get(self.current);
+        state
+    }
+
+    pub fn label<'a>(&'a self) -> &'a str {
+        let &(ref label, _) = self.entries.get(self.current);
+        label.as_slice()
+    }
+
+    /// label of the generation undo would go back to
+    pub fn undo_label<'a>(&'a self) -> Option<&'a str> {
+        if self.current == 0 {
+            None
+        } else {
+            let &(ref label, _) = self.entries.get(self.current - 1);
+            Some(label.as_slice())
+        }
+    }
+
+    /// label of the generation redo would go forward to
+    pub fn redo_label<'a>(&'a self) -> Option<&'a str> {
+        if self.current + 1 >= self.entries.len() {
+            None
+        } else {
+            let &(ref label, _) = self.entries.get(self.current + 1);
+            Some(label.as_slice())
+        }
+    }
+
+    pub fn len(&self) -> uint { self.entries.len() }
+}
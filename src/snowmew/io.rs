@@ -1,23 +1,32 @@
 use sync::Arc;
 #[cfg(target_os="linux")]
 use libc::c_void;
+use libc::c_int;
 
 use glfw::{WindowEvent, Key, MouseButton, Glfw, Context};
 use glfw::{Press, Release, KeyEvent, MouseButtonEvent, CursorPosEvent};
 use glfw::{CloseEvent, FocusEvent, FullScreen};
+use glfw::{PosEvent, SizeEvent, RefreshEvent, IconifyEvent, FramebufferSizeEvent};
+use glfw::{CursorEnterEvent, ScrollEvent, CharEvent, Action, Modifiers};
 use glfw::{Windowed, RenderContext};
 use glfw;
 use gl;
 use gfx;
 
 use semver;
+use std::char;
 use std::collections::HashSet;
+use std::io;
+use std::io::{IoResult, File, BufferedReader, BufferedWriter};
 use collections::TrieMap;
 
 use cgmath::quaternion::Quaternion;
 
 use ovr;
 
+use common::FrameInfo;
+use snapshot::{Snapshot, invalid};
+
 pub type WindowId = uint;
 
 #[cfg(target_os="macos")]
@@ -109,6 +118,26 @@ impl InputState {
         }
     }
 
+    /// the state of a window that does not exist, used when running
+    /// without a display
+    pub fn headless(size: (i32, i32)) -> InputState {
+        InputState {
+            history: None,
+            keyboard: HashSet::new(),
+            mouse: HashSet::new(),
+            should_close: false,
+            focus: true,
+            framebuffer_size: size,
+            screen_size: size,
+            predicted: Quaternion::identity()
+        }
+    }
+
+    /// feeds an event as if the window had sent it
+    pub fn feed(&mut self, time: Option<f64>, event: WindowEvent) {
+        self.event(time, event)
+    }
+
     fn event(&mut self, time: Option<f64>, event: WindowEvent) {
         self.history = Some(Arc::new( InputHistory{
             older: self.history.clone(),
@@ -221,6 +250,381 @@ impl InputState {
     }
 }
 
+/// Stands in for the IOManager when the game runs without a window
+pub trait InputSource {
+    /// feeds the events for the step about to run
+    fn poll(&mut self, frame: &FrameInfo);
+    fn get(&self) -> InputState;
+}
+
+/// Input from a list of events, each event is fed at the start of
+/// the frame it was scripted for.
+pub struct ScriptedInput {
+    state: InputState,
+    events: Vec<(uint, WindowEvent)>,
+    next: uint
+}
+
+impl ScriptedInput {
+    pub fn new(size: (i32, i32)) -> ScriptedInput {
+        ScriptedInput {
+            state: InputState::headless(size),
+            events: Vec::new(),
+            next: 0
+        }
+    }
+
+    pub fn push(&mut self, frame: uint, event: WindowEvent) {
+        let mut idx = self.events.len();
+        while idx > 0 && self.events.get(idx-1).val0() > frame {
+            idx -= 1;
+        }
+        self.events.insert(idx, (frame, event));
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn poll(&mut self, frame: &FrameInfo) {
+        while self.next < self.events.len() {
+            let &(at, ref event) = self.events.get(self.next);
+            if at > frame.count {
+                break;
+            }
+            self.state.event(Some(frame.time), event.clone());
+            self.next += 1;
+        }
+    }
+
+    fn get(&self) -> InputState {
+        self.state.clone()
+    }
+}
+
+// "SNIR" in little endian
+static RECORDING_MAGIC: u32 = 0x52494e53;
+static RECORDING_VERSION: u32 = 2;
+
+// every key, button and action a recording can hold. They are
+// written as glfw's values and looked up when read, so a value this
+// build does not know is an error
+static KEYS: &'static [Key] = &[
+    glfw::KeySpace, glfw::KeyApostrophe, glfw::KeyComma, glfw::KeyMinus, glfw::KeyPeriod,
+    glfw::KeySlash, glfw::Key0, glfw::Key1, glfw::Key2, glfw::Key3, glfw::Key4, glfw::Key5,
+    glfw::Key6, glfw::Key7, glfw::Key8, glfw::Key9, glfw::KeySemicolon, glfw::KeyEqual,
+    glfw::KeyA, glfw::KeyB, glfw::KeyC, glfw::KeyD, glfw::KeyE, glfw::KeyF, glfw::KeyG,
+    glfw::KeyH, glfw::KeyI, glfw::KeyJ, glfw::KeyK, glfw::KeyL, glfw::KeyM, glfw::KeyN,
+    glfw::KeyO, glfw::KeyP, glfw::KeyQ, glfw::KeyR, glfw::KeyS, glfw::KeyT, glfw::KeyU,
+    glfw::KeyV, glfw::KeyW, glfw::KeyX, glfw::KeyY, glfw::KeyZ, glfw::KeyLeftBracket,
+    glfw::KeyBackslash, glfw::KeyRightBracket, glfw::KeyGraveAccent, glfw::KeyWorld1,
+    glfw::KeyWorld2, glfw::KeyEscape, glfw::KeyEnter, glfw::KeyTab, glfw::KeyBackspace,
+    glfw::KeyInsert, glfw::KeyDelete, glfw::KeyRight, glfw::KeyLeft, glfw::KeyDown, glfw::KeyUp,
+    glfw::KeyPageUp, glfw::KeyPageDown, glfw::KeyHome, glfw::KeyEnd, glfw::KeyCapsLock,
+    glfw::KeyScrollLock, glfw::KeyNumLock, glfw::KeyPrintScreen, glfw::KeyPause, glfw::KeyF1,
+    glfw::KeyF2, glfw::KeyF3, glfw::KeyF4, glfw::KeyF5, glfw::KeyF6, glfw::KeyF7, glfw::KeyF8,
+    glfw::KeyF9, glfw::KeyF10, glfw::KeyF11, glfw::KeyF12, glfw::KeyF13, glfw::KeyF14,
+    glfw::KeyF15, glfw::KeyF16, glfw::KeyF17, glfw::KeyF18, glfw::KeyF19, glfw::KeyF20,
+    glfw::KeyF21, glfw::KeyF22, glfw::KeyF23, glfw::KeyF24, glfw::KeyF25, glfw::KeyKp0,
+    glfw::KeyKp1, glfw::KeyKp2, glfw::KeyKp3, glfw::KeyKp4, glfw::KeyKp5, glfw::KeyKp6,
+    glfw::KeyKp7, glfw::KeyKp8, glfw::KeyKp9, glfw::KeyKpDecimal, glfw::KeyKpDivide,
+    glfw::KeyKpMultiply, glfw::KeyKpSubtract, glfw::KeyKpAdd, glfw::KeyKpEnter,
+    glfw::KeyKpEqual, glfw::KeyLeftShift, glfw::KeyLeftControl, glfw::KeyLeftAlt,
+    glfw::KeyLeftSuper, glfw::KeyRightShift, glfw::KeyRightControl, glfw::KeyRightAlt,
+    glfw::KeyRightSuper, glfw::KeyMenu
+];
+
+static MOUSE_BUTTONS: &'static [MouseButton] = &[
+    glfw::MouseButton1, glfw::MouseButton2, glfw::MouseButton3, glfw::MouseButton4,
+    glfw::MouseButton5, glfw::MouseButton6, glfw::MouseButton7, glfw::MouseButton8
+];
+
+static ACTIONS: &'static [Action] = &[glfw::Release, glfw::Press, glfw::Repeat];
+
+fn load_key(r: &mut Reader) -> IoResult<Key> {
+    let v = try!(r.read_le_i32());
+    match KEYS.iter().find(|k| **k as i32 == v) {
+        Some(k) => Ok(*k),
+        None => Err(invalid("unknown key"))
+    }
+}
+
+fn load_mouse_button(r: &mut Reader) -> IoResult<MouseButton> {
+    let v = try!(r.read_le_i32());
+    match MOUSE_BUTTONS.iter().find(|b| **b as i32 == v) {
+        Some(b) => Ok(*b),
+        None => Err(invalid("unknown mouse button"))
+    }
+}
+
+fn load_action(r: &mut Reader) -> IoResult<Action> {
+    let v = try!(r.read_le_i32());
+    match ACTIONS.iter().find(|a| **a as i32 == v) {
+        Some(a) => Ok(*a),
+        None => Err(invalid("unknown key action"))
+    }
+}
+
+fn save_modifiers(w: &mut Writer, m: &Modifiers) -> IoResult<()> {
+    w.write_le_i32(m.bits() as i32)
+}
+
+fn load_modifiers(r: &mut Reader) -> IoResult<Modifiers> {
+    match Modifiers::from_bits(try!(r.read_le_i32()) as c_int) {
+        Some(m) => Ok(m),
+        None => Err(invalid("unknown key modifiers"))
+    }
+}
+
+impl Snapshot for WindowEvent {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        match *self {
+            PosEvent(x, y) => { try!(w.write_u8(0)); try!(x.save(w)); y.save(w) }
+            SizeEvent(x, y) => { try!(w.write_u8(1)); try!(x.save(w)); y.save(w) }
+            CloseEvent => w.write_u8(2),
+            RefreshEvent => w.write_u8(3),
+            FocusEvent(f) => { try!(w.write_u8(4)); f.save(w) }
+            IconifyEvent(i) => { try!(w.write_u8(5)); i.save(w) }
+            FramebufferSizeEvent(x, y) => { try!(w.write_u8(6)); try!(x.save(w)); y.save(w) }
+            MouseButtonEvent(ref button, ref action, ref mods) => {
+                try!(w.write_u8(7));
+                try!(w.write_le_i32(*button as i32));
+                try!(w.write_le_i32(*action as i32));
+                save_modifiers(w, mods)
+            }
+            CursorPosEvent(x, y) => { try!(w.write_u8(8)); try!(x.save(w)); y.save(w) }
+            CursorEnterEvent(e) => { try!(w.write_u8(9)); e.save(w) }
+            ScrollEvent(x, y) => { try!(w.write_u8(10)); try!(x.save(w)); y.save(w) }
+            KeyEvent(ref key, scancode, ref action, ref mods) => {
+                try!(w.write_u8(11));
+                try!(w.write_le_i32(*key as i32));
+                try!((scancode as i32).save(w));
+                try!(w.write_le_i32(*action as i32));
+                save_modifiers(w, mods)
+            }
+            CharEvent(c) => { try!(w.write_u8(12)); (c as u32).save(w) }
+        }
+    }
+
+    fn load(r: &mut Reader) -> IoResult<WindowEvent> {
+        Ok(match try!(r.read_u8()) {
+            0 => PosEvent(try!(Snapshot::load(r)), try!(Snapshot::load(r))),
+            1 => SizeEvent(try!(Snapshot::load(r)), try!(Snapshot::load(r))),
+            2 => CloseEvent,
+            3 => RefreshEvent,
+            4 => FocusEvent(try!(Snapshot::load(r))),
+            5 => IconifyEvent(try!(Snapshot::load(r))),
+            6 => FramebufferSizeEvent(try!(Snapshot::load(r)), try!(Snapshot::load(r))),
+            7 => {
+                let button = try!(load_mouse_button(r));
+                let action = try!(load_action(r));
+                MouseButtonEvent(button, action, try!(load_modifiers(r)))
+            }
+            8 => CursorPosEvent(try!(Snapshot::load(r)), try!(Snapshot::load(r))),
+            9 => CursorEnterEvent(try!(Snapshot::load(r))),
+            10 => ScrollEvent(try!(Snapshot::load(r)), try!(Snapshot::load(r))),
+            11 => {
+                let key = try!(load_key(r));
+                let scancode: i32 = try!(Snapshot::load(r));
+                let action = try!(load_action(r));
+                KeyEvent(key, scancode as c_int, action, try!(load_modifiers(r)))
+            }
+            12 => match char::from_u32(try!(Snapshot::load(r))) {
+                Some(c) => CharEvent(c),
+                None => return Err(invalid("invalid character event"))
+            },
+            _ => return Err(invalid("unknown window event"))
+        })
+    }
+}
+
+type Step = (uint, Vec<(Option<f64>, WindowEvent)>);
+
+// the events that arrived between last and input, oldest first
+fn step_events(input: &InputState, last: &InputState) -> Vec<(Option<f64>, WindowEvent)> {
+    let mut events: Vec<(Option<f64>, WindowEvent)> = input.iter_delta(last).collect();
+    events.reverse();
+    events
+}
+
+fn write_recording_header(w: &mut Writer, focus: bool, framebuffer_size: (i32, i32),
+                          screen_size: (i32, i32)) -> IoResult<()> {
+    try!(w.write_le_u32(RECORDING_MAGIC));
+    try!(w.write_le_u32(RECORDING_VERSION));
+    try!(focus.save(w));
+    try!(framebuffer_size.save(w));
+    screen_size.save(w)
+}
+
+// since version 2 each step is written on its own behind a marker,
+// the recording ends where the file does
+fn write_step(w: &mut Writer, step: &Step) -> IoResult<()> {
+    try!(w.write_u8(1));
+    step.save(w)
+}
+
+/// The input of a session, the events each step was handed along with
+/// the time glfw reported them at.
+#[deriving(Clone)]
+pub struct Recording {
+    focus: bool,
+    framebuffer_size: (i32, i32),
+    screen_size: (i32, i32),
+    steps: Vec<Step>
+}
+
+impl Recording {
+    /// start is the input before the first step
+    pub fn new(start: &InputState) -> Recording {
+        Recording {
+            focus: start.focus,
+            framebuffer_size: start.framebuffer_size,
+            screen_size: start.screen_size,
+            steps: Vec::new()
+        }
+    }
+
+    /// records the events that arrived between last and input
+    pub fn record(&mut self, frame: &FrameInfo, input: &InputState, last: &InputState) {
+        let events = step_events(input, last);
+        if events.len() != 0 {
+            self.steps.push((frame.count, events));
+        }
+    }
+
+    pub fn write(&self, w: &mut Writer) -> IoResult<()> {
+        try!(write_recording_header(w, self.focus, self.framebuffer_size, self.screen_size));
+        for step in self.steps.iter() {
+            try!(write_step(w, step));
+        }
+        Ok(())
+    }
+
+    pub fn read(r: &mut Reader) -> IoResult<Recording> {
+        if try!(r.read_le_u32()) != RECORDING_MAGIC {
+            return Err(invalid("not a snowmew input recording"));
+        }
+        let version = try!(r.read_le_u32());
+        if version == 0 || version > RECORDING_VERSION {
+            return Err(invalid("unsupported input recording version"));
+        }
+        let focus = try!(Snapshot::load(r));
+        let framebuffer_size = try!(Snapshot::load(r));
+        let screen_size = try!(Snapshot::load(r));
+        let steps = if version == 1 {
+            try!(Snapshot::load(r))
+        } else {
+            let mut steps = Vec::new();
+            loop {
+                match r.read_u8() {
+                    Ok(1) => steps.push(try!(Snapshot::load(r))),
+                    Ok(_) => return Err(invalid("invalid input recording step")),
+                    Err(ref err) if err.kind == io::EndOfFile => break,
+                    Err(err) => return Err(err)
+                }
+            }
+            steps
+        };
+        Ok(Recording {
+            focus: focus,
+            framebuffer_size: framebuffer_size,
+            screen_size: screen_size,
+            steps: steps
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> IoResult<()> {
+        let mut w = BufferedWriter::new(try!(File::create(path)));
+        try!(self.write(&mut w));
+        w.flush()
+    }
+
+    pub fn load(path: &Path) -> IoResult<Recording> {
+        let mut r = BufferedReader::new(try!(File::open(path)));
+        Recording::read(&mut r)
+    }
+
+    pub fn replay(self) -> Replay {
+        let mut state = InputState::headless(self.screen_size);
+        state.framebuffer_size = self.framebuffer_size;
+        state.focus = self.focus;
+        Replay {
+            state: state,
+            steps: self.steps,
+            next: 0
+        }
+    }
+}
+
+/// Writes the input of a session as it is recorded, in the format of
+/// `Recording::write`. Every step is flushed once it was written, a
+/// game that crashes leaves the steps up to the crash behind.
+pub struct Recorder<W> {
+    w: W
+}
+
+impl Recorder<BufferedWriter<File>> {
+    pub fn create(path: &Path, start: &InputState) -> IoResult<Recorder<BufferedWriter<File>>> {
+        Recorder::new(BufferedWriter::new(try!(File::create(path))), start)
+    }
+}
+
+impl<W: Writer> Recorder<W> {
+    /// start is the input before the first step
+    pub fn new(w: W, start: &InputState) -> IoResult<Recorder<W>> {
+        let mut w = w;
+        try!(write_recording_header(&mut w, start.focus, start.framebuffer_size, start.screen_size));
+        try!(w.flush());
+        Ok(Recorder { w: w })
+    }
+
+    /// writes the events that arrived between last and input
+    pub fn record(&mut self, frame: &FrameInfo, input: &InputState, last: &InputState) -> IoResult<()> {
+        let events = step_events(input, last);
+        if events.len() == 0 {
+            return Ok(());
+        }
+        try!(write_step(&mut self.w, &(frame.count, events)));
+        self.w.flush()
+    }
+
+    pub fn unwrap(self) -> W { self.w }
+}
+
+/// Feeds a recording back one step at a time. Each step sees the same
+/// events with the same timestamps as when it was recorded, a game
+/// that only reads its input and FrameInfo produces the same
+/// generations again.
+pub struct Replay {
+    state: InputState,
+    steps: Vec<(uint, Vec<(Option<f64>, WindowEvent)>)>,
+    next: uint
+}
+
+impl Replay {
+    /// true once every recorded step was fed
+    pub fn done(&self) -> bool {
+        self.next >= self.steps.len()
+    }
+}
+
+impl InputSource for Replay {
+    fn poll(&mut self, frame: &FrameInfo) {
+        while self.next < self.steps.len() {
+            let &(at, ref events) = self.steps.get(self.next);
+            if at > frame.count {
+                break;
+            }
+            for &(time, ref event) in events.iter() {
+                self.state.event(time, event.clone());
+            }
+            self.next += 1;
+        }
+    }
+
+    fn get(&self) -> InputState {
+        self.state.clone()
+    }
+}
+
 struct WindowHandle {
     window: glfw::Window,
     receiver: Receiver<(f64, WindowEvent)>,
@@ -24,16 +24,36 @@ extern crate OpenCL;
 extern crate gfx;
 extern crate ovr = "oculus-vr";
 
-pub use common::{ObjectKey};
+pub use common::{ObjectKey, FrameInfo};
 pub use io::IOManager;
+pub use manager::{ActiveManager, PassiveManager, Scheduler};
+pub use merge::Merge;
 
+use common::Common;
+use io::InputSource;
 use sync::Arc;
 use OpenCL::hl::{Device, get_platforms, GPU, CPU};
+use std::io::IoResult;
 use std::io::timer::Timer;
+use time::precise_time_s;
 
 pub mod common;
 pub mod camera;
 pub mod io;
+pub mod snapshot;
+pub mod diff;
+pub mod history;
+pub mod component;
+pub mod query;
+pub mod manager;
+pub mod merge;
+pub mod event;
+pub mod replicate;
+pub mod net;
+pub mod lockstep;
+pub mod console;
+pub mod stats;
+pub mod parallel;
 
 fn get_cl() -> Option<Arc<Device>> {
     let platforms = get_platforms();
@@ -120,20 +140,48 @@ impl DisplayConfig {
 
 pub trait Render<T> {
     fn update(&mut self, db: T, scene: ObjectKey, camera: ObjectKey);
+
+    // last is the frame before db, frame.alpha is how far the display
+    // time is between the two. By default only db is drawn.
+    fn update_frame(&mut self, _last: T, db: T, _frame: &FrameInfo, scene: ObjectKey, camera: ObjectKey) {
+        self.update(db, scene, camera)
+    }
 }
 
 pub trait RenderFactory<T, R: Render<T>> {
     fn init(self, im: &IOManager, window: io::Window, size: (i32, i32), cl: Option<Arc<Device>>) -> R;
 }
 
+/// Draws nothing, the render of a headless game, see
+/// `SnowmewConfig::start_headless`
+pub struct NullRender;
+
+impl<T> Render<T> for NullRender {
+    fn update(&mut self, _: T, _: ObjectKey, _: ObjectKey) {}
+}
+
+impl<T> RenderFactory<T, NullRender> for NullRender {
+    fn init(self, _: &IOManager, _: io::Window, _: (i32, i32), _: Option<Arc<Device>>) -> NullRender {
+        NullRender
+    }
+}
+
 pub struct SnowmewConfig<GD, R> {
     pub display: DisplayConfig,
     pub use_opencl: bool,
     pub cadance_ms: u64,
-    pub render: Option<R>
+    // most simulation steps run before a frame is drawn, time past
+    // this is dropped rather then catching up
+    pub max_steps: uint,
+    pub render: Option<R>,
+    pub managers: Scheduler<GD>,
+    // the input of the session is written here as it arrives
+    pub record: Option<Path>,
+    // input is read from the replay instead of the window
+    pub replay: Option<io::Replay>
 }
 
-impl<GD: Clone, R: Render<GD>, RF: RenderFactory<GD, R>> SnowmewConfig<GD, RF> {
+impl<GD: Common+Clone+Send, R: Render<GD>, RF: RenderFactory<GD, R>> SnowmewConfig<GD, RF> {
     pub fn new() -> SnowmewConfig<GD, RF> {
         SnowmewConfig {
             display: DisplayConfig {
@@ -144,17 +192,47 @@ impl<GD: Clone, R: Render<GD>, RF: RenderFactory<GD, R>> SnowmewConfig<GD, RF> {
             },
             use_opencl: true,
             cadance_ms: 8,
-            render: None
+            max_steps: 5,
+            render: None,
+            managers: Scheduler::new(),
+            record: None,
+            replay: None
         }
     }
 
-    pub fn start(self, gd: GD, game: |GD, &io::InputState, &io::InputState| -> (GD, ObjectKey, ObjectKey)) {
+    /// Runs the game every cadance_ms of simulated time, independent
+    /// of how often the timer actually fires. Fails if the input can
+    /// not be recorded.
+    pub fn start(self, gd: GD, game: |GD, &FrameInfo, &io::InputState, &io::InputState| -> (GD, ObjectKey, ObjectKey)) -> IoResult<()> {
+        self.run(gd, |gd, frame, input, input_last| Ok(Some(game(gd, frame, input, input_last))))
+    }
+
+    /// Like `start`, but every step first waits for the input of all
+    /// players, game is handed the input of every player. Stops once a
+    /// player closed or on a desync, hash is the state hash the peers
+    /// compare, see `lockstep::StateHash`.
+    pub fn start_lockstep(self, gd: GD, lockstep: &mut lockstep::Lockstep, hash: |&GD| -> u64,
+                          game: |GD, &FrameInfo, &[io::InputState], &[io::InputState]| -> (GD, ObjectKey, ObjectKey)) -> IoResult<()> {
+        self.run(gd, |gd, frame, input, input_last| {
+            if lockstep.desync().is_some() || lockstep.inputs().iter().any(|i| i.should_close()) {
+                return Ok(None);
+            }
+            try!(lockstep.exchange(frame, input, input_last, hash(&gd)));
+            Ok(Some(game(gd, frame, lockstep.inputs(), lockstep.last_inputs())))
+        })
+    }
+
+    // the window, render and timing shared by start and start_lockstep,
+    // step runs the game for one step and returns None to stop
+    fn run(self, gd: GD, step: |GD, &FrameInfo, &io::InputState, &io::InputState|
+                                -> IoResult<Option<(GD, ObjectKey, ObjectKey)>>) -> IoResult<()> {
         let mut gd = gd;
+        let mut step_game = step;
         let mut im = io::IOManager::new(setup_glfw());
 
         // create display
         let display = match self.display.create_display(&mut im) {
-            None => return,
+            None => fail!("Failed to create a display"),
             Some(display) => display
         };
         let ih = display.handle();
@@ -162,20 +240,167 @@ impl<GD: Clone, R: Render<GD>, RF: RenderFactory<GD, R>> SnowmewConfig<GD, RF> {
         let res = im.get_framebuffer_size(&display);
         let dev = if self.use_opencl { get_cl() } else { None };
         let mut render = self.render.unwrap().init(&im, display, res, dev);
+        let mut managers = self.managers;
+        let mut replay = self.replay;
 
         let mut timer = Timer::new().unwrap();
         let timer_port = timer.periodic(self.cadance_ms);
 
-        let mut input_last = im.get(&ih);
-        while !input_last.should_close() {
+        let step = self.cadance_ms as f64 / 1000.;
+        let mut frame = FrameInfo {
+            count: 0,
+            time: 0.,
+            delta: step,
+            alpha: 0.
+        };
+        let mut accumulator = 0.;
+        let mut last_time = precise_time_s();
+        let mut last_gd = gd.clone();
+        let (mut scene, mut camera) = (0, 0);
+
+        let mut input_last = match replay {
+            Some(ref replay) => replay.get(),
+            None => im.get(&ih)
+        };
+        let mut recorder = match self.record {
+            Some(ref path) => Some(try!(io::Recorder::create(path, &input_last))),
+            None => None
+        };
+
+        while !input_last.should_close() && !im.get(&ih).should_close() {
             timer_port.recv();
-            im.poll();
-            let input = im.get(&ih);
-            let (new_gd, scene, camera) = game(gd, &input, &input_last);
-            render.update(new_gd.clone(), scene, camera);
-            gd = new_gd;
-            input_last = input;
+            let now = precise_time_s();
+            accumulator += now - last_time;
+            last_time = now;
+
+            let mut steps = 0;
+            while accumulator >= step && steps < self.max_steps {
+                im.poll();
+                let input = match replay {
+                    Some(ref mut replay) => {
+                        replay.poll(&frame);
+                        replay.get()
+                    }
+                    None => im.get(&ih)
+                };
+                match recorder {
+                    Some(ref mut recorder) => try!(recorder.record(&frame, &input, &input_last)),
+                    None => ()
+                }
+                last_gd = gd.clone();
+                gd.swap_events();
+                let (new_gd, s, c) = match try!(step_game(gd, &frame, &input, &input_last)) {
+                    Some(next) => next,
+                    None => return Ok(())
+                };
+                gd = managers.step(new_gd, &frame);
+                scene = s;
+                camera = c;
+                input_last = input;
+
+                frame.count += 1;
+                frame.time += step;
+                accumulator -= step;
+                steps += 1;
+            }
+
+            // fell too far behind, drop the time instead of spiraling
+            if accumulator >= step {
+                accumulator = 0.;
+            }
+
+            // every tick is drawn, the render interpolates between the
+            // last two steps by alpha
+            if frame.count != 0 {
+                frame.alpha = accumulator / step;
+                render.update_frame(last_gd.clone(), gd.clone(), &frame, scene, camera);
+            }
         }
+        Ok(())
     }
 }
 
+impl<GD: Common+Clone+Send> SnowmewConfig<GD, NullRender> {
+    /// Runs the game without a window, OpenGL or OpenCL, the
+    /// NullRender is handed every step in place of a drawn frame.
+    /// Steps are run back to back on simulated time with input from
+    /// the source, until frames steps ran, stop returns true or the
+    /// input asks to close. The last generation is returned.
+    pub fn start_headless<I: io::InputSource>(self, gd: GD, input: I, frames: Option<uint>,
+                          stop: |&GD, &FrameInfo| -> bool,
+                          game: |GD, &FrameInfo, &io::InputState, &io::InputState| -> (GD, ObjectKey, ObjectKey)) -> GD {
+        let mut gd = gd;
+        let mut input = input;
+        let mut render = NullRender;
+        let mut managers = self.managers;
+
+        let step = self.cadance_ms as f64 / 1000.;
+        let mut frame = FrameInfo {
+            count: 0,
+            time: 0.,
+            delta: step,
+            alpha: 1.
+        };
+
+        let mut input_last = input.get();
+        loop {
+            if frames.map_or(false, |n| frame.count >= n) ||
+               input_last.should_close() || stop(&gd, &frame) {
+                break;
+            }
+
+            input.poll(&frame);
+            let state = input.get();
+            gd.swap_events();
+            let (new_gd, scene, camera) = game(gd, &frame, &state, &input_last);
+            gd = managers.step(new_gd, &frame);
+            render.update(gd.clone(), scene, camera);
+            input_last = state;
+
+            frame.count += 1;
+            frame.time += step;
+        }
+        gd
+    }
+
+    /// Like `start_headless`, but every step first waits for the input
+    /// of all players as `start_lockstep` does. Stops after frames
+    /// steps, once a player closed or on a desync.
+    pub fn start_headless_lockstep<I: io::InputSource>(self, gd: GD, input: I, lockstep: &mut lockstep::Lockstep,
+                          frames: Option<uint>, hash: |&GD| -> u64,
+                          game: |GD, &FrameInfo, &[io::InputState], &[io::InputState]| -> (GD, ObjectKey, ObjectKey)) -> IoResult<GD> {
+        let mut gd = gd;
+        let mut input = input;
+        let mut render = NullRender;
+        let mut managers = self.managers;
+
+        let step = self.cadance_ms as f64 / 1000.;
+        let mut frame = FrameInfo {
+            count: 0,
+            time: 0.,
+            delta: step,
+            alpha: 1.
+        };
+
+        let mut input_last = input.get();
+        loop {
+            if frames.map_or(false, |n| frame.count >= n) || lockstep.desync().is_some() ||
+               lockstep.inputs().iter().any(|i| i.should_close()) {
+                break;
+            }
+
+            input.poll(&frame);
+            let state = input.get();
+            try!(lockstep.exchange(&frame, &state, &input_last, hash(&gd)));
+            gd.swap_events();
+            let (new_gd, scene, camera) = game(gd, &frame, lockstep.inputs(), lockstep.last_inputs());
+            gd = managers.step(new_gd, &frame);
+            render.update(gd.clone(), scene, camera);
+            input_last = state;
+
+            frame.count += 1;
+            frame.time += step;
+        }
+        Ok(gd)
+    }
+}
@@ -0,0 +1,234 @@
+use std::hash::sip::SipState;
+use std::io::{IoResult, MemWriter, BufferedReader};
+use std::io::net::tcp::{TcpListener, TcpStream};
+use std::io::{Listener, Acceptor};
+
+use cow::btree::BTreeMap;
+use glfw::WindowEvent;
+
+use common::FrameInfo;
+use diff::diff_by;
+use io::InputState;
+use snapshot::{Snapshot, invalid};
+
+type Events = Vec<(Option<f64>, WindowEvent)>;
+
+/// hash of one entry of the table name, the state hash is the sum of
+/// the hashes of every entry
+pub fn entry_hash<K: Snapshot, V: Snapshot>(table: &str, key: &K, value: &V) -> u64 {
+    let mut state = SipState::new();
+    state.write(table.as_bytes()).unwrap();
+    key.save(&mut state).unwrap();
+    value.save(&mut state).unwrap();
+    state.result()
+}
+
+/// what changing the table from old to new adds to the state hash,
+/// only the entries for which same is false are hashed
+pub fn table_hash_delta<K: Snapshot+Clone+Ord+Send+Share, V: Snapshot+Clone+Send+Share>
+    (table: &str, old: &BTreeMap<K, V>, new: &BTreeMap<K, V>, same: |&V, &V| -> bool) -> u64 {
+    let mut delta = 0u64;
+    for change in diff_by(old, new, same) {
+        let key = change.key();
+        match old.find(&key) {
+            Some(value) => delta -= entry_hash(table, &key, value),
+            None => ()
+        }
+        match new.find(&key) {
+            Some(value) => delta += entry_hash(table, &key, value),
+            None => ()
+        }
+    }
+    delta
+}
+
+/// what changing a single value from old to new adds to the state hash
+pub fn value_hash_delta<V: Snapshot+PartialEq>(name: &str, old: &V, new: &V) -> u64 {
+    if old == new {
+        0
+    } else {
+        entry_hash(name, &0u32, new) - entry_hash(name, &0u32, old)
+    }
+}
+
+/// Implemented by the data of every subsystem the simulation depends
+/// on. The data of a game sums the deltas of the subsystems it holds.
+pub trait StateHashDelta {
+    /// what the change from old to self adds to the state hash
+    fn state_hash_delta(&self, old: &Self) -> u64;
+}
+
+/// The hash of a generation as the desync detector compares it. It
+/// covers what `StateHashDelta` of the game data covers, and is updated
+/// from the entries that changed since the last generation it was
+/// given. Every peer starts from the same empty generation, so the
+/// hashes of equal generations are equal.
+pub struct StateHash<GD> {
+    last: GD,
+    hash: u64
+}
+
+impl<GD: StateHashDelta+Clone> StateHash<GD> {
+    pub fn new(empty: GD) -> StateHash<GD> {
+        StateHash {
+            last: empty,
+            hash: 0
+        }
+    }
+
+    pub fn update(&mut self, gd: &GD) -> u64 {
+        self.hash += gd.state_hash_delta(&self.last);
+        self.last = gd.clone();
+        self.hash
+    }
+}
+
+fn write_input(w: &mut Writer, frame: uint, hash: u64, events: &Events) -> IoResult<()> {
+    try!(frame.save(w));
+    try!(hash.save(w));
+    events.save(w)
+}
+
+fn read_input(r: &mut Reader, frame: uint) -> IoResult<(u64, Events)> {
+    let at: uint = try!(Snapshot::load(r));
+    if at != frame {
+        return Err(invalid("input for the wrong frame"));
+    }
+    let hash = try!(Snapshot::load(r));
+    let events = try!(Snapshot::load(r));
+    Ok((hash, events))
+}
+
+struct Link {
+    reader: BufferedReader<TcpStream>,
+    writer: TcpStream
+}
+
+impl Link {
+    fn new(stream: TcpStream) -> Link {
+        Link {
+            reader: BufferedReader::new(stream.clone()),
+            writer: stream
+        }
+    }
+}
+
+enum Role {
+    // one link per player that joined, in player order
+    Host(Vec<Link>),
+    Join(Link)
+}
+
+/// Runs the game on every peer from the same inputs. Each frame every
+/// player sends the events it saw to the host, which passes the input
+/// of all players on once everything for that frame arrived.
+///
+/// Every player starts out from an empty headless `InputState`, so the
+/// other players only know what the events told them.
+pub struct Lockstep {
+    player: uint,
+    role: Role,
+    inputs: Vec<InputState>,
+    last: Vec<InputState>,
+    desync: Option<(uint, uint)>
+}
+
+impl Lockstep {
+    fn new(player: uint, players: uint, role: Role) -> Lockstep {
+        let inputs = Vec::from_elem(players, InputState::headless((0, 0)));
+        Lockstep {
+            player: player,
+            role: role,
+            last: inputs.clone(),
+            inputs: inputs,
+            desync: None
+        }
+    }
+
+    /// Waits for players-1 other players to join, the host is player 0
+    pub fn host(listener: TcpListener, players: uint) -> IoResult<Lockstep> {
+        let mut acceptor = try!(listener.listen());
+        let mut links = Vec::new();
+        for player in range(1, players) {
+            let mut stream = try!(acceptor.accept());
+            try!((player as u32).save(&mut stream));
+            try!((players as u32).save(&mut stream));
+            links.push(Link::new(stream));
+        }
+        Ok(Lockstep::new(0, players, Host(links)))
+    }
+
+    pub fn join(host: &str, port: u16) -> IoResult<Lockstep> {
+        let mut link = Link::new(try!(TcpStream::connect(host, port)));
+        let player: u32 = try!(Snapshot::load(&mut link.reader));
+        let players: u32 = try!(Snapshot::load(&mut link.reader));
+        Ok(Lockstep::new(player as uint, players as uint, Join(link)))
+    }
+
+    pub fn player(&self) -> uint { self.player }
+
+    pub fn players(&self) -> uint { self.inputs.len() }
+
+    /// the input of every player for the last exchanged frame
+    pub fn inputs<'a>(&'a self) -> &'a [InputState] { self.inputs.as_slice() }
+
+    /// the input of every player for the frame before that
+    pub fn last_inputs<'a>(&'a self) -> &'a [InputState] { self.last.as_slice() }
+
+    /// The first frame and player whose state hash did not match ours,
+    /// everything simulated from there on differs between the peers.
+    pub fn desync(&self) -> Option<(uint, uint)> { self.desync }
+
+    /// Sends what changed from last to input and blocks until the input
+    /// of every player for frame arrived. hash is the `StateHash` of
+    /// the generation the frame starts from.
+    pub fn exchange(&mut self, frame: &FrameInfo, input: &InputState, last: &InputState,
+                    hash: u64) -> IoResult<()> {
+        let mut events: Events = input.iter_delta(last).collect();
+        events.reverse();
+
+        let all = match self.role {
+            Host(ref mut links) => {
+                let mut all = vec!((hash, events));
+                for link in links.mut_iter() {
+                    all.push(try!(read_input(&mut link.reader, frame.count)));
+                }
+
+                let mut w = MemWriter::new();
+                try!(frame.count.save(&mut w));
+                try!(all.save(&mut w));
+                for link in links.mut_iter() {
+                    try!(link.writer.write(w.get_ref()));
+                }
+                all
+            }
+            Join(ref mut link) => {
+                let mut w = MemWriter::new();
+                try!(write_input(&mut w, frame.count, hash, &events));
+                try!(link.writer.write(w.get_ref()));
+
+                let at: uint = try!(Snapshot::load(&mut link.reader));
+                if at != frame.count {
+                    return Err(invalid("inputs for the wrong frame"));
+                }
+                let all: Vec<(u64, Events)> = try!(Snapshot::load(&mut link.reader));
+                all
+            }
+        };
+
+        if all.len() != self.inputs.len() {
+            return Err(invalid("inputs for the wrong number of players"));
+        }
+
+        self.last = self.inputs.clone();
+        for (player, (state, &(their_hash, ref events))) in self.inputs.mut_iter().zip(all.iter()).enumerate() {
+            for &(time, ref event) in events.iter() {
+                state.feed(time, event.clone());
+            }
+            if their_hash != hash && self.desync.is_none() {
+                self.desync = Some((frame.count, player));
+            }
+        }
+        Ok(())
+    }
+}
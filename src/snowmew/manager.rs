@@ -0,0 +1,119 @@
+use std::task::TaskBuilder;
+use std::comm::{Full, RecvDisconnected};
+
+use common::FrameInfo;
+use merge::Merge;
+
+/// A sink for generations, render and audio are passive managers.
+pub trait PassiveManager<GD>: Send {
+    fn update(&mut self, gd: GD, frame: &FrameInfo);
+}
+
+/// Writes its changes back to the database, physics and AI are
+/// active managers.
+pub trait ActiveManager<GD>: Send {
+    fn run(&mut self, gd: GD, frame: &FrameInfo) -> GD;
+}
+
+/// Runs each manager on its own task. Every active manager is handed
+/// the same generation so they run at the same time, their results are
+/// merged in the order the managers were added. Every passive manager
+/// is then handed the merged generation, one that is still busy with
+/// an older generation skips the new one.
+pub struct Scheduler<GD> {
+    active: Vec<(Sender<(GD, FrameInfo)>, Receiver<GD>)>,
+    passive: Vec<SyncSender<(GD, FrameInfo)>>,
+    // set by add_active, only a database with active managers is merged
+    merge: Option<fn(&GD, &GD, &GD) -> Result<GD, String>>
+}
+
+fn merge<GD: Merge>(base: &GD, ours: &GD, theirs: &GD) -> Result<GD, String> {
+    Merge::merge(base, ours, theirs)
+}
+
+impl<GD: Clone+Send+Merge> Scheduler<GD> {
+    pub fn add_active<M: ActiveManager<GD>>(&mut self, name: &str, manager: M) {
+        let (send_gd, recv_gd) = channel();
+        let (send_result, recv_result) = channel();
+
+        TaskBuilder::new().named(name.to_string()).spawn(proc() {
+            let mut manager = manager;
+            for (gd, frame) in recv_gd.iter() {
+                send_result.send(manager.run(gd, &frame));
+            }
+        });
+
+        self.active.push((send_gd, recv_result));
+        self.merge = Some(merge::<GD>);
+    }
+}
+
+impl<GD: Clone+Send> Scheduler<GD> {
+    pub fn new() -> Scheduler<GD> {
+        Scheduler {
+            active: Vec::new(),
+            passive: Vec::new(),
+            merge: None
+        }
+    }
+
+    pub fn add_passive<M: PassiveManager<GD>>(&mut self, name: &str, manager: M) {
+        let (send_gd, recv_gd) = sync_channel(1);
+
+        TaskBuilder::new().named(name.to_string()).spawn(proc() {
+            let mut manager = manager;
+            for (gd, frame) in recv_gd.iter() {
+                manager.update(gd, &frame);
+            }
+        });
+
+        self.passive.push(send_gd);
+    }
+
+    /// hands gd to every manager and returns the generation written
+    /// by the active managers
+    pub fn step(&mut self, gd: GD, frame: &FrameInfo) -> GD {
+        let gd = self.run_active(gd, frame);
+
+        for send in self.passive.iter() {
+            match send.try_send((gd.clone(), frame.clone())) {
+                Ok(()) | Err(Full(_)) => (),
+                Err(RecvDisconnected(_)) => fail!("passive manager exited")
+            }
+        }
+        gd
+    }
+
+    // every manager is sent the generation before any result is
+    // waited on. A manager whose result can not be merged is run
+    // again on top of the merged generation once the others are done
+    fn run_active(&self, base: GD, frame: &FrameInfo) -> GD {
+        for &(ref send, _) in self.active.iter() {
+            send.send((base.clone(), frame.clone()));
+        }
+
+        let mut gd = base.clone();
+        let mut retry = Vec::new();
+        for (idx, &(_, ref recv)) in self.active.iter().enumerate() {
+            let result = recv.recv();
+            gd = if idx == 0 {
+                result
+            } else {
+                match (self.merge.unwrap())(&base, &gd, &result) {
+                    Ok(merged) => merged,
+                    Err(_) => {
+                        retry.push(idx);
+                        gd
+                    }
+                }
+            };
+        }
+
+        for idx in retry.iter() {
+            let &(ref send, ref recv) = self.active.get(*idx);
+            send.send((gd, frame.clone()));
+            gd = recv.recv();
+        }
+        gd
+    }
+}
@@ -0,0 +1,97 @@
+use cow::btree::BTreeMap;
+
+use diff::{diff_by, shared};
+
+/// How a key that was changed by both sides of a merge is resolved
+pub enum Policy<V> {
+    /// the side merged in last is kept
+    LastWriterWins,
+    /// the value with the higher priority is kept, ours on a tie. A
+    /// side that removed the key loses to one that kept it.
+    Priority(fn(&V) -> int),
+    /// called with the base, ours and theirs values, None removes the key
+    Custom(fn(Option<&V>, Option<&V>, Option<&V>) -> Option<V>)
+}
+
+impl<V: Clone> Policy<V> {
+    pub fn resolve(&self, base: Option<&V>, ours: Option<&V>, theirs: Option<&V>) -> Option<V> {
+        match *self {
+            LastWriterWins => theirs.map(|v| v.clone()),
+            Priority(f) => match (ours, theirs) {
+                (Some(o), Some(t)) => Some(if f(t) > f(o) { t.clone() } else { o.clone() }),
+                (o, t) => o.or(t).map(|v| v.clone())
+            },
+            Custom(f) => f(base, ours, theirs)
+        }
+    }
+}
+
+impl<V> Clone for Policy<V> {
+    fn clone(&self) -> Policy<V> {
+        match *self {
+            LastWriterWins => LastWriterWins,
+            Priority(f) => Priority(f),
+            Custom(f) => Custom(f)
+        }
+    }
+}
+
+/// Three way merge of two generations that share base as a parent.
+/// A database is merged into ours, theirs is the other side.
+pub trait Merge {
+    fn merge(base: &Self, ours: &Self, theirs: &Self) -> Result<Self, String>;
+}
+
+fn same_opt<V>(a: Option<&V>, b: Option<&V>, eq: |&V, &V| -> bool) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => shared(a, b) || eq(a, b),
+        (None, None) => true,
+        _ => false
+    }
+}
+
+pub fn merge_map<K: Clone+Ord+Send+Share, V: Clone+PartialEq+Send+Share>
+    (base: &BTreeMap<K, V>, ours: &BTreeMap<K, V>, theirs: &BTreeMap<K, V>,
+     policy: &Policy<V>) -> BTreeMap<K, V> {
+    merge_map_by(base, ours, theirs, |a, b| a == b, policy)
+}
+
+/// Applies every key theirs changed to a copy of ours, keys changed
+/// on both sides are resolved by the policy.
+pub fn merge_map_by<K: Clone+Ord+Send+Share, V: Clone+Send+Share>
+    (base: &BTreeMap<K, V>, ours: &BTreeMap<K, V>, theirs: &BTreeMap<K, V>,
+     eq: |&V, &V| -> bool, policy: &Policy<V>) -> BTreeMap<K, V> {
+    let mut out = ours.clone();
+    let changes: Vec<K> = diff_by(base, theirs, |a, b| shared(a, b) || eq(a, b))
+        .map(|c| c.key()).collect();
+
+    for key in changes.move_iter() {
+        let (b, o, t) = (base.find(&key), ours.find(&key), theirs.find(&key));
+        if same_opt(o, t, |a, b| eq(a, b)) {
+            continue;
+        }
+
+        let value = if same_opt(b, o, |a, b| eq(a, b)) {
+            t.map(|v| v.clone())
+        } else {
+            policy.resolve(b, o, t)
+        };
+
+        match value {
+            Some(v) => { out.insert(key, v); }
+            None => { out.remove(&key); }
+        }
+    }
+    out
+}
+
+/// For data that can not be merged key by key, the side that changed
+/// is taken. Fails if both did.
+pub fn pick<'a, T>(base: &T, ours: &'a T, theirs: &'a T,
+                   changed: |&T, &T| -> bool, what: &str) -> Result<&'a T, String> {
+    match (changed(base, ours), changed(base, theirs)) {
+        (true, true) => Err(format!("{} was changed by both sides", what)),
+        (false, true) => Ok(theirs),
+        _ => Ok(ours)
+    }
+}
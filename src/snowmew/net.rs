@@ -0,0 +1,284 @@
+use std::io::{IoResult, MemWriter, BufferedReader};
+use std::io::net::tcp::{TcpListener, TcpAcceptor, TcpStream};
+use std::io::{Listener, Acceptor};
+use std::task::TaskBuilder;
+
+use cow::btree::BTreeSet;
+
+use common::{Common, ObjectKey};
+use replicate::Replicate;
+use snapshot::{Snapshot, invalid};
+
+// a client that is this many generations behind is skipped until
+// it acknowledged one of them
+static MAX_UNACKED: uint = 32;
+
+enum Message {
+    Ack(u32),
+    Subscribe(Vec<ObjectKey>)
+}
+
+fn write_message(w: &mut Writer, msg: &Message) -> IoResult<()> {
+    match *msg {
+        Ack(seq) => {
+            try!(w.write_u8(0));
+            seq.save(w)
+        }
+        Subscribe(ref scenes) => {
+            try!(w.write_u8(1));
+            scenes.save(w)
+        }
+    }
+}
+
+fn read_message(r: &mut Reader) -> IoResult<Message> {
+    match try!(r.read_u8()) {
+        0 => Ok(Ack(try!(Snapshot::load(r)))),
+        1 => Ok(Subscribe(try!(Snapshot::load(r)))),
+        _ => Err(invalid("unknown client message"))
+    }
+}
+
+fn write_frame<GD: Replicate>(w: &mut Writer, seq: u32, base: &Sent<GD>, gd: &GD,
+                              hidden: &BTreeSet<ObjectKey>) -> IoResult<()> {
+    try!(w.write_le_u32(seq));
+    try!(w.write_le_u32(base.seq));
+    gd.write_delta(&base.gd, &base.hidden, hidden, w)
+}
+
+/// Members of every scene that is not in scenes. Starts from what was
+/// hidden of old and only visits the scenes whose members changed
+/// since. An object is a member of at most one scene, so the old
+/// members are all removed before the new ones are added.
+fn hidden_objects<GD: Common>(old: &GD, old_hidden: &BTreeSet<ObjectKey>, gd: &GD,
+                              scenes: &[ObjectKey]) -> BTreeSet<ObjectKey> {
+    let changed: Vec<ObjectKey> = gd.get_common().scene_diff(old.get_common())
+        .map(|c| c.key())
+        .filter(|scene| !scenes.contains(scene))
+        .collect();
+
+    let mut hidden = old_hidden.clone();
+    for scene in changed.iter() {
+        match old.scene_set(*scene) {
+            Some(members) => for member in members.iter() { hidden.remove(member); },
+            None => ()
+        }
+    }
+    for scene in changed.iter() {
+        match gd.scene_set(*scene) {
+            Some(members) => for member in members.iter() { hidden.insert(*member); },
+            None => ()
+        }
+    }
+    hidden
+}
+
+// a generation as the client was sent it, hidden is what was left out
+struct Sent<GD> {
+    seq: u32,
+    gd: GD,
+    hidden: BTreeSet<ObjectKey>
+}
+
+impl<GD> Sent<GD> {
+    fn empty(gd: GD) -> Sent<GD> {
+        Sent {
+            seq: 0,
+            gd: gd,
+            hidden: BTreeSet::new()
+        }
+    }
+}
+
+struct Peer<GD> {
+    // frames are written by a task of their own, a slow client only
+    // falls behind on acks instead of blocking the server
+    frames: Sender<Vec<u8>>,
+    messages: Receiver<Message>,
+    acked: Sent<GD>,
+    sent: Vec<Sent<GD>>,
+    scenes: Option<Vec<ObjectKey>>,
+    closed: bool
+}
+
+impl<GD: Replicate+Common+Clone> Peer<GD> {
+    fn new(stream: TcpStream, empty: GD) -> Peer<GD> {
+        let (send, recv) = channel();
+        let reader = stream.clone();
+        TaskBuilder::new().named("replication peer".to_string()).spawn(proc() {
+            let mut reader = BufferedReader::new(reader);
+            loop {
+                match read_message(&mut reader) {
+                    Ok(msg) => if send.send_opt(msg).is_err() { break },
+                    Err(_) => break
+                }
+            }
+        });
+
+        let (frames, recv_frames) = channel();
+        let mut writer = stream;
+        TaskBuilder::new().named("replication writer".to_string()).spawn(proc() {
+            for frame in recv_frames.iter() {
+                if writer.write(frame.as_slice()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Peer {
+            frames: frames,
+            messages: recv,
+            acked: Sent::empty(empty),
+            sent: Vec::new(),
+            scenes: None,
+            closed: false
+        }
+    }
+
+    fn read_messages(&mut self, empty: &GD) {
+        loop {
+            match self.messages.try_recv() {
+                Ok(Ack(seq)) => {
+                    match self.sent.iter().position(|s| s.seq == seq) {
+                        Some(idx) => {
+                            self.acked = self.sent.remove(idx).unwrap();
+                            self.sent.retain(|s| s.seq > seq);
+                        }
+                        None => ()
+                    }
+                }
+                // the client starts over from an empty generation
+                Ok(Subscribe(scenes)) => {
+                    self.scenes = Some(scenes);
+                    self.acked = Sent::empty(empty.clone());
+                    self.sent.clear();
+                }
+                Err(_) => break
+            }
+        }
+    }
+
+    fn send(&mut self, seq: u32, gd: &GD, empty: &GD) {
+        self.read_messages(empty);
+        if self.sent.len() >= MAX_UNACKED {
+            return;
+        }
+
+        let hidden = match self.scenes {
+            Some(ref scenes) => {
+                let last = self.sent.last().unwrap_or(&self.acked);
+                hidden_objects(&last.gd, &last.hidden, gd, scenes.as_slice())
+            }
+            None => BTreeSet::new()
+        };
+
+        // the writer task exits once a write failed
+        let mut w = MemWriter::new();
+        let res = write_frame(&mut w, seq, &self.acked, gd, &hidden);
+        if res.is_ok() && self.frames.send_opt(w.unwrap()).is_ok() {
+            self.sent.push(Sent {
+                seq: seq,
+                gd: gd.clone(),
+                hidden: hidden
+            });
+        } else {
+            self.closed = true;
+        }
+    }
+}
+
+/// Sends every generation to the connected clients, each as a delta
+/// against the last generation that client acknowledged.
+pub struct Server<GD> {
+    acceptor: TcpAcceptor,
+    peers: Vec<Peer<GD>>,
+    empty: GD,
+    seq: u32,
+    port: u16
+}
+
+impl<GD: Replicate+Common+Clone> Server<GD> {
+    /// empty is what a new client starts out with, port 0 picks a free port
+    pub fn bind(host: &str, port: u16, empty: GD) -> IoResult<Server<GD>> {
+        let mut listener = try!(TcpListener::bind(host, port));
+        let port = try!(listener.socket_name()).port;
+        let acceptor = try!(listener.listen());
+        Ok(Server {
+            acceptor: acceptor,
+            peers: Vec::new(),
+            empty: empty,
+            seq: 0,
+            port: port
+        })
+    }
+
+    pub fn port(&self) -> u16 { self.port }
+
+    pub fn clients(&self) -> uint { self.peers.len() }
+
+    /// accepts any waiting clients, then sends gd to every client
+    pub fn send(&mut self, gd: &GD) {
+        loop {
+            self.acceptor.set_timeout(Some(0));
+            match self.acceptor.accept() {
+                Ok(stream) => self.peers.push(Peer::new(stream, self.empty.clone())),
+                Err(_) => break
+            }
+        }
+
+        self.seq += 1;
+        for peer in self.peers.mut_iter() {
+            peer.send(self.seq, gd, &self.empty);
+        }
+        self.peers.retain(|p| !p.closed);
+    }
+}
+
+/// Rebuilds the generations sent by a Server
+pub struct Client<GD> {
+    reader: BufferedReader<TcpStream>,
+    writer: TcpStream,
+    received: Vec<(u32, GD)>,
+    empty: GD
+}
+
+impl<GD: Replicate+Clone> Client<GD> {
+    pub fn connect(host: &str, port: u16, empty: GD) -> IoResult<Client<GD>> {
+        let stream = try!(TcpStream::connect(host, port));
+        Ok(Client {
+            reader: BufferedReader::new(stream.clone()),
+            writer: stream,
+            received: Vec::new(),
+            empty: empty
+        })
+    }
+
+    /// Only the members of scenes are sent from now on, objects that
+    /// are not in any scene are always sent. Everything is sent again.
+    pub fn subscribe(&mut self, scenes: &[ObjectKey]) -> IoResult<()> {
+        write_message(&mut self.writer, &Subscribe(Vec::from_slice(scenes)))
+    }
+
+    /// blocks until the next generation arrived and acknowledges it
+    pub fn recv(&mut self) -> IoResult<GD> {
+        let seq = try!(self.reader.read_le_u32());
+        let base = try!(self.reader.read_le_u32());
+        let gd = {
+            let base_gd = if base == 0 {
+                &self.empty
+            } else {
+                match self.received.iter().find(|&&(s, _)| s == base) {
+                    Some(&(_, ref gd)) => gd,
+                    None => return Err(invalid("delta against an unknown generation"))
+                }
+            };
+            try!(base_gd.read_delta(&mut self.reader))
+        };
+
+        // the server only sends deltas against base or newer from now on
+        self.received.retain(|&(s, _)| s >= base);
+        self.received.push((seq, gd.clone()));
+        try!(write_message(&mut self.writer, &Ack(seq)));
+        Ok(gd)
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::TaskPool;
+
+use common::{Common, ObjectKey};
+
+/// Splits len entries into at most count ranges of about the same size,
+/// as (start, len) pairs in order
+pub fn split(len: uint, count: uint) -> Vec<(uint, uint)> {
+    let count = if count == 0 { 1 } else { count };
+    let size = (len + count - 1) / count;
+    let mut out = Vec::new();
+    let mut start = 0;
+    while start < len {
+        let take = if start + size > len { len - start } else { size };
+        out.push((start, take));
+        start += take;
+    }
+    out
+}
+
+/// The keys from lower up to but not including upper, no upper is
+/// the end of the table
+#[deriving(Clone, PartialEq, Show)]
+pub struct KeyRange<K> {
+    pub lower: K,
+    pub upper: Option<K>
+}
+
+impl<K: Ord> KeyRange<K> {
+    pub fn contains(&self, key: &K) -> bool {
+        *key >= self.lower && self.upper.as_ref().map_or(true, |upper| key < upper)
+    }
+
+    /// The entries of iter that are in the range, iter has to be in key
+    /// order. `cow::btree` can not seek, so the entries before lower are
+    /// walked past rather then skipped.
+    pub fn entries<'a, V, I: Iterator<(&'a K, V)>>(&'a self, iter: I) -> RangeEntries<'a, K, I> {
+        RangeEntries {
+            iter: iter,
+            range: self
+        }
+    }
+}
+
+pub struct RangeEntries<'a, K, I> {
+    iter: I,
+    range: &'a KeyRange<K>
+}
+
+impl<'a, K: Ord, V, I: Iterator<(&'a K, V)>> Iterator<(&'a K, V)> for RangeEntries<'a, K, I> {
+    fn next(&mut self) -> Option<(&'a K, V)> {
+        loop {
+            match self.iter.next() {
+                Some((key, _)) if *key < self.range.lower => (),
+                Some((key, value)) => {
+                    return if self.range.contains(key) { Some((key, value)) } else { None };
+                }
+                None => return None
+            }
+        }
+    }
+}
+
+/// Splits keys into the ranges of `split`, in one walk. Only the first
+/// key of every range is copied, each task walks its own range of the
+/// table with `KeyRange::entries`.
+pub fn split_ranges<'a, K: Clone+Ord, I: Iterator<&'a K>>(keys: I, len: uint, count: uint) -> Vec<KeyRange<K>> {
+    let mut keys = keys;
+    let mut lowers = Vec::new();
+    let mut at = 0;
+    for (start, _) in split(len, count).move_iter() {
+        match keys.nth(start - at) {
+            Some(key) => lowers.push(key.clone()),
+            None => break
+        }
+        at = start + 1;
+    }
+
+    let mut out = Vec::new();
+    for (idx, lower) in lowers.iter().enumerate() {
+        out.push(KeyRange {
+            lower: lower.clone(),
+            upper: lowers.as_slice().get(idx + 1).map(|k| k.clone())
+        });
+    }
+    out
+}
+
+fn fold<R>(acc: Option<R>, r: Option<R>, reduce: fn(R, R) -> R) -> Option<R> {
+    match (acc, r) {
+        (Some(a), Some(b)) => Some(reduce(a, b)),
+        (a, None) => a,
+        (None, b) => b
+    }
+}
+
+/// Work split across a `TaskPool`. Every task gets its own copy of the
+/// generation, cloning one only copies the roots of its tables. The
+/// results are reduced in key order.
+pub trait ParallelCommon: Common+Clone+Send {
+    fn par_scene_map_reduce<T, R: Send>(&self, pool: &mut TaskPool<T>, scene: ObjectKey, chunks: uint,
+                                        map: fn(&Self, ObjectKey) -> R,
+                                        reduce: fn(R, R) -> R) -> Option<R> {
+        let ranges = match self.scene_set(scene) {
+            Some(set) => split_ranges(set.iter(), set.len(), chunks),
+            None => Vec::new()
+        };
+        let mut results = Vec::new();
+        for range in ranges.move_iter() {
+            let (send, recv) = channel();
+            let gd = self.clone();
+            pool.execute(proc(_) {
+                let mut acc = None;
+                let set = gd.scene_set(scene).unwrap();
+                for (key, _) in range.entries(set.iter().map(|k| (k, ()))) {
+                    acc = fold(acc, Some(map(&gd, *key)), reduce);
+                }
+                send.send(acc);
+            });
+            results.push(recv);
+        }
+
+        let mut acc = None;
+        for r in results.iter() {
+            acc = fold(acc, r.recv(), reduce);
+        }
+        acc
+    }
+
+    /// calls f for every member of scene, returns once all calls are done
+    fn par_scene_for_each<T, C: Clone+Send>(&self, pool: &mut TaskPool<T>, scene: ObjectKey, chunks: uint,
+                                            ctx: &C, f: fn(&Self, ObjectKey, &C)) {
+        let ranges = match self.scene_set(scene) {
+            Some(set) => split_ranges(set.iter(), set.len(), chunks),
+            None => Vec::new()
+        };
+        let mut done = Vec::new();
+        for range in ranges.move_iter() {
+            let (send, recv) = channel();
+            let gd = self.clone();
+            let ctx = ctx.clone();
+            pool.execute(proc(_) {
+                let set = gd.scene_set(scene).unwrap();
+                for (key, _) in range.entries(set.iter().map(|k| (k, ()))) {
+                    f(&gd, *key, &ctx);
+                }
+                send.send(());
+            });
+            done.push(recv);
+        }
+        for d in done.iter() {
+            d.recv();
+        }
+    }
+
+    fn par_component_map_reduce<T, V: Clone+PartialEq+Send+Share+'static, R: Send>
+        (&self, pool: &mut TaskPool<T>, chunks: uint,
+         map: fn(&Self, ObjectKey, &V) -> R, reduce: fn(R, R) -> R) -> Option<R> {
+        let ranges = match self.component_table::<V>() {
+            Some(table) => split_ranges(table.iter().map(|(k, _)| k), table.len(), chunks),
+            None => Vec::new()
+        };
+        let mut results = Vec::new();
+        for range in ranges.move_iter() {
+            let (send, recv) = channel();
+            let gd = self.clone();
+            pool.execute(proc(_) {
+                let mut acc = None;
+                let table = gd.component_table::<V>().unwrap();
+                for (key, value) in range.entries(table.iter()) {
+                    acc = fold(acc, Some(map(&gd, *key, value)), reduce);
+                }
+                send.send(acc);
+            });
+            results.push(recv);
+        }
+
+        let mut acc = None;
+        for r in results.iter() {
+            acc = fold(acc, r.recv(), reduce);
+        }
+        acc
+    }
+
+    /// calls f for every object with a V, returns once all calls are done
+    fn par_component_for_each<T, V: Clone+PartialEq+Send+Share+'static, C: Clone+Send>
+        (&self, pool: &mut TaskPool<T>, chunks: uint, ctx: &C, f: fn(&Self, ObjectKey, &V, &C)) {
+        let ranges = match self.component_table::<V>() {
+            Some(table) => split_ranges(table.iter().map(|(k, _)| k), table.len(), chunks),
+            None => Vec::new()
+        };
+        let mut done = Vec::new();
+        for range in ranges.move_iter() {
+            let (send, recv) = channel();
+            let gd = self.clone();
+            let ctx = ctx.clone();
+            pool.execute(proc(_) {
+                let table = gd.component_table::<V>().unwrap();
+                for (key, value) in range.entries(table.iter()) {
+                    f(&gd, *key, value, &ctx);
+                }
+                send.send(());
+            });
+            done.push(recv);
+        }
+        for d in done.iter() {
+            d.recv();
+        }
+    }
+}
+
+impl<GD: Common+Clone+Send> ParallelCommon for GD {}
@@ -0,0 +1,207 @@
+use cow::btree::{BTreeMap, BTreeSet, BTreeSetIterator};
+
+use common::{Common, ObjectKey};
+
+/// Anything that can answer if an object is part of it, used to
+/// filter a query without fetching the values.
+pub trait Contains {
+    fn contains_key(&self, key: &ObjectKey) -> bool;
+}
+
+impl<V: Clone+Send+Share> Contains for BTreeMap<ObjectKey, V> {
+    fn contains_key(&self, key: &ObjectKey) -> bool { self.find(key).is_some() }
+}
+
+impl Contains for BTreeSet<ObjectKey> {
+    fn contains_key(&self, key: &ObjectKey) -> bool { self.contains(key) }
+}
+
+/// Intersection of two iterators sorted by ObjectKey
+pub struct Join<A, B> {
+    a: A,
+    b: B
+}
+
+impl<'a, VA, VB, A: Iterator<(&'a ObjectKey, VA)>, B: Iterator<(&'a ObjectKey, VB)>>
+    Iterator<(&'a ObjectKey, (VA, VB))> for Join<A, B> {
+    fn next(&mut self) -> Option<(&'a ObjectKey, (VA, VB))> {
+        let (mut ka, mut va) = match self.a.next() {
+            Some(a) => a,
+            None => return None
+        };
+        let (mut kb, mut vb) = match self.b.next() {
+            Some(b) => b,
+            None => return None
+        };
+
+        loop {
+            match ka.cmp(kb) {
+                Equal => return Some((ka, (va, vb))),
+                Less => match self.a.next() {
+                    Some((k, v)) => { ka = k; va = v; }
+                    None => return None
+                },
+                Greater => match self.b.next() {
+                    Some((k, v)) => { kb = k; vb = v; }
+                    None => return None
+                }
+            }
+        }
+    }
+}
+
+/// Pairs the keys of an iterator with their value in a table, keys
+/// the table does not have are skipped. Every key costs a lookup, so
+/// this is cheaper then a `Join` if the iterator is much smaller than
+/// the table.
+pub struct Lookup<'a, I, V> {
+    iter: I,
+    table: &'a BTreeMap<ObjectKey, V>
+}
+
+impl<'a, VA, VB: Clone+Send+Share, I: Iterator<(&'a ObjectKey, VA)>>
+    Iterator<(&'a ObjectKey, (VA, &'a VB))> for Lookup<'a, I, VB> {
+    fn next(&mut self) -> Option<(&'a ObjectKey, (VA, &'a VB))> {
+        loop {
+            let (key, value) = match self.iter.next() {
+                Some(next) => next,
+                None => return None
+            };
+            match self.table.find(key) {
+                Some(v) => return Some((key, (value, v))),
+                None => ()
+            }
+        }
+    }
+}
+
+/// The keys of a set with no value, lets a query be driven by a
+/// scene set.
+pub struct SetKeys<'a> {
+    iter: BTreeSetIterator<'a, ObjectKey>
+}
+
+impl<'a> Iterator<(&'a ObjectKey, ())> for SetKeys<'a> {
+    fn next(&mut self) -> Option<(&'a ObjectKey, ())> {
+        self.iter.next().map(|k| (k, ()))
+    }
+}
+
+/// A typed iterator over the objects that have every joined
+/// component, built up with `join`, `has`, `without`, `in_scene`
+/// and `under`.
+///
+///     let iter = query(db.drawable_iter())
+///         .join(db.location_iter())
+///         .in_scene(db, scene);
+///
+pub struct Query<'a, I> {
+    iter: I,
+    has: Vec<&'a Contains>,
+    without: Vec<&'a Contains>,
+    subtrees: Vec<BTreeSet<ObjectKey>>,
+    empty: bool
+}
+
+pub fn query<'a, V, I: Iterator<(&'a ObjectKey, V)>>(iter: I) -> Query<'a, I> {
+    Query {
+        iter: iter,
+        has: Vec::new(),
+        without: Vec::new(),
+        subtrees: Vec::new(),
+        empty: false
+    }
+}
+
+/// a query over the members of set, join the tables to it with
+/// `lookup`
+pub fn query_set<'a>(set: &'a BTreeSet<ObjectKey>) -> Query<'a, SetKeys<'a>> {
+    query(SetKeys { iter: set.iter() })
+}
+
+impl<'a, V, I: Iterator<(&'a ObjectKey, V)>> Query<'a, I> {
+    /// like `join` but looks every key up in table instead of walking
+    /// it, for when the query is much smaller than the table
+    pub fn lookup<VB: Clone+Send+Share>(self, table: &'a BTreeMap<ObjectKey, VB>) -> Query<'a, Lookup<'a, I, VB>> {
+        Query {
+            iter: Lookup {
+                iter: self.iter,
+                table: table
+            },
+            has: self.has,
+            without: self.without,
+            subtrees: self.subtrees,
+            empty: self.empty
+        }
+    }
+
+    /// only objects that also have a value in other, the value is
+    /// returned alongside the existing ones
+    pub fn join<VB, J: Iterator<(&'a ObjectKey, VB)>>(self, other: J) -> Query<'a, Join<I, J>> {
+        Query {
+            iter: Join {
+                a: self.iter,
+                b: other
+            },
+            has: self.has,
+            without: self.without,
+            subtrees: self.subtrees,
+            empty: self.empty
+        }
+    }
+
+    /// only objects that are in table
+    pub fn has<T: Contains>(mut self, table: &'a T) -> Query<'a, I> {
+        self.has.push(table as &'a Contains);
+        self
+    }
+
+    /// only objects that are not in table
+    pub fn without<T: Contains>(mut self, table: &'a T) -> Query<'a, I> {
+        self.without.push(table as &'a Contains);
+        self
+    }
+
+    /// only objects that are members of scene
+    pub fn in_scene<C: Common>(mut self, db: &'a C, scene: ObjectKey) -> Query<'a, I> {
+        match db.scene_set(scene) {
+            Some(set) => self.has.push(set as &'a Contains),
+            None => self.empty = true
+        }
+        self
+    }
+
+    /// only root and objects below it
+    pub fn under<C: Common>(mut self, db: &C, root: ObjectKey) -> Query<'a, I> {
+        let mut set = BTreeSet::new();
+        for key in db.subtree(root).move_iter() {
+            set.insert(key);
+        }
+        self.subtrees.push(set);
+        self
+    }
+
+    fn matches(&self, key: &ObjectKey) -> bool {
+        self.subtrees.iter().all(|t| t.contains(key)) &&
+        self.has.iter().all(|t| t.contains_key(key)) &&
+        !self.without.iter().any(|t| t.contains_key(key))
+    }
+}
+
+impl<'a, V, I: Iterator<(&'a ObjectKey, V)>> Iterator<(&'a ObjectKey, V)> for Query<'a, I> {
+    fn next(&mut self) -> Option<(&'a ObjectKey, V)> {
+        if self.empty {
+            return None;
+        }
+
+        loop {
+            let (key, value) = match self.iter.next() {
+                Some(next) => next,
+                None => return None
+            };
+            if self.matches(key) {
+                return Some((key, value));
+            }
+        }
+    }
+}
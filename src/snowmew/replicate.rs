@@ -0,0 +1,130 @@
+use std::io::IoResult;
+
+use cow::btree::{BTreeMap, BTreeSet};
+
+use common::ObjectKey;
+use diff::{diff, diff_by};
+use snapshot::{Snapshot, invalid};
+
+/// Delta encoding of a generation against an older one the receiver
+/// already has, uses the same encoding as `Snapshot`.
+pub trait Replicate {
+    /// Writes what changed from old to self as the receiver sees it.
+    /// old_hidden are the objects that were left out when old was
+    /// sent, hidden the ones to leave out of self. An object that
+    /// became visible is sent whole, one that became hidden is removed.
+    fn write_delta(&self, old: &Self, old_hidden: &BTreeSet<ObjectKey>, hidden: &BTreeSet<ObjectKey>,
+                   w: &mut Writer) -> IoResult<()>;
+
+    /// the generation a delta written against self describes
+    fn read_delta(&self, r: &mut Reader) -> IoResult<Self>;
+}
+
+pub fn write_map_delta<K: Snapshot+Clone+Ord+Send+Share, V: Snapshot+Clone+PartialEq+Send+Share>
+    (old: &BTreeMap<K, V>, new: &BTreeMap<K, V>, w: &mut Writer) -> IoResult<()> {
+    let changes: Vec<K> = diff(old, new).map(|c| c.key()).collect();
+    write_changes(new, changes.as_slice(), w)
+}
+
+/// writes the keys for which same is false
+pub fn write_map_delta_by<K: Snapshot+Clone+Ord+Send+Share, V: Snapshot+Clone+Send+Share>
+    (old: &BTreeMap<K, V>, new: &BTreeMap<K, V>, same: |&V, &V| -> bool,
+     w: &mut Writer) -> IoResult<()> {
+    let changes: Vec<K> = diff_by(old, new, same).map(|c| c.key()).collect();
+    write_changes(new, changes.as_slice(), w)
+}
+
+/// Writes the table as the receiver sees it: old without old_hidden
+/// becomes new without hidden. Keys that changed visibility are sent
+/// even if their value did not change.
+pub fn write_map_delta_hidden<K: Snapshot+Clone+Ord+Send+Share, V: Snapshot+Clone+Send+Share>
+    (old: &BTreeMap<K, V>, new: &BTreeMap<K, V>, same: |&V, &V| -> bool,
+     old_hidden: &BTreeSet<K>, hidden: &BTreeSet<K>, w: &mut Writer) -> IoResult<()> {
+    let mut keys = BTreeSet::new();
+    for change in diff_by(old, new, same) {
+        keys.insert(change.key());
+    }
+    for key in old_hidden.iter() {
+        if !hidden.contains(key) {
+            keys.insert(key.clone());
+        }
+    }
+    for key in hidden.iter() {
+        if !old_hidden.contains(key) {
+            keys.insert(key.clone());
+        }
+    }
+
+    // what the receiver has and should have for every candidate key
+    let mut changes = Vec::new();
+    for key in keys.iter() {
+        let had = old.find(key).is_some() && !old_hidden.contains(key);
+        let has = new.find(key).is_some() && !hidden.contains(key);
+        if has || had {
+            changes.push((key.clone(), has));
+        }
+    }
+
+    try!(w.write_le_u32(changes.len() as u32));
+    for &(ref key, has) in changes.iter() {
+        if has {
+            try!(w.write_u8(1));
+            try!(key.save(w));
+            try!(new.find(key).unwrap().save(w));
+        } else {
+            try!(w.write_u8(0));
+            try!(key.save(w));
+        }
+    }
+    Ok(())
+}
+
+fn write_changes<K: Snapshot+Clone+Ord+Send+Share, V: Snapshot+Clone+Send+Share>
+    (new: &BTreeMap<K, V>, keys: &[K], w: &mut Writer) -> IoResult<()> {
+    try!(w.write_le_u32(keys.len() as u32));
+    for key in keys.iter() {
+        match new.find(key) {
+            Some(value) => {
+                try!(w.write_u8(1));
+                try!(key.save(w));
+                try!(value.save(w));
+            }
+            None => {
+                try!(w.write_u8(0));
+                try!(key.save(w));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// applies a delta written by `write_map_delta` to base
+pub fn read_map_delta<K: Snapshot+Clone+Ord+Send+Share, V: Snapshot+Clone+Send+Share>
+    (base: &BTreeMap<K, V>, r: &mut Reader) -> IoResult<BTreeMap<K, V>> {
+    read_map_delta_with(base, r, |_, _| ())
+}
+
+/// like `read_map_delta`, changed is called with every key the delta
+/// touches and whether the key is in the result
+pub fn read_map_delta_with<K: Snapshot+Clone+Ord+Send+Share, V: Snapshot+Clone+Send+Share>
+    (base: &BTreeMap<K, V>, r: &mut Reader, changed: |&K, bool|) -> IoResult<BTreeMap<K, V>> {
+    let mut out = base.clone();
+    let len = try!(r.read_le_u32());
+    for _ in range(0, len) {
+        match try!(r.read_u8()) {
+            0 => {
+                let key: K = try!(Snapshot::load(r));
+                out.remove(&key);
+                changed(&key, false);
+            }
+            1 => {
+                let key: K = try!(Snapshot::load(r));
+                let value: V = try!(Snapshot::load(r));
+                changed(&key, true);
+                out.insert(key, value);
+            }
+            _ => return Err(invalid("unknown table change"))
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,329 @@
+use std::cmp;
+use std::io;
+use std::io::{IoResult, IoError, File, BufferedReader, BufferedWriter};
+
+use cgmath::vector::{Vector2, Vector3, Vector4};
+use cgmath::point::Point3;
+use cgmath::quaternion::Quaternion;
+use cgmath::transform::Decomposed;
+
+use cow::btree::{BTreeMap, BTreeSet};
+
+// "SNOW" in little endian
+pub static MAGIC: u32 = 0x574f4e53;
+pub static VERSION: u32 = 5;
+
+/// Binary encoding used for snapshots of a game state generation.
+/// Everything is written little endian.
+pub trait Snapshot {
+    fn save(&self, w: &mut Writer) -> IoResult<()>;
+    fn load(r: &mut Reader) -> IoResult<Self>;
+
+    /// Loads what an older build wrote, version is the one returned
+    /// by `read_header`. Only types whose encoding changed override it.
+    fn load_version(r: &mut Reader, _version: u32) -> IoResult<Self> {
+        Snapshot::load(r)
+    }
+}
+
+pub fn invalid(desc: &'static str) -> IoError {
+    IoError {
+        kind: io::InvalidInput,
+        desc: desc,
+        detail: None
+    }
+}
+
+// lengths are read from files and sockets, no more then this is
+// allocated ahead of the data that actually arrived
+static MAX_RESERVE: uint = 4096;
+
+/// reads len bytes, the buffer grows as they arrive
+pub fn read_bytes(r: &mut Reader, len: uint) -> IoResult<Vec<u8>> {
+    let mut out = Vec::with_capacity(cmp::min(len, MAX_RESERVE));
+    while out.len() < len {
+        let n = cmp::min(len - out.len(), MAX_RESERVE);
+        try!(r.push_at_least(n, n, &mut out));
+    }
+    Ok(out)
+}
+
+pub fn write_header(w: &mut Writer) -> IoResult<()> {
+    try!(w.write_le_u32(MAGIC));
+    w.write_le_u32(VERSION)
+}
+
+pub fn read_header(r: &mut Reader) -> IoResult<u32> {
+    if try!(r.read_le_u32()) != MAGIC {
+        return Err(invalid("not a snowmew snapshot"));
+    }
+    let version = try!(r.read_le_u32());
+    if version > VERSION {
+        return Err(invalid("snapshot version is newer than this build"));
+    }
+    if version == 0 {
+        return Err(invalid("invalid snapshot version"));
+    }
+    Ok(version)
+}
+
+pub fn save_file<S: Snapshot>(path: &Path, s: &S) -> IoResult<()> {
+    let mut w = BufferedWriter::new(try!(File::create(path)));
+    try!(write_header(&mut w));
+    try!(s.save(&mut w));
+    w.flush()
+}
+
+pub fn load_file<S: Snapshot>(path: &Path) -> IoResult<S> {
+    let mut r = BufferedReader::new(try!(File::open(path)));
+    let version = try!(read_header(&mut r));
+    Snapshot::load_version(&mut r, version)
+}
+
+impl Snapshot for u8 {
+    fn save(&self, w: &mut Writer) -> IoResult<()> { w.write_u8(*self) }
+    fn load(r: &mut Reader) -> IoResult<u8> { r.read_u8() }
+}
+
+impl Snapshot for u32 {
+    fn save(&self, w: &mut Writer) -> IoResult<()> { w.write_le_u32(*self) }
+    fn load(r: &mut Reader) -> IoResult<u32> { r.read_le_u32() }
+}
+
+impl Snapshot for u64 {
+    fn save(&self, w: &mut Writer) -> IoResult<()> { w.write_le_u64(*self) }
+    fn load(r: &mut Reader) -> IoResult<u64> { r.read_le_u64() }
+}
+
+impl Snapshot for i32 {
+    fn save(&self, w: &mut Writer) -> IoResult<()> { w.write_le_i32(*self) }
+    fn load(r: &mut Reader) -> IoResult<i32> { r.read_le_i32() }
+}
+
+impl Snapshot for uint {
+    fn save(&self, w: &mut Writer) -> IoResult<()> { w.write_le_u64(*self as u64) }
+    fn load(r: &mut Reader) -> IoResult<uint> { Ok(try!(r.read_le_u64()) as uint) }
+}
+
+impl Snapshot for int {
+    fn save(&self, w: &mut Writer) -> IoResult<()> { w.write_le_i64(*self as i64) }
+    fn load(r: &mut Reader) -> IoResult<int> { Ok(try!(r.read_le_i64()) as int) }
+}
+
+impl Snapshot for f32 {
+    fn save(&self, w: &mut Writer) -> IoResult<()> { w.write_le_f32(*self) }
+    fn load(r: &mut Reader) -> IoResult<f32> { r.read_le_f32() }
+}
+
+impl Snapshot for f64 {
+    fn save(&self, w: &mut Writer) -> IoResult<()> { w.write_le_f64(*self) }
+    fn load(r: &mut Reader) -> IoResult<f64> { r.read_le_f64() }
+}
+
+impl Snapshot for bool {
+    fn save(&self, w: &mut Writer) -> IoResult<()> { w.write_u8(if *self {1} else {0}) }
+    fn load(r: &mut Reader) -> IoResult<bool> { Ok(try!(r.read_u8()) != 0) }
+}
+
+impl Snapshot for String {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(w.write_le_u32(self.len() as u32));
+        w.write_str(self.as_slice())
+    }
+
+    fn load(r: &mut Reader) -> IoResult<String> {
+        let len = try!(r.read_le_u32());
+        let bytes = try!(read_bytes(r, len as uint));
+        match String::from_utf8(bytes) {
+            Ok(s) => Ok(s),
+            Err(_) => Err(invalid("string is not valid utf8"))
+        }
+    }
+}
+
+impl<T: Snapshot> Snapshot for Option<T> {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        match *self {
+            Some(ref v) => {
+                try!(w.write_u8(1));
+                v.save(w)
+            }
+            None => w.write_u8(0)
+        }
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Option<T>> {
+        match try!(r.read_u8()) {
+            0 => Ok(None),
+            1 => Ok(Some(try!(Snapshot::load(r)))),
+            _ => Err(invalid("invalid option tag"))
+        }
+    }
+}
+
+impl<A: Snapshot, B: Snapshot> Snapshot for (A, B) {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        let (ref a, ref b) = *self;
+        try!(a.save(w));
+        b.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<(A, B)> {
+        let a = try!(Snapshot::load(r));
+        let b = try!(Snapshot::load(r));
+        Ok((a, b))
+    }
+}
+
+impl<T: Snapshot> Snapshot for Vec<T> {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(w.write_le_u32(self.len() as u32));
+        for v in self.iter() {
+            try!(v.save(w));
+        }
+        Ok(())
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Vec<T>> {
+        let len = try!(r.read_le_u32()) as uint;
+        let mut out = Vec::with_capacity(cmp::min(len, MAX_RESERVE));
+        for _ in range(0, len) {
+            out.push(try!(Snapshot::load(r)));
+        }
+        Ok(out)
+    }
+}
+
+impl<K: Snapshot+Clone+Ord+Send+Share, V: Snapshot+Clone+Send+Share> Snapshot for BTreeMap<K, V> {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(w.write_le_u32(self.len() as u32));
+        for (k, v) in self.iter() {
+            try!(k.save(w));
+            try!(v.save(w));
+        }
+        Ok(())
+    }
+
+    fn load(r: &mut Reader) -> IoResult<BTreeMap<K, V>> {
+        let len = try!(r.read_le_u32());
+        let mut out = BTreeMap::new();
+        for _ in range(0, len) {
+            let k: K = try!(Snapshot::load(r));
+            let v: V = try!(Snapshot::load(r));
+            out.insert(k, v);
+        }
+        Ok(out)
+    }
+}
+
+impl<K: Snapshot+Clone+Ord+Send+Share> Snapshot for BTreeSet<K> {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(w.write_le_u32(self.len() as u32));
+        for k in self.iter() {
+            try!(k.save(w));
+        }
+        Ok(())
+    }
+
+    fn load(r: &mut Reader) -> IoResult<BTreeSet<K>> {
+        let len = try!(r.read_le_u32());
+        let mut out = BTreeSet::new();
+        for _ in range(0, len) {
+            let k: K = try!(Snapshot::load(r));
+            out.insert(k);
+        }
+        Ok(out)
+    }
+}
+
+impl Snapshot for Vector2<f32> {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(w.write_le_f32(self.x));
+        w.write_le_f32(self.y)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Vector2<f32>> {
+        let x = try!(r.read_le_f32());
+        let y = try!(r.read_le_f32());
+        Ok(Vector2::new(x, y))
+    }
+}
+
+impl Snapshot for Vector3<f32> {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(w.write_le_f32(self.x));
+        try!(w.write_le_f32(self.y));
+        w.write_le_f32(self.z)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Vector3<f32>> {
+        let x = try!(r.read_le_f32());
+        let y = try!(r.read_le_f32());
+        let z = try!(r.read_le_f32());
+        Ok(Vector3::new(x, y, z))
+    }
+}
+
+impl Snapshot for Vector4<f32> {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(w.write_le_f32(self.x));
+        try!(w.write_le_f32(self.y));
+        try!(w.write_le_f32(self.z));
+        w.write_le_f32(self.w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Vector4<f32>> {
+        let x = try!(r.read_le_f32());
+        let y = try!(r.read_le_f32());
+        let z = try!(r.read_le_f32());
+        let w = try!(r.read_le_f32());
+        Ok(Vector4::new(x, y, z, w))
+    }
+}
+
+impl Snapshot for Point3<f32> {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(w.write_le_f32(self.x));
+        try!(w.write_le_f32(self.y));
+        w.write_le_f32(self.z)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Point3<f32>> {
+        let x = try!(r.read_le_f32());
+        let y = try!(r.read_le_f32());
+        let z = try!(r.read_le_f32());
+        Ok(Point3::new(x, y, z))
+    }
+}
+
+impl Snapshot for Quaternion<f32> {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(w.write_le_f32(self.s));
+        self.v.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Quaternion<f32>> {
+        let s = try!(r.read_le_f32());
+        let v: Vector3<f32> = try!(Snapshot::load(r));
+        Ok(Quaternion::from_sv(s, v))
+    }
+}
+
+impl Snapshot for Decomposed<f32, Vector3<f32>, Quaternion<f32>> {
+    fn save(&self, w: &mut Writer) -> IoResult<()> {
+        try!(w.write_le_f32(self.scale));
+        try!(self.rot.save(w));
+        self.disp.save(w)
+    }
+
+    fn load(r: &mut Reader) -> IoResult<Decomposed<f32, Vector3<f32>, Quaternion<f32>>> {
+        let scale = try!(r.read_le_f32());
+        let rot = try!(Snapshot::load(r));
+        let disp = try!(Snapshot::load(r));
+        Ok(Decomposed {
+            scale: scale,
+            rot: rot,
+            disp: disp
+        })
+    }
+}
@@ -0,0 +1,106 @@
+use std::mem;
+
+use cow::btree::BTreeMap;
+
+use diff::shared;
+
+/// Size of a table, and how much of it is shared with another
+/// generation of the same table.
+///
+/// Counting the BTree nodes two generations share is out of scope,
+/// `cow::btree` does not expose its nodes. Sharing is counted per
+/// entry instead, which is what `shared_entries` reports.
+#[deriving(Clone, PartialEq, Show)]
+pub struct TableStats {
+    pub name: String,
+    pub entries: uint,
+    /// approximate, the entries plus what they own on the heap
+    pub bytes: uint,
+    /// entries whose value is the same one in memory as in the other
+    /// generation
+    pub shared_entries: uint
+}
+
+impl TableStats {
+    pub fn new(name: &str) -> TableStats {
+        TableStats {
+            name: name.to_string(),
+            entries: 0,
+            bytes: 0,
+            shared_entries: 0
+        }
+    }
+
+    pub fn unique(&self) -> uint { self.entries - self.shared_entries }
+
+    /// the bytes the other generation does not share, assuming every
+    /// entry is about the same size
+    pub fn unique_bytes(&self) -> uint {
+        if self.entries == 0 { 0 } else { self.bytes * self.unique() / self.entries }
+    }
+
+    /// adds the counts of other to self
+    pub fn add(&mut self, other: &TableStats) {
+        self.entries += other.entries;
+        self.bytes += other.bytes;
+        self.shared_entries += other.shared_entries;
+    }
+}
+
+/// Memory use of a subsystem's data, per table
+pub trait Stats {
+    /// sharing is counted against other, nothing is shared without it
+    fn stats(&self, other: Option<&Self>) -> Vec<TableStats>;
+}
+
+/// stats of a table whose entries own nothing on the heap
+pub fn table_stats<K: Clone+Ord+Send+Share, V: Clone+Send+Share>
+    (name: &str, table: &BTreeMap<K, V>, other: Option<&BTreeMap<K, V>>) -> TableStats {
+    table_stats_by(name, table, other, |_, _| 0)
+}
+
+/// heap is the number of bytes an entry owns on the heap
+pub fn table_stats_by<K: Clone+Ord+Send+Share, V: Clone+Send+Share>
+    (name: &str, table: &BTreeMap<K, V>, other: Option<&BTreeMap<K, V>>,
+     heap: |&K, &V| -> uint) -> TableStats {
+    let mut stats = TableStats::new(name);
+    let size = mem::size_of::<K>() + mem::size_of::<V>();
+    for (k, v) in table.iter() {
+        stats.entries += 1;
+        stats.bytes += size + heap(k, v);
+        match other.and_then(|o| o.find(k)) {
+            Some(o) if shared(v, o) => stats.shared_entries += 1,
+            _ => ()
+        }
+    }
+    stats
+}
+
+/// stats of a Vec, which is copied whole with every generation
+pub fn vec_stats<T>(name: &str, v: &Vec<T>, heap: |&T| -> uint) -> TableStats {
+    let mut stats = TableStats::new(name);
+    stats.entries = v.len();
+    stats.bytes = v.len() * mem::size_of::<T>();
+    for t in v.iter() {
+        stats.bytes += heap(t);
+    }
+    stats
+}
+
+fn line(t: &TableStats) -> String {
+    format!("{:<24s} {:>10u} {:>12u} {:>14u} {:>12u}",
+            t.name, t.entries, t.bytes, t.shared_entries, t.unique_bytes())
+}
+
+/// a line per table followed by the totals
+pub fn report(tables: &[TableStats]) -> String {
+    let mut out = vec!(format!("{:<24s} {:>10s} {:>12s} {:>14s} {:>12s}",
+                               "table", "entries", "bytes", "shared entries", "unique bytes"));
+    let mut total = TableStats::new("total");
+    for t in tables.iter() {
+        out.push(line(t));
+        total.add(t);
+    }
+    out.push(line(&total));
+    out.connect("\n")
+}
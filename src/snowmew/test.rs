@@ -2,9 +2,10 @@
 
 extern crate snowmew;
 extern crate cow;
+extern crate glfw;
 
 mod core {
-    use snowmew::common::{CommonData, Common};
+    use snowmew::common::{CommonData, Common, ObjectKey, FrameInfo, Remap};
 
     #[test]
     fn db_new_object() {
@@ -14,4 +15,839 @@ mod core {
 
         assert!(db.find("main").unwrap() == id);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn db_delete_object() {
+        let mut db = CommonData::new();
+
+        let scene = db.new_scene("scene");
+        let a = db.new_object(Some(scene), "a");
+        let b = db.new_object(Some(a), "b");
+
+        assert!(!db.delete_object(a));
+        assert!(db.delete_object(b));
+        assert!(db.find("scene/a/b").is_none());
+        assert!(db.object(b).is_none());
+        assert!(db.scene_iter(scene).all(|&k| k != b));
+        assert!(db.find("scene/a").unwrap() == a);
+    }
+
+    #[test]
+    fn db_delete_subtree() {
+        let mut db = CommonData::new();
+
+        let scene = db.new_scene("scene");
+        let a = db.new_object(Some(scene), "a");
+        let b = db.new_object(Some(a), "b");
+        let c = db.new_object(Some(b), "c");
+
+        let removed = db.delete_subtree(a);
+        assert!(removed.len() == 3);
+        assert!(removed.contains(&a) && removed.contains(&b) && removed.contains(&c));
+        assert!(db.find("scene/a").is_none());
+        assert!(db.scene_iter(scene).count() == 0);
+
+        let d = db.new_object(Some(scene), "a");
+        assert!(db.find("scene/a").unwrap() == d);
+    }
+
+    #[test]
+    fn db_rename() {
+        let mut db = CommonData::new();
+
+        let a = db.new_object(None, "a");
+        let b = db.new_object(Some(a), "b");
+        let c = db.new_object(Some(a), "c");
+
+        assert!(!db.rename(b, "c"));
+        assert!(db.rename(b, "d"));
+        assert!(db.find("a/b").is_none());
+        assert!(db.find("a/d").unwrap() == b);
+        assert!(db.find("a/c").unwrap() == c);
+    }
+
+    #[test]
+    fn db_reparent() {
+        let mut db = CommonData::new();
+
+        let staging = db.new_object(None, "staging");
+        let scene = db.new_scene("scene");
+        let a = db.new_object(Some(staging), "a");
+        let b = db.new_object(Some(a), "b");
+
+        assert!(db.scene_iter(scene).count() == 0);
+        assert!(!db.reparent(staging, Some(b)));
+        assert!(db.reparent(a, Some(scene)));
+        assert!(db.find("staging/a").is_none());
+        assert!(db.find("scene/a/b").unwrap() == b);
+        assert!(db.scene_iter(scene).count() == 2);
+
+        assert!(db.reparent(a, None));
+        assert!(db.find("a/b").unwrap() == b);
+        assert!(db.scene_iter(scene).count() == 0);
+    }
+
+    #[test]
+    fn db_snapshot() {
+        use std::io::{MemWriter, MemReader};
+        use snowmew::snapshot::{Snapshot, write_header, read_header};
+
+        let mut db = CommonData::new();
+
+        let scene = db.new_scene("scene");
+        let a = db.new_object(Some(scene), "a");
+        let b = db.new_object(Some(a), "b");
+
+        let mut w = MemWriter::new();
+        write_header(&mut w).unwrap();
+        db.save(&mut w).unwrap();
+
+        let mut r = MemReader::new(w.unwrap());
+        read_header(&mut r).unwrap();
+        let mut loaded: CommonData = Snapshot::load(&mut r).unwrap();
+
+        assert!(loaded.find("scene/a/b").unwrap() == b);
+        assert!(loaded.scene_iter(scene).count() == 2);
+
+        let c = loaded.new_object(Some(a), "c");
+        assert!(c != a && c != b && c != scene);
+        assert!(loaded.find("scene/a/c").unwrap() == c);
+    }
+
+    #[test]
+    fn db_snapshot_components() {
+        use std::io::{MemWriter, MemReader};
+        use snowmew::snapshot::Snapshot;
+
+        let mut db = CommonData::new();
+        let a = db.new_object(None, "a");
+        let b = db.new_object(None, "b");
+        db.set_component(a, 10u);
+        db.set_component(b, true);
+
+        // bool has no name yet, its components can not be saved
+        db.set_component_snapshot::<uint>("health").unwrap();
+        assert!(db.save(&mut MemWriter::new()).is_err());
+        db.set_component_snapshot::<bool>("alive").unwrap();
+
+        let mut w = MemWriter::new();
+        db.save(&mut w).unwrap();
+        let mut loaded: CommonData = Snapshot::load(&mut MemReader::new(w.unwrap())).unwrap();
+
+        // until the type is registered the table is kept as it was saved
+        loaded.delete_object(b);
+        let mut w = MemWriter::new();
+        loaded.save(&mut w).unwrap();
+        let mut loaded: CommonData = Snapshot::load(&mut MemReader::new(w.unwrap())).unwrap();
+
+        loaded.set_component_snapshot::<uint>("health").unwrap();
+        loaded.set_component_snapshot::<bool>("alive").unwrap();
+        assert!(loaded.component::<uint>(a) == Some(&10));
+        assert!(loaded.component::<bool>(b).is_none());
+    }
+
+    #[test]
+    fn snapshot_bad_length() {
+        use std::io::MemReader;
+        use snowmew::snapshot::Snapshot;
+
+        // a length far past the end of the data fails to read instead
+        // of being allocated
+        let bytes = vec!(0xffu8, 0xff, 0xff, 0xff, 1, 0, 0, 0);
+        let v: ::std::io::IoResult<Vec<u32>> = Snapshot::load(&mut MemReader::new(bytes.clone()));
+        assert!(v.is_err());
+        let s: ::std::io::IoResult<String> = Snapshot::load(&mut MemReader::new(bytes));
+        assert!(s.is_err());
+    }
+
+    #[test]
+    fn db_snapshot_v1() {
+        use std::io::{MemWriter, MemReader};
+        use cow::btree::{BTreeMap, BTreeSet};
+        use snowmew::snapshot::Snapshot;
+
+        // a root object "a" as version 1 wrote it
+        let mut w = MemWriter::new();
+        2u32.save(&mut w).unwrap();
+        let mut strings = BTreeMap::new();
+        strings.insert(1u32, "a".to_string());
+        strings.save(&mut w).unwrap();
+        let mut refs = BTreeMap::new();
+        refs.insert(1u32, 1u);
+        refs.save(&mut w).unwrap();
+        2u32.save(&mut w).unwrap();
+        for v in [1u32, 1, 0, 1].iter() {
+            v.save(&mut w).unwrap();
+        }
+        let mut children = BTreeMap::new();
+        children.insert(1u32, 1u32);
+        let mut parent_child = BTreeMap::new();
+        parent_child.insert(0u32, children);
+        parent_child.save(&mut w).unwrap();
+        let scenes: BTreeMap<u32, BTreeSet<u32>> = BTreeMap::new();
+        scenes.save(&mut w).unwrap();
+
+        let mut r = MemReader::new(w.unwrap());
+        let mut loaded: CommonData = Snapshot::load_version(&mut r, 1).unwrap();
+        let a = loaded.find("a").unwrap();
+        assert!(loaded.tags(a) == 0);
+        let handle = loaded.handle(a).unwrap();
+        assert!(loaded.resolve(&handle) == Some(a));
+        let b = loaded.new_object(Some(a), "b");
+        assert!(b != a && loaded.find("a/b") == Some(b));
+    }
+
+    #[test]
+    fn db_diff() {
+        use snowmew::diff::{Added, Removed, Modified};
+
+        let mut db = CommonData::new();
+        let scene = db.new_scene("scene");
+        let a = db.new_object(Some(scene), "a");
+        let b = db.new_object(Some(scene), "b");
+
+        let old = db.clone();
+        assert!(db.diff(&old).count() == 0);
+
+        let c = db.new_object(Some(scene), "c");
+        db.delete_object(a);
+        db.rename(b, "d");
+
+        let changes: Vec<_> = db.diff(&old).collect();
+        assert!(changes == vec!(Removed(a), Modified(b), Added(c)));
+    }
+
+    #[test]
+    fn db_history() {
+        use snowmew::history::History;
+
+        let mut db = CommonData::new();
+        let scene = db.new_scene("scene");
+        let mut history = History::new(3, "new scene", &db);
+
+        let a = db.new_object(Some(scene), "a");
+        history.checkpoint("add a", &db);
+        db.new_object(Some(scene), "b");
+        history.checkpoint("add b", &db);
+
+        db = history.undo().unwrap().clone();
+        assert!(db.find("scene/b").is_none());
+        assert!(db.find("scene/a").unwrap() == a);
+        assert!(history.redo_label() == Some("add b"));
+
+        db = history.redo().unwrap().clone();
+        assert!(db.find("scene/b").is_some());
+        assert!(history.redo().is_none());
+
+        // the oldest generation is dropped
+        db.new_object(Some(scene), "c");
+        history.checkpoint("add c", &db);
+        assert!(history.len() == 3);
+        history.undo();
+        history.undo();
+        assert!(history.undo().is_none());
+        assert!(history.label() == "add a");
+    }
+
+    #[test]
+    fn db_components() {
+        #[deriving(Clone, PartialEq)]
+        struct Health(uint);
+
+        let mut db = CommonData::new();
+        let a = db.new_object(None, "a");
+        let b = db.new_object(None, "b");
+
+        db.set_component(a, Health(10));
+        db.set_component(b, 5u);
+        assert!(db.component::<Health>(a) == Some(&Health(10)));
+        assert!(db.component::<Health>(b).is_none());
+        assert!(db.component::<uint>(b) == Some(&5));
+
+        let old = db.clone();
+        *db.component_mut::<Health>(a).unwrap() = Health(3);
+        assert!(old.component::<Health>(a) == Some(&Health(10)));
+        assert!(db.component::<Health>(a) == Some(&Health(3)));
+
+        db.delete_object(a);
+        assert!(db.component::<Health>(a).is_none());
+        assert!(db.component_iter::<Health>().unwrap().count() == 0);
+        assert!(db.remove_component::<uint>(b));
+        assert!(db.component::<uint>(b).is_none());
+    }
+
+    #[test]
+    fn db_query() {
+        use snowmew::query::query;
+
+        let mut db = CommonData::new();
+        let scene = db.new_scene("scene");
+        let a = db.new_object(Some(scene), "a");
+        let b = db.new_object(Some(a), "b");
+        let c = db.new_object(Some(scene), "c");
+        let d = db.new_object(None, "d");
+
+        for &key in [a, b, c, d].iter() {
+            db.set_component(key, key as uint);
+            db.set_component(key, key as f32);
+        }
+        db.remove_component::<f32>(b);
+        db.set_component(c, true);
+
+        let found: Vec<ObjectKey> = query(db.component_iter::<uint>().unwrap())
+            .join(db.component_iter::<f32>().unwrap())
+            .in_scene(&db, scene)
+            .without(db.component_table::<bool>().unwrap())
+            .map(|(k, _)| *k)
+            .collect();
+        assert!(found == vec!(a));
+
+        let found: Vec<ObjectKey> = query(db.component_iter::<uint>().unwrap())
+            .under(&db, a)
+            .map(|(k, _)| *k)
+            .collect();
+        assert!(found == vec!(a, b));
+    }
+
+    #[test]
+    fn db_tags() {
+        use snowmew::common::ALL_TAGS;
+
+        let mut db = CommonData::new();
+        let scene = db.new_scene("scene");
+        let a = db.new_object(Some(scene), "a");
+        let b = db.new_object(Some(scene), "b");
+        let c = db.new_object(None, "c");
+
+        let enemy = db.tag("enemy");
+        let hidden = db.tag("hidden");
+        assert!(enemy != hidden);
+        assert!(db.tag("enemy") == enemy);
+        assert!(db.find_tag("hidden") == Some(hidden));
+
+        db.add_tags(a, enemy);
+        db.add_tags(b, enemy | hidden);
+        db.set_tags(c, enemy);
+        db.remove_tags(b, enemy);
+
+        let found: Vec<ObjectKey> = db.scene_iter_tagged(scene, enemy).map(|k| *k).collect();
+        assert!(found == vec!(a));
+
+        let found: Vec<ObjectKey> = db.tagged_iter(enemy).map(|k| *k).collect();
+        assert!(found == vec!(a, c));
+
+        assert!(db.scene_iter_tagged(scene, ALL_TAGS).count() == 2);
+        assert!(db.has_tags(b, hidden));
+        assert!(!db.has_tags(b, enemy));
+    }
+
+    #[test]
+    fn db_instantiate() {
+        let mut db = CommonData::new();
+        let template = db.new_object(None, "template");
+        let a = db.new_object(Some(template), "a");
+        let b = db.new_object(Some(a), "b");
+        db.set_tags(b, 4);
+        db.set_component(b, 7u);
+
+        let scene = db.new_scene("scene");
+        let remap = db.instantiate(template, Some(scene), "copy").unwrap();
+
+        let copy = db.find("scene/copy").unwrap();
+        let copy_b = db.find("scene/copy/a/b").unwrap();
+        assert!(remap.get(template) == copy);
+        assert!(remap.get(b) == copy_b);
+        assert!(remap.get(scene) == scene);
+        assert!(copy_b != b);
+        assert!(db.tags(copy_b) == 4);
+        assert!(db.component::<uint>(copy_b) == Some(&7));
+        assert!(db.scene_iter(scene).count() == 3);
+
+        assert!(db.instantiate(template, Some(scene), "copy").is_none());
+    }
+
+    // counts what the data hooks were called with
+    struct Hooked {
+        common: CommonData,
+        deleted: Vec<ObjectKey>,
+        copied: uint
+    }
+
+    impl Common for Hooked {
+        fn get_common<'a>(&'a self) -> &'a CommonData { &self.common }
+        fn get_common_mut<'a>(&'a mut self) -> &'a mut CommonData { &mut self.common }
+
+        fn delete_data(&mut self, oid: ObjectKey) {
+            assert!(self.object(oid).is_some());
+            self.deleted.push(oid);
+        }
+
+        fn copy_data(&mut self, remap: &Remap) {
+            self.copied += remap.iter().count();
+        }
+    }
+
+    #[test]
+    fn db_data_hooks() {
+        let mut db = Hooked {
+            common: CommonData::new(),
+            deleted: Vec::new(),
+            copied: 0
+        };
+        let template = db.new_object(None, "template");
+        let a = db.new_object(Some(template), "a");
+
+        db.instantiate(template, None, "copy").unwrap();
+        assert!(db.copied == 2);
+
+        assert!(!db.delete_object(template));
+        assert!(db.deleted.len() == 0);
+        db.delete_subtree(template);
+        assert!(db.deleted == vec!(a, template));
+    }
+
+    #[test]
+    fn db_find_all() {
+        let mut db = CommonData::new();
+        let scene = db.new_scene("scene");
+        let room0 = db.new_object(Some(scene), "room0");
+        let room1 = db.new_object(Some(scene), "room1");
+        let lights0 = db.new_object(Some(room0), "lights");
+        let lights1 = db.new_object(Some(room1), "lights");
+        let sun = db.new_object(Some(lights0), "sun");
+        let lamp = db.new_object(Some(lights1), "lamp");
+        let bulb = db.new_object(Some(lamp), "bulb");
+
+        let found: Vec<ObjectKey> = db.find_all("scene/*").collect();
+        assert!(found == vec!(room0, room1));
+
+        let found: Vec<ObjectKey> = db.find_all("scene/*/lights/**").collect();
+        assert!(found == vec!(lights0, lights1, sun, lamp, bulb));
+
+        let found: Vec<ObjectKey> = db.find_all("**/l*p").collect();
+        assert!(found == vec!(lamp));
+
+        let found: Vec<ObjectKey> = db.find_all("scene/room?").collect();
+        assert!(found == vec!(room0, room1));
+
+        let found: Vec<ObjectKey> = db.find_all_where("scene/**", |db, k| db.walk_dir(k).count() == 0)
+                                      .collect();
+        assert!(found == vec!(sun, bulb));
+
+        assert!(db.find_all("scene/nothing/**").count() == 0);
+    }
+
+    #[test]
+    fn db_handle() {
+        let mut db = CommonData::new();
+        let a = db.new_object(None, "a");
+        let handle = db.handle(a).unwrap();
+
+        assert!(handle.key() == a);
+        assert!(db.resolve(&handle) == Some(a));
+
+        db.rename(a, "b");
+        assert!(db.is_alive(&handle));
+
+        db.delete_object(a);
+        assert!(!db.is_alive(&handle));
+        assert!(db.resolve(&handle).is_none());
+        assert!(db.handle(a).is_none());
+
+        // the key is reused, the old handle still does not resolve
+        let c = db.new_object(None, "c");
+        assert!(c == a);
+        assert!(!db.is_alive(&handle));
+        assert!(db.object_by_handle(&handle).is_none());
+        assert!(db.delete_handle(&handle).len() == 0);
+        assert!(db.find("c") == Some(c));
+
+        let fresh = db.handle(c).unwrap();
+        assert!(db.delete_handle(&fresh) == vec!(c));
+        assert!(db.find("c").is_none());
+    }
+
+    #[test]
+    fn db_merge() {
+        use snowmew::merge::{Merge, Policy, Priority};
+
+        #[deriving(Clone, PartialEq)]
+        struct Health(uint);
+
+        // the healthier side wins
+        fn healthiest(h: &Health) -> int {
+            let Health(v) = *h;
+            v as int
+        }
+
+        let mut base = CommonData::new();
+        let a = base.new_object(None, "a");
+        let b = base.new_object(None, "b");
+        base.set_component(a, Health(10));
+        base.set_component(b, Health(10));
+        let policy: Policy<Health> = Priority(healthiest);
+        base.set_merge_policy(policy);
+
+        let mut physics = base.clone();
+        let c = physics.new_object(None, "c");
+        physics.set_component(a, Health(5));
+
+        let mut ai = base.clone();
+        ai.set_tags(b, 1);
+        ai.set_component(a, Health(8));
+        ai.set_component(b, Health(7));
+
+        let merged: CommonData = Merge::merge(&base, &physics, &ai).unwrap();
+        assert!(merged.find("c") == Some(c));
+        assert!(merged.tags(b) == 1);
+        assert!(merged.component::<Health>(a) == Some(&Health(8)));
+        assert!(merged.component::<Health>(b) == Some(&Health(7)));
+
+        ai.new_object(None, "d");
+        let merged: Result<CommonData, String> = Merge::merge(&base, &physics, &ai);
+        assert!(merged.is_err());
+    }
+
+    #[test]
+    fn db_events() {
+        use snowmew::event::{Broadcast, To};
+
+        #[deriving(Clone, PartialEq)]
+        struct Damage(uint);
+
+        let mut db = CommonData::new();
+        let a = db.new_object(None, "a");
+        let b = db.new_object(None, "b");
+
+        db.post_event(To(a), Damage(3));
+        db.post_event(Broadcast, Damage(1));
+        assert!(db.events::<Damage>().count() == 0);
+
+        db.swap_events();
+        assert!(db.events::<Damage>().count() == 2);
+        let to_b: Vec<&Damage> = db.events_for::<Damage>(b).map(|(_, e)| e).collect();
+        assert!(to_b == vec!(&Damage(1)));
+        assert!(db.events_for::<Damage>(a).count() == 2);
+
+        db.swap_events();
+        assert!(db.events::<Damage>().count() == 0);
+    }
+
+    #[test]
+    fn headless() {
+        use glfw::CloseEvent;
+        use snowmew::{SnowmewConfig, NullRender};
+        use snowmew::io::ScriptedInput;
+
+        let count_frames = |gd: CommonData, frame: &FrameInfo| {
+            let mut gd = gd;
+            gd.new_object(None, format!("{}", frame.count).as_slice());
+            gd
+        };
+
+        let sc: SnowmewConfig<CommonData, NullRender> = SnowmewConfig::new();
+        let db = sc.start_headless(CommonData::new(), ScriptedInput::new((640, 480)), Some(10),
+                                   |_, _| false, |gd, frame, _, _| (count_frames(gd, frame), 0, 0));
+        assert!(db.find("9").is_some());
+        assert!(db.find("10").is_none());
+
+        let mut input = ScriptedInput::new((640, 480));
+        input.push(3, CloseEvent);
+        let sc: SnowmewConfig<CommonData, NullRender> = SnowmewConfig::new();
+        let db = sc.start_headless(CommonData::new(), input, None,
+                                   |_, _| false, |gd, frame, _, _| (count_frames(gd, frame), 0, 0));
+        assert!(db.find("3").is_some());
+        assert!(db.find("4").is_none());
+    }
+
+    #[test]
+    fn input_replay() {
+        use std::default::Default;
+        use std::io::{MemWriter, MemReader};
+        use glfw::{KeyEvent, KeySpace, Press, CursorPosEvent, Modifiers};
+        use snowmew::io::{ScriptedInput, InputSource, Recording};
+
+        let mut input = ScriptedInput::new((640, 480));
+        input.push(2, KeyEvent(KeySpace, 0, Press, Modifiers::empty()));
+        input.push(4, CursorPosEvent(10., 20.));
+
+        let mut frame: FrameInfo = Default::default();
+        let mut last = input.get();
+        let mut recording = Recording::new(&last);
+        let mut pressed = Vec::new();
+        for i in range(0u, 6) {
+            frame.count = i;
+            frame.time = i as f64;
+            input.poll(&frame);
+            let state = input.get();
+            recording.record(&frame, &state, &last);
+            pressed.push(state.key_down(KeySpace));
+            last = state;
+        }
+
+        let mut w = MemWriter::new();
+        recording.write(&mut w).unwrap();
+        let mut r = MemReader::new(w.unwrap());
+        let mut replay = Recording::read(&mut r).unwrap().replay();
+
+        for i in range(0u, 6) {
+            frame.count = i;
+            frame.time = i as f64;
+            replay.poll(&frame);
+            assert!(replay.get().key_down(KeySpace) == *pressed.get(i));
+        }
+        assert!(replay.done());
+        assert!(replay.get().cursor_delta(3.) == Some((10., 20.)));
+    }
+
+    #[test]
+    fn input_recorder() {
+        use std::default::Default;
+        use std::io::{MemWriter, MemReader};
+        use glfw::{KeyEvent, KeySpace, Press, Modifiers};
+        use snowmew::io::{ScriptedInput, InputSource, Recorder, Recording};
+
+        let mut input = ScriptedInput::new((640, 480));
+        input.push(1, KeyEvent(KeySpace, 0, Press, Modifiers::empty()));
+
+        let mut frame: FrameInfo = Default::default();
+        let mut last = input.get();
+        let mut recorder = Recorder::new(MemWriter::new(), &last).unwrap();
+        for i in range(0u, 3) {
+            frame.count = i;
+            input.poll(&frame);
+            let state = input.get();
+            recorder.record(&frame, &state, &last).unwrap();
+            last = state;
+        }
+
+        // the steps are readable without the recorder being closed
+        let bytes = recorder.unwrap().unwrap();
+        let mut replay = Recording::read(&mut MemReader::new(bytes.clone())).unwrap().replay();
+        frame.count = 1;
+        replay.poll(&frame);
+        assert!(replay.get().key_down(KeySpace));
+
+        // a key this build does not know is rejected, the last step
+        // ends with the key, scancode, action and modifiers
+        let mut broken = bytes.clone();
+        let at = broken.len() - 4 * 4;
+        *broken.get_mut(at) = 0xff;
+        *broken.get_mut(at + 1) = 0xff;
+        assert!(Recording::read(&mut MemReader::new(broken)).is_err());
+    }
+
+    #[test]
+    fn replication() {
+        use snowmew::net::{Server, Client};
+
+        let mut server = Server::bind("127.0.0.1", 0, CommonData::new()).unwrap();
+        let mut client = Client::connect("127.0.0.1", server.port(), CommonData::new()).unwrap();
+
+        let mut db = CommonData::new();
+        let scene = db.new_scene("scene");
+        let a = db.new_object(Some(scene), "a");
+        server.send(&db);
+        assert!(server.clients() == 1);
+        let copy = client.recv().unwrap();
+        assert!(copy.find("scene/a") == Some(a));
+
+        db.rename(a, "b");
+        db.set_tags(a, 4);
+        server.send(&db);
+        let copy = client.recv().unwrap();
+        assert!(copy.find("scene/b") == Some(a));
+        assert!(copy.find("scene/a").is_none());
+        assert!(copy.tags(a) == 4);
+        assert!(copy.scene_iter(scene).count() == 1);
+
+        // freed keys follow the deltas
+        db.delete_object(a);
+        server.send(&db);
+        let mut copy = client.recv().unwrap();
+        assert!(copy.new_object(Some(scene), "c") == a);
+    }
+
+    #[test]
+    fn state_hash() {
+        use snowmew::lockstep::StateHash;
+
+        let mut db = CommonData::new();
+        db.set_component_snapshot::<uint>("health").unwrap();
+        let mut hash = StateHash::new(CommonData::new());
+        let mut last = hash.update(&db);
+
+        // updated from the changes it matches a hash of everything
+        let a = db.new_object(None, "a");
+        db.set_component(a, 10u);
+        let b = db.new_object(Some(a), "b");
+        assert!(hash.update(&db) != last);
+        db.delete_object(b);
+        last = hash.update(&db);
+        assert!(last == StateHash::new(CommonData::new()).update(&db));
+
+        // components are covered
+        db.set_component(a, 11u);
+        assert!(hash.update(&db) != last);
+        assert!(hash.update(&db) == StateHash::new(CommonData::new()).update(&db));
+    }
+
+    #[test]
+    fn lockstep() {
+        use std::io::net::tcp::TcpListener;
+        use std::io::Listener;
+        use glfw::{KeyEvent, KeySpace, Press, Modifiers};
+        use snowmew::{SnowmewConfig, NullRender};
+        use snowmew::io::ScriptedInput;
+        use snowmew::lockstep::{Lockstep, StateHash};
+
+        // every player adds an object for each frame it holds space,
+        // cheat adds one more only on that peer
+        fn play(mut lockstep: Lockstep, press: uint, cheat: Option<uint>) -> (u64, Option<(uint, uint)>) {
+            let mut input = ScriptedInput::new((640, 480));
+            input.push(press, KeyEvent(KeySpace, 0, Press, Modifiers::empty()));
+            let sc: SnowmewConfig<CommonData, NullRender> = SnowmewConfig::new();
+            let mut hash = StateHash::new(CommonData::new());
+            let db = sc.start_headless_lockstep(CommonData::new(), input, &mut lockstep, Some(8),
+                                                |gd| hash.update(gd), |gd, frame, inputs, _| {
+                let mut gd = gd;
+                for (i, input) in inputs.iter().enumerate() {
+                    if input.key_down(KeySpace) {
+                        gd.new_object(None, format!("{}-{}", i, frame.count).as_slice());
+                    }
+                }
+                if cheat == Some(frame.count) {
+                    gd.new_object(None, "cheat");
+                }
+                (gd, 0, 0)
+            }).unwrap();
+            (StateHash::new(CommonData::new()).update(&db), lockstep.desync())
+        }
+
+        for &cheat in [None, Some(5)].iter() {
+            let mut listener = TcpListener::bind("127.0.0.1", 0).unwrap();
+            let port = listener.socket_name().unwrap().port;
+            let (send, recv) = channel();
+            spawn(proc() {
+                send.send(play(Lockstep::host(listener, 2).unwrap(), 2, None));
+            });
+            let (hash, desync) = play(Lockstep::join("127.0.0.1", port).unwrap(), 4, cheat);
+            let (host_hash, host_desync) = recv.recv();
+
+            match cheat {
+                None => {
+                    assert!(hash == host_hash);
+                    assert!(desync.is_none() && host_desync.is_none());
+                }
+                // the generation after the cheat is compared in the next frame
+                Some(at) => {
+                    assert!(desync == Some((at+1, 0)));
+                    assert!(host_desync == Some((at+1, 1)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn console() {
+        use snowmew::console::{Console, parse, lookup};
+
+        fn set_tags(gd: &mut CommonData, args: &[&str]) -> Result<String, String> {
+            let key = try!(lookup(gd, args[0]));
+            gd.set_tags(key, from_str(args[1]).unwrap());
+            Ok(String::new())
+        }
+
+        fn set(_: &mut CommonData, _: &[&str]) -> Result<String, String> {
+            Err("nothing to set".to_string())
+        }
+
+        assert!(parse("ls \"a b\"  c") == Ok(vec!("ls".to_string(), "a b".to_string(), "c".to_string())));
+        assert!(parse("ls \"a").is_err());
+
+        let mut db = CommonData::new();
+        let scene = db.new_scene("scene");
+        let a = db.new_object(Some(scene), "a");
+        db.new_object(Some(a), "b");
+
+        let mut console = Console::new();
+        console.register("set", "set", set);
+        console.register("set tags", "set tags <path> tags", set_tags);
+
+        assert!(console.run(&mut db, "ls scene") == Ok(format!("a/\t{}", a)));
+        assert!(console.run(&mut db, "find scene/*/b").unwrap().as_slice().contains("scene/a/b"));
+        assert!(console.run(&mut db, "set tags scene/a 4").is_ok());
+        assert!(db.tags(a) == 4);
+        assert!(console.run(&mut db, "set other").is_err());
+        assert!(console.run(&mut db, "inspect scene/missing").is_err());
+        assert!(console.run(&mut db, "frobnicate").is_err());
+
+        assert!(console.run(&mut db, "spawn scene/a c scene").is_ok());
+        assert!(db.find("scene/c/b").is_some());
+        assert!(console.run(&mut db, "delete scene/c") == Ok("deleted 2 objects".to_string()));
+        assert!(db.find("scene/c").is_none());
+    }
+
+    #[test]
+    fn db_stats() {
+        use snowmew::stats::Stats;
+
+        let mut db = CommonData::new();
+        let scene = db.new_scene("scene");
+        for i in range(0u, 100) {
+            db.new_object(Some(scene), format!("{}", i).as_slice());
+        }
+
+        let old = db.clone();
+        let a = db.find("scene/50").unwrap();
+        db.set_tags(a, 1);
+
+        let objects = db.stats(Some(&old)).move_iter().find(|t| t.name.as_slice() == "objects").unwrap();
+        assert!(objects.entries == 101);
+        assert!(objects.shared_entries > 0 && objects.shared_entries < 101);
+        assert!(objects.unique_bytes() < objects.bytes);
+
+        let alone = db.stats(None).move_iter().find(|t| t.name.as_slice() == "objects").unwrap();
+        assert!(alone.shared_entries == 0);
+    }
+
+    fn key_sum(_: &CommonData, key: ObjectKey) -> uint { key as uint }
+    fn add(a: uint, b: uint) -> uint { a + b }
+
+    #[test]
+    fn parallel() {
+        use std::sync::TaskPool;
+        use cow::btree::BTreeMap;
+        use snowmew::parallel::{ParallelCommon, split, split_ranges};
+
+        assert!(split(10, 3) == vec!((0, 4), (4, 4), (8, 2)));
+        assert!(split(0, 3).len() == 0);
+
+        // the ranges cover the table in order without overlapping
+        let mut table = BTreeMap::new();
+        for key in range(1u, 11) {
+            table.insert(key * 2, key);
+        }
+        let ranges = split_ranges(table.iter().map(|(k, _)| k), table.len(), 3);
+        assert!(ranges.len() == 3);
+        for pair in ranges.as_slice().windows(2) {
+            assert!(pair[0].upper == Some(pair[1].lower));
+        }
+        assert!(ranges.last().unwrap().upper.is_none());
+        let lens: Vec<uint> = ranges.iter().map(|r| r.entries(table.iter()).count()).collect();
+        assert!(lens == vec!(4u, 4, 2));
+        let walked: Vec<uint> = ranges.iter().flat_map(|r| r.entries(table.iter()).map(|(k, _)| *k)).collect();
+        let keys: Vec<uint> = table.iter().map(|(k, _)| *k).collect();
+        assert!(walked == keys);
+
+        let mut db = CommonData::new();
+        let scene = db.new_scene("scene");
+        let mut expected = 0;
+        for i in range(0u, 100) {
+            expected += db.new_object(Some(scene), format!("{}", i).as_slice()) as uint;
+        }
+
+        let mut pool = TaskPool::new(4, || proc(_) ());
+        assert!(db.par_scene_map_reduce(&mut pool, scene, 7, key_sum, add) == Some(expected));
+    }
+}